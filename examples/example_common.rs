@@ -1,4 +1,6 @@
 #![allow(clippy::unwrap_used)]
+// Each example uses only a part of this shared module.
+#![allow(dead_code)]
 
 use std::collections::HashSet;
 
@@ -99,6 +101,7 @@ pub fn make_layout_config(max_width: Option<f32>, max_height: Option<f32>) -> Te
         wrap_hard_break: true,
         word_separators,
         linebreak_char,
+        ..Default::default()
     }
 }
 
@@ -147,175 +150,170 @@ pub fn build_text_data(
     let mut data = TextData::new();
 
     // --- Header ---
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 64.0,
-        content: "NEON CITY DAILY\n".into(),
-        user_data: TextColor::NEON_CYAN,
-    });
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 24.0,
-        content: "The Pulse of the Metropolis\t--\tWednesday, October 12, 2154\n".into(),
-        user_data: TextColor::MUTED_GRAY,
-    });
-    data.append(TextElement {
-        font_id: mono_font,
-        font_size: 18.0,
-        content: "Weather:\tAcid Rain (Heavy)\tVisibility:\t20%\tAir Quality:\tPoor\n\n".into(),
-        user_data: TextColor::NEON_GREEN,
-    });
+    data.append(TextElement::new(
+        heading_font,
+        64.0,
+        "NEON CITY DAILY\n",
+        TextColor::NEON_CYAN,
+    ));
+    data.append(TextElement::new(
+        heading_font,
+        24.0,
+        "The Pulse of the Metropolis\t--\tWednesday, October 12, 2154\n",
+        TextColor::MUTED_GRAY,
+    ));
+    data.append(TextElement::new(
+        mono_font,
+        18.0,
+        "Weather:\tAcid Rain (Heavy)\tVisibility:\t20%\tAir Quality:\tPoor\n\n",
+        TextColor::NEON_GREEN,
+    ));
 
     // --- Section 1: Breaking News ---
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 48.0,
-        content: "# TOP STORIES\n".into(),
-        user_data: TextColor::WHITE,
-    });
-    data.append(TextElement {
-        font_id: mono_font,
-        font_size: 20.0,
-        content: "---------------------------------------------------------------------\n".into(),
-        user_data: TextColor::NEON_PINK,
-    });
+    data.append(TextElement::new(
+        heading_font,
+        48.0,
+        "# TOP STORIES\n",
+        TextColor::WHITE,
+    ));
+    data.append(TextElement::new(
+        mono_font,
+        20.0,
+        "---------------------------------------------------------------------\n",
+        TextColor::NEON_PINK,
+    ));
 
     // Article 1
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 32.0,
-        content: "> Sky-High Real Estate?\n".into(),
-        user_data: TextColor::GOLD,
-    });
-    data.append(TextElement {
-        font_id: body_font,
-        font_size: 24.0,
-        content: "\tLevitating Condos in Sector 7 reach record prices. \"Gravity is a luxury,\" says lead architect \
+    data.append(TextElement::new(
+        heading_font,
+        32.0,
+        "> Sky-High Real Estate?\n",
+        TextColor::GOLD,
+    ));
+    data.append(TextElement::new(
+        body_font,
+        24.0,
+        "\tLevitating Condos in Sector 7 reach record prices. \"Gravity is a luxury,\" says lead architect \
                   Dr. Xalor. Constructed with aggregated carbon-nanotubes, these homes offer the best view \
-                  above the smog layer, but residents complain about altitude sickness.\n".into(),
-        user_data: TextColor::WHITE,
-    });
+                  above the smog layer, but residents complain about altitude sickness.\n",
+        TextColor::WHITE,
+    ));
 
     // Article 2
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 32.0,
-        content: "\n> Cyber-Fashion Week Begins\n".into(),
-        user_data: TextColor::GOLD,
-    });
-    data.append(TextElement {
-        font_id: body_font,
-        font_size: 24.0,
-        content: "\tDesigners embrace \"Retro-Analog\" aesthetics. Expect to see more mechanical watches \
+    data.append(TextElement::new(
+        heading_font,
+        32.0,
+        "\n> Cyber-Fashion Week Begins\n",
+        TextColor::GOLD,
+    ));
+    data.append(TextElement::new(
+        body_font,
+        24.0,
+        "\tDesigners embrace \"Retro-Analog\" aesthetics. Expect to see more mechanical watches \
                    and non-LED fabrics on the runway this season. Critics call it 'impractical', but the \
-                   youth are loving the tactile sensation of physical buttons.\n".into(),
-        user_data: TextColor::WHITE,
-    });
+                   youth are loving the tactile sensation of physical buttons.\n",
+        TextColor::WHITE,
+    ));
     // Tags
-    data.append(TextElement {
-        font_id: mono_font,
-        font_size: 18.0,
-        content: "#Fashion #Retro #AnalogIsTheNewDigital #NoLatency\n".into(),
-        user_data: TextColor::NEON_PINK,
-    });
+    data.append(TextElement::new(
+        mono_font,
+        18.0,
+        "#Fashion #Retro #AnalogIsTheNewDigital #NoLatency\n",
+        TextColor::NEON_PINK,
+    ));
 
     // Article 3 (Warning)
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 32.0,
-        content: "\n> Traffic Advisory: Maglev Line C\n".into(),
-        user_data: TextColor::WARNING_RED,
-    });
-    data.append(TextElement {
-        font_id: body_font,
-        font_size: 24.0,
-        content: "\tDelayed due to rogue AI playing chess with the signaling system. \
+    data.append(TextElement::new(
+        heading_font,
+        32.0,
+        "\n> Traffic Advisory: Maglev Line C\n",
+        TextColor::WARNING_RED,
+    ));
+    data.append(TextElement::new(
+        body_font,
+        24.0,
+        "\tDelayed due to rogue AI playing chess with the signaling system. \
                   Authorities are negotiating a draw. Expect delays of 20-30 minutes. \
-                  Commuters are advised to take the hyper-loop tunnels or rent a drone-cab.\n"
-            .into(),
-        user_data: TextColor::WHITE,
-    });
+                  Commuters are advised to take the hyper-loop tunnels or rent a drone-cab.\n",
+        TextColor::WHITE,
+    ));
 
     // --- Section 2: Classifieds ---
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 48.0,
-        content: "\n# CLASSIFIEDS\n".into(),
-        user_data: TextColor::WHITE,
-    });
-    data.append(TextElement {
-        font_id: mono_font,
-        font_size: 20.0,
-        content: "---------------------------------------------------------------------\n".into(),
-        user_data: TextColor::NEON_PINK,
-    });
+    data.append(TextElement::new(
+        heading_font,
+        48.0,
+        "\n# CLASSIFIEDS\n",
+        TextColor::WHITE,
+    ));
+    data.append(TextElement::new(
+        mono_font,
+        20.0,
+        "---------------------------------------------------------------------\n",
+        TextColor::NEON_PINK,
+    ));
 
     // Ad 1
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 28.0,
-        content: "[SELLING]\tVintage 2020 Keyboard\n".into(),
-        user_data: TextColor::NEON_GREEN,
-    });
-    data.append(TextElement {
-        font_id: body_font,
-        font_size: 22.0,
-        content: "\tType:\t\tMechanical switches (Blue)\n\tSound:\t\tDistinct clicky sound\n\tCondition:\tPerfect. A relic of the pre-neural-link era.\n\tPrice:\t\t5000 Credits (Firm)\n\tContact:\tUser_882\n"
-            .into(),
-        user_data: TextColor::WHITE,
-    });
+    data.append(TextElement::new(
+        heading_font,
+        28.0,
+        "[SELLING]\tVintage 2020 Keyboard\n",
+        TextColor::NEON_GREEN,
+    ));
+    data.append(TextElement::new(
+        body_font,
+        22.0,
+        "\tType:\t\tMechanical switches (Blue)\n\tSound:\t\tDistinct clicky sound\n\tCondition:\tPerfect. A relic of the pre-neural-link era.\n\tPrice:\t\t5000 Credits (Firm)\n\tContact:\tUser_882\n",
+        TextColor::WHITE,
+    ));
 
     // Ad 2
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 28.0,
-        content: "\n[WANTED]\tAndroid Mechanic\n".into(),
-        user_data: TextColor::NEON_GREEN,
-    });
-    data.append(TextElement {
-        font_id: body_font,
-        font_size: 22.0,
-        content: "\tSpec:\t\tEmotional sub-routine debugging\n\tIssue:\t\tHousekeeping bot existential crisis\n\tDetails:\tRefuses to vacuum until it understands the meaning of dust.\n"
-            .into(),
-        user_data: TextColor::WHITE,
-    });
+    data.append(TextElement::new(
+        heading_font,
+        28.0,
+        "\n[WANTED]\tAndroid Mechanic\n",
+        TextColor::NEON_GREEN,
+    ));
+    data.append(TextElement::new(
+        body_font,
+        22.0,
+        "\tSpec:\t\tEmotional sub-routine debugging\n\tIssue:\t\tHousekeeping bot existential crisis\n\tDetails:\tRefuses to vacuum until it understands the meaning of dust.\n",
+        TextColor::WHITE,
+    ));
 
     // Ad 3
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 28.0,
-        content: "\n[LOST] Cyber-Dog \"Sparky\"\n".into(),
-        user_data: TextColor::NEON_GREEN,
-    });
-    data.append(TextElement {
-        font_id: body_font,
-        font_size: 22.0,
-        content: "\tSmall beagle model, chrome finish. Last seen chasing a holographic cat \
-                  near the Data District. Answers to binary commands. Reward offered.\n"
-            .into(),
-        user_data: TextColor::WHITE,
-    });
+    data.append(TextElement::new(
+        heading_font,
+        28.0,
+        "\n[LOST] Cyber-Dog \"Sparky\"\n",
+        TextColor::NEON_GREEN,
+    ));
+    data.append(TextElement::new(
+        body_font,
+        22.0,
+        "\tSmall beagle model, chrome finish. Last seen chasing a holographic cat \
+                  near the Data District. Answers to binary commands. Reward offered.\n",
+        TextColor::WHITE,
+    ));
 
     // --- Footer ---
-    data.append(TextElement {
-        font_id: mono_font,
-        font_size: 20.0,
-        content: "\n=====================================================================\n".into(),
-        user_data: TextColor::MUTED_GRAY,
-    });
-    data.append(TextElement {
-        font_id: mono_font,
-        font_size: 18.0,
-        content: "Crypto-Yen:\t145.2 (+2.1%)\tNeural-Net Load:\tStable\tHappy Hacking\n".into(),
-        user_data: TextColor::NEON_CYAN,
-    });
-    data.append(TextElement {
-        font_id: mono_font,
-        font_size: 16.0,
-        content: "Thank you for reading via your optical implant.\tBlink twice to refresh.\n"
-            .into(),
-        user_data: TextColor::MUTED_GRAY,
-    });
+    data.append(TextElement::new(
+        mono_font,
+        20.0,
+        "\n=====================================================================\n",
+        TextColor::MUTED_GRAY,
+    ));
+    data.append(TextElement::new(
+        mono_font,
+        18.0,
+        "Crypto-Yen:\t145.2 (+2.1%)\tNeural-Net Load:\tStable\tHappy Hacking\n",
+        TextColor::NEON_CYAN,
+    ));
+    data.append(TextElement::new(
+        mono_font,
+        16.0,
+        "Thank you for reading via your optical implant.\tBlink twice to refresh.\n",
+        TextColor::MUTED_GRAY,
+    ));
 
     data
 }
@@ -24,27 +24,25 @@ fn main() {
 
     // 3. Create TextData with a very long word
     let mut data = TextData::new();
-    data.append(TextElement {
-        font_id: heading_font,
-        font_size: 24.0,
-        content: "HardWalk:\n".into(),
-        user_data: TextColor::NEON_PINK,
-    });
-    data.append(TextElement {
-        font_id: body_font,
-        font_size: 18.0,
+    data.append(TextElement::new(
+        heading_font,
+        24.0,
+        "HardWalk:\n",
+        TextColor::NEON_PINK,
+    ));
+    data.append(TextElement::new(
+        body_font,
+        18.0,
         // formatted as a single long word without spaces
-        content:
-            "SuperCalifoRagiListicExpoaliDociousEvenThoughTheSoundOfItIsSomethingQuiteAtrocious\n"
-                .into(),
-        user_data: TextColor::WHITE,
-    });
-    data.append(TextElement {
-        font_id: body_font,
-        font_size: 14.0,
-        content: "\n(The word above should be broken across multiple lines)".into(),
-        user_data: TextColor::MUTED_GRAY,
-    });
+        "SuperCalifoRagiListicExpoaliDociousEvenThoughTheSoundOfItIsSomethingQuiteAtrocious\n",
+        TextColor::WHITE,
+    ));
+    data.append(TextElement::new(
+        body_font,
+        14.0,
+        "\n(The word above should be broken across multiple lines)",
+        TextColor::MUTED_GRAY,
+    ));
 
     // 4. Perform Layout
     let layout = font_system.layout_text(&data, &config);
@@ -32,6 +32,7 @@ fn make_config(max_width: Option<f32>, max_height: Option<f32>) -> TextLayoutCon
         wrap_hard_break: true,
         word_separators,
         linebreak_char,
+        ..Default::default()
     }
 }
 
@@ -80,12 +81,7 @@ fn main() {
         Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur. \
         Excepteur sint occaecat cupidatat non proident, sunt in culpa qui officia deserunt mollit anim id est laborum.\n\n".repeat(5);
 
-    data.append(TextElement {
-        font_id,
-        font_size: 24.0,
-        content: text_content,
-        user_data: (),
-    });
+    data.append(TextElement::new(font_id, 24.0, text_content, ()));
 
     // Perform layout once
     println!("Performing layout...");
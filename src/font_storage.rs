@@ -1,5 +1,7 @@
 use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
+use crate::glyph_id::{ColorFace, GlyphId};
+
 /// Manages font loading and retrieval using `fontdb` and `fontdue`.
 ///
 /// This struct combines a database of available fonts (`fontdb`) with a cache of loaded
@@ -11,6 +13,32 @@ pub struct FontStorage {
     /// This is the font that has been loaded by fontdue.
     /// Not all fonts in fontdb are necessarily loaded here.
     loaded_font: HashMap<fontdb::ID, Arc<fontdue::Font>, fxhash::FxBuildHasher>,
+    /// Raw face data of the fonts in `loaded_font`.
+    /// fontdue does not expose the OpenType layout tables, so the shaper reads them from here.
+    loaded_face_data: HashMap<fontdb::ID, FaceData, fxhash::FxBuildHasher>,
+    /// Families tried first when a font has no glyph for a character.
+    fallback_families: Vec<String>,
+    /// Characters covered by faces that were checked for fallback but not loaded.
+    coverage: HashMap<fontdb::ID, Coverage, fxhash::FxBuildHasher>,
+    /// Faces of the database in the order they are tried as fallback for a font.
+    fallback_candidates: HashMap<fontdb::ID, Arc<[fontdb::ID]>, fxhash::FxBuildHasher>,
+    /// Resolved fallback faces, keyed by the requested font and character.
+    fallback_cache: HashMap<(fontdb::ID, char), Option<fontdb::ID>, fxhash::FxBuildHasher>,
+    /// Bumped whenever the result of a layout may change. See [`FontStorage::generation`].
+    generation: u64,
+}
+
+/// Raw font file data together with the index of the face inside it.
+///
+/// The data is shared, so cloning is cheap.
+#[derive(Clone)]
+pub struct FaceData {
+    /// The whole font file (or collection) the face was loaded from.
+    pub data: Arc<[u8]>,
+    /// Index of the face within `data` (non-zero only for font collections).
+    pub index: u32,
+    /// The face parsed for its color glyphs, if it has `COLR`, `CBDT` or `sbix` tables.
+    pub color: Option<Arc<ColorFace>>,
 }
 
 impl Default for FontStorage {
@@ -25,6 +53,12 @@ impl FontStorage {
         Self {
             font_db: fontdb::Database::new(),
             loaded_font: HashMap::with_hasher(fxhash::FxBuildHasher::default()),
+            loaded_face_data: HashMap::with_hasher(fxhash::FxBuildHasher::default()),
+            fallback_families: Vec::new(),
+            coverage: HashMap::with_hasher(fxhash::FxBuildHasher::default()),
+            fallback_candidates: HashMap::with_hasher(fxhash::FxBuildHasher::default()),
+            fallback_cache: HashMap::with_hasher(fxhash::FxBuildHasher::default()),
+            generation: 0,
         }
     }
 }
@@ -34,32 +68,41 @@ impl FontStorage {
     /// Loads a font from binary data.
     pub fn load_font_binary(&mut self, data: impl Into<Vec<u8>>) {
         self.font_db.load_font_data(data.into());
+        self.fonts_changed();
     }
 
     /// Loads a font from a file path.
     pub fn load_font_file(&mut self, path: PathBuf) -> Result<(), std::io::Error> {
-        self.font_db.load_font_file(path)
+        self.font_db.load_font_file(path)?;
+        self.fonts_changed();
+        Ok(())
     }
 
     /// Loads all fonts from a directory.
     pub fn load_fonts_dir(&mut self, dir: PathBuf) {
-        self.font_db.load_fonts_dir(dir)
+        self.font_db.load_fonts_dir(dir);
+        self.fonts_changed();
     }
 
     /// Loads the system fonts.
     pub fn load_system_fonts(&mut self) {
         self.font_db.load_system_fonts();
+        self.fonts_changed();
     }
 
     /// Manually adds a face info.
     pub fn push_face_info(&mut self, info: fontdb::FaceInfo) {
         self.font_db.push_face_info(info);
+        self.fonts_changed();
     }
 
     /// Removes a face by ID.
     pub fn remove_face(&mut self, id: fontdb::ID) {
         self.font_db.remove_face(id);
         self.loaded_font.remove(&id);
+        self.loaded_face_data.remove(&id);
+        self.coverage.remove(&id);
+        self.fonts_changed();
     }
 
     /// Checks if the storage is empty.
@@ -72,6 +115,15 @@ impl FontStorage {
         self.font_db.len()
     }
 
+    /// Returns a counter that changes whenever faces are added or removed or
+    /// the fallback families are set.
+    ///
+    /// Layouts computed in an older generation may use different fonts, so
+    /// caches of layout results must be dropped when it changes.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     /// Sets the family name for the "serif" generic family.
     pub fn set_serif_family(&mut self, family: impl Into<String>) {
         self.font_db.set_serif_family(family);
@@ -120,19 +172,24 @@ impl FontStorage {
         match self.loaded_font.entry(id) {
             Entry::Occupied(entry) => Some(Arc::clone(entry.get())),
             Entry::Vacant(entry) => {
-                let font_result = self.font_db.with_face_data(id, |data, index| {
-                    fontdue::Font::from_bytes(
-                        data,
-                        fontdue::FontSettings {
-                            collection_index: index,
-                            scale: 40.0,
-                            load_substitutions: true,
-                        },
-                    )
-                })?;
+                let (data, index) = self
+                    .font_db
+                    .with_face_data(id, |data, index| (Arc::<[u8]>::from(data), index))?;
+
+                let font_result = fontdue::Font::from_bytes(
+                    &*data,
+                    fontdue::FontSettings {
+                        collection_index: index,
+                        scale: 40.0,
+                        load_substitutions: true,
+                    },
+                );
 
                 match font_result {
                     Ok(font) => {
+                        let color = ColorFace::parse(Arc::clone(&data), index).map(Arc::new);
+                        self.loaded_face_data
+                            .insert(id, FaceData { data, index, color });
                         let r: &mut Arc<fontdue::Font> = entry.insert(Arc::new(font));
                         Some(Arc::clone(r))
                     }
@@ -145,6 +202,17 @@ impl FontStorage {
         }
     }
 
+    /// Retrieves the raw data of a face by ID, loading the font if necessary.
+    ///
+    /// This is used to read OpenType tables that `fontdue` does not expose, such as the
+    /// GSUB/GPOS tables needed for shaping.
+    pub fn face_data(&mut self, id: fontdb::ID) -> Option<FaceData> {
+        if !self.loaded_face_data.contains_key(&id) {
+            self.font(id)?;
+        }
+        self.loaded_face_data.get(&id).cloned()
+    }
+
     /// Returns an iterator over all available faces.
     pub fn faces(&self) -> impl Iterator<Item = &fontdb::FaceInfo> {
         self.font_db.faces()
@@ -160,3 +228,424 @@ impl FontStorage {
         self.font_db.face_source(id)
     }
 }
+
+/// Color glyphs
+impl FontStorage {
+    /// Returns the metrics of the bitmap drawn for a glyph and whether it is a
+    /// color image: [`GlyphId::color_metrics`] if the glyph is drawn from the
+    /// color tables of its face, [`GlyphId::metrics`] otherwise.
+    ///
+    /// How each glyph is drawn is kept with its face, so color tables are only
+    /// walked the first time a glyph is asked for, whatever its size.
+    pub fn glyph_metrics(&mut self, glyph_id: &GlyphId) -> Option<(fontdue::Metrics, bool)> {
+        let font = self.font(glyph_id.font_id())?;
+        let color = self
+            .color_face(glyph_id.font_id())
+            .and_then(|face| glyph_id.color_metrics(&font, &face));
+        Some(color.unwrap_or_else(|| (glyph_id.metrics(&font), false)))
+    }
+
+    /// Returns the metrics of the color image of a glyph, or `None` if the glyph
+    /// is drawn as coverage.
+    ///
+    /// See [`GlyphId::color_metrics`].
+    pub fn color_glyph_metrics(&mut self, glyph_id: &GlyphId) -> Option<fontdue::Metrics> {
+        self.glyph_metrics(glyph_id)
+            .and_then(|(metrics, color)| color.then_some(metrics))
+    }
+
+    /// Rasterizes the bitmap described by [`FontStorage::glyph_metrics`]:
+    /// premultiplied RGBA for a color image, coverage otherwise.
+    ///
+    /// See [`GlyphId::rasterize_color`] and [`GlyphId::rasterize`].
+    pub fn rasterize_glyph(&mut self, glyph_id: &GlyphId) -> Option<(fontdue::Metrics, Vec<u8>)> {
+        let font = self.font(glyph_id.font_id())?;
+        let color = self
+            .color_face(glyph_id.font_id())
+            .and_then(|face| glyph_id.rasterize_color(&font, &face));
+        Some(match color {
+            Some((metrics, bitmap, _)) => (metrics, bitmap),
+            None => glyph_id.rasterize(&font),
+        })
+    }
+
+    /// Returns the color tables of a face, or `None` if it has none.
+    pub fn color_face(&mut self, id: fontdb::ID) -> Option<Arc<ColorFace>> {
+        self.face_data(id)?.color
+    }
+}
+
+/// Font fallback
+impl FontStorage {
+    /// Sets the families tried first when a font has no glyph for a character.
+    ///
+    /// Families are tried in order, matching the weight, style and stretch of the
+    /// requested font. If none of them covers the character, every other face in
+    /// the database is considered.
+    pub fn set_fallback_families<S: Into<String>>(
+        &mut self,
+        families: impl IntoIterator<Item = S>,
+    ) {
+        self.fallback_families = families.into_iter().map(Into::into).collect();
+        self.fonts_changed();
+    }
+
+    /// Returns the families set by [`FontStorage::set_fallback_families`].
+    pub fn fallback_families(&self) -> &[String] {
+        &self.fallback_families
+    }
+
+    /// Checks whether a face has a glyph for `ch`.
+    ///
+    /// Faces that are not loaded yet are only parsed for their character map,
+    /// once, and the covered characters are kept.
+    pub fn has_glyph(&mut self, id: fontdb::ID, ch: char) -> bool {
+        if let Some(font) = self.loaded_font.get(&id) {
+            return font.lookup_glyph_index(ch) != 0;
+        }
+
+        let font_db = &self.font_db;
+        self.coverage
+            .entry(id)
+            .or_insert_with(|| {
+                font_db
+                    .with_face_data(id, |data, index| {
+                        rustybuzz::ttf_parser::Face::parse(data, index)
+                            .map(|face| Coverage::new(&face))
+                            .unwrap_or_default()
+                    })
+                    .unwrap_or_default()
+            })
+            .contains(ch)
+    }
+
+    /// Finds a face that can render `ch` in place of the font `id`.
+    ///
+    /// Candidates are, in order: the fallback families and then every face in the
+    /// database, closest style first. The result only depends on `id`, `ch` and the
+    /// faces in the storage, and is cached until the set of faces changes.
+    ///
+    /// Returns `None` if no face has a glyph for `ch`.
+    pub fn fallback_font(&mut self, id: fontdb::ID, ch: char) -> Option<fontdb::ID> {
+        if let Some(cached) = self.fallback_cache.get(&(id, ch)) {
+            return *cached;
+        }
+
+        let resolved = self.resolve_fallback(id, ch);
+        self.fallback_cache.insert((id, ch), resolved);
+        resolved
+    }
+
+    fn resolve_fallback(&mut self, id: fontdb::ID, ch: char) -> Option<fontdb::ID> {
+        let (weight, style, stretch) = self
+            .font_db
+            .face(id)
+            .map(|face| (face.weight, face.style, face.stretch))
+            .unwrap_or_default();
+
+        for index in 0..self.fallback_families.len() {
+            let query = fontdb::Query {
+                families: &[fontdb::Family::Name(&self.fallback_families[index])],
+                weight,
+                stretch,
+                style,
+            };
+            if let Some(fallback_id) = self.font_db.query(&query)
+                && fallback_id != id
+                && self.has_glyph(fallback_id, ch)
+            {
+                return Some(fallback_id);
+            }
+        }
+
+        let candidates = match self.fallback_candidates.get(&id) {
+            Some(candidates) => Arc::clone(candidates),
+            None => {
+                let mut candidates: Vec<(u32, fontdb::ID)> = self
+                    .font_db
+                    .faces()
+                    .filter(|face| face.id != id)
+                    .map(|face| {
+                        let style_distance = if face.style == style { 0 } else { 10_000 };
+                        let stretch_distance = (face.stretch.to_number() as i32
+                            - stretch.to_number() as i32)
+                            .unsigned_abs();
+                        let weight_distance =
+                            (face.weight.0 as i32 - weight.0 as i32).unsigned_abs();
+                        (
+                            style_distance + stretch_distance * 100 + weight_distance,
+                            face.id,
+                        )
+                    })
+                    .collect();
+                // Stable sort keeps the database order between equally close faces.
+                candidates.sort_by_key(|(distance, _)| *distance);
+
+                let candidates: Arc<[fontdb::ID]> =
+                    candidates.into_iter().map(|(_, face_id)| face_id).collect();
+                self.fallback_candidates.insert(id, Arc::clone(&candidates));
+                candidates
+            }
+        };
+
+        candidates
+            .iter()
+            .copied()
+            .find(|&fallback_id| self.has_glyph(fallback_id, ch))
+    }
+
+    /// Drops everything derived from the set of faces and starts a new generation.
+    fn fonts_changed(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        self.fallback_cache.clear();
+        self.fallback_candidates.clear();
+    }
+}
+
+/// Characters a face has glyphs for, as sorted ranges of code points.
+#[derive(Default)]
+struct Coverage(Vec<std::ops::RangeInclusive<u32>>);
+
+impl Coverage {
+    /// Reads the Unicode subtables of the character map of `face`.
+    fn new(face: &rustybuzz::ttf_parser::Face) -> Self {
+        let mut codepoints = Vec::new();
+        for subtable in face
+            .tables()
+            .cmap
+            .iter()
+            .flat_map(|cmap| cmap.subtables)
+            .filter(|subtable| subtable.is_unicode())
+        {
+            subtable.codepoints(|codepoint| {
+                if subtable
+                    .glyph_index(codepoint)
+                    .is_some_and(|glyph| glyph.0 != 0)
+                {
+                    codepoints.push(codepoint);
+                }
+            });
+        }
+        codepoints.sort_unstable();
+        codepoints.dedup();
+
+        let mut ranges: Vec<std::ops::RangeInclusive<u32>> = Vec::new();
+        for codepoint in codepoints {
+            match ranges.last_mut() {
+                Some(range) if *range.end() + 1 == codepoint => {
+                    *range = *range.start()..=codepoint;
+                }
+                _ => ranges.push(codepoint..=codepoint),
+            }
+        }
+        Self(ranges)
+    }
+
+    fn contains(&self, ch: char) -> bool {
+        let codepoint = ch as u32;
+        self.0
+            .binary_search_by(|range| {
+                if *range.end() < codepoint {
+                    std::cmp::Ordering::Less
+                } else if *range.start() > codepoint {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn u16s(out: &mut Vec<u8>, values: &[u16]) {
+        values.iter().for_each(|v| out.extend(v.to_be_bytes()));
+    }
+
+    pub(crate) fn u32s(out: &mut Vec<u8>, values: &[u32]) {
+        values.iter().for_each(|v| out.extend(v.to_be_bytes()));
+    }
+
+    /// Builds a font whose character map gives a glyph to each of `chars`.
+    ///
+    /// Glyphs have no outline and advance by half an em. The ascender is at
+    /// 0.8 em and the descender at -0.2 em.
+    pub(crate) fn font_data(chars: &[char]) -> Vec<u8> {
+        font_data_with_tables(chars, Vec::new())
+    }
+
+    /// Builds the font of [`font_data`] with additional `tables`.
+    pub(crate) fn font_data_with_tables(
+        chars: &[char],
+        tables: Vec<([u8; 4], Vec<u8>)>,
+    ) -> Vec<u8> {
+        let mut head = Vec::new();
+        u32s(&mut head, &[0x0001_0000, 0, 0, 0x5F0F_3CF5]);
+        u16s(&mut head, &[0, 1000]);
+        head.extend([0; 16]);
+        u16s(&mut head, &[0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let mut hhea = Vec::new();
+        u32s(&mut hhea, &[0x0001_0000]);
+        u16s(&mut hhea, &[800, (-200i16) as u16, 0, 500]);
+        u16s(&mut hhea, &[0; 11]);
+        u16s(&mut hhea, &[chars.len() as u16 + 1]);
+        let mut hmtx = Vec::new();
+        for _ in 0..=chars.len() {
+            u16s(&mut hmtx, &[500, 0]);
+        }
+        let mut maxp = Vec::new();
+        u32s(&mut maxp, &[0x0000_5000]);
+        u16s(&mut maxp, &[chars.len() as u16 + 1]);
+        let mut cmap = Vec::new();
+        u16s(&mut cmap, &[0, 1, 3, 10]);
+        u32s(&mut cmap, &[12]);
+        u16s(&mut cmap, &[12, 0]);
+        u32s(
+            &mut cmap,
+            &[16 + 12 * chars.len() as u32, 0, chars.len() as u32],
+        );
+        for (glyph, &ch) in chars.iter().enumerate() {
+            u32s(&mut cmap, &[ch as u32, ch as u32, glyph as u32 + 1]);
+        }
+
+        let mut tables = [
+            (*b"cmap", cmap),
+            (*b"head", head),
+            (*b"hhea", hhea),
+            (*b"hmtx", hmtx),
+            (*b"maxp", maxp),
+        ]
+        .into_iter()
+        .chain(tables)
+        .collect::<Vec<_>>();
+        tables.sort_by_key(|(tag, _)| *tag);
+        let mut data = Vec::new();
+        u32s(&mut data, &[0x0001_0000]);
+        u16s(&mut data, &[tables.len() as u16, 0, 0, 0]);
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, table) in &tables {
+            data.extend(tag);
+            u32s(&mut data, &[0, offset as u32, table.len() as u32]);
+            offset += table.len().next_multiple_of(4);
+        }
+        for (_, table) in &tables {
+            data.extend(table);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+        data
+    }
+
+    /// Builds `glyf` and `loca` tables giving each of `glyph_count` glyphs an
+    /// outline filling its advance, 500 units wide and 700 units high.
+    pub(crate) fn outline_tables(glyph_count: u16) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut glyf = Vec::new();
+        let mut loca = Vec::new();
+        for _ in 0..glyph_count {
+            u16s(&mut loca, &[(glyf.len() / 2) as u16]);
+            // One contour of four on-curve points, given as 16-bit deltas.
+            u16s(&mut glyf, &[1, 0, 0, 500, 700, 3, 0]);
+            glyf.extend([0x01; 4]);
+            u16s(&mut glyf, &[0, 500, 0, (-500i16) as u16]);
+            u16s(&mut glyf, &[0, 0, 700, 0]);
+        }
+        u16s(&mut loca, &[(glyf.len() / 2) as u16]);
+        vec![(*b"glyf", glyf), (*b"loca", loca)]
+    }
+
+    /// Builds an `OS/2` table (version 0) with the given superscript and
+    /// subscript offsets, in font units.
+    pub(crate) fn os2_table(superscript: i16, subscript: i16) -> Vec<u8> {
+        let mut os2 = Vec::new();
+        u16s(&mut os2, &[0, 500, 400, 5, 0]);
+        u16s(&mut os2, &[650, 600, 0, subscript as u16]);
+        u16s(&mut os2, &[650, 600, 0, superscript as u16]);
+        u16s(&mut os2, &[50, 300, 0]);
+        os2.extend([0; 30]);
+        u16s(
+            &mut os2,
+            &[0, 0x20, 0xFFFF, 800, (-200i16) as u16, 0, 800, 200],
+        );
+        os2
+    }
+
+    fn font_id(id: u64) -> fontdb::ID {
+        unsafe { std::mem::transmute(id) }
+    }
+
+    pub(crate) fn push_face(storage: &mut FontStorage, family: &str, chars: &[char]) -> fontdb::ID {
+        push_face_data(storage, family, font_data(chars))
+    }
+
+    /// Adds a face made of `data` to `storage`, as the only face of `family`.
+    pub(crate) fn push_face_data(
+        storage: &mut FontStorage,
+        family: &str,
+        data: Vec<u8>,
+    ) -> fontdb::ID {
+        // The database assigns the real ID.
+        storage.push_face_info(fontdb::FaceInfo {
+            id: font_id(0),
+            source: fontdb::Source::Binary(Arc::new(data)),
+            index: 0,
+            families: vec![(family.to_string(), fontdb::Language::English_UnitedStates)],
+            post_script_name: family.to_string(),
+            style: fontdb::Style::Normal,
+            weight: fontdb::Weight::NORMAL,
+            stretch: fontdb::Stretch::Normal,
+            monospaced: false,
+        });
+        storage.faces().last().unwrap().id
+    }
+
+    #[test]
+    fn test_fallback_order() {
+        let mut storage = FontStorage::new();
+        let primary = push_face(&mut storage, "Primary", &['a']);
+        let other = push_face(&mut storage, "Other", &['b', 'c', 'd']);
+        let preferred = push_face(&mut storage, "Preferred", &['b', 'd']);
+        storage.set_fallback_families(["Preferred"]);
+
+        assert!(storage.has_glyph(primary, 'a'));
+        assert!(!storage.has_glyph(primary, 'b'));
+
+        // Fallback families come before the other faces of the database.
+        assert_eq!(storage.fallback_font(primary, 'b'), Some(preferred));
+        // Faces outside the fallback families are tried last.
+        assert_eq!(storage.fallback_font(primary, 'c'), Some(other));
+        assert_eq!(storage.fallback_font(primary, 'z'), None);
+
+        // Earlier lookups do not change the result: a fresh storage agrees.
+        assert_eq!(storage.fallback_font(primary, 'd'), Some(preferred));
+        let mut fresh = FontStorage::new();
+        let primary = push_face(&mut fresh, "Primary", &['a']);
+        push_face(&mut fresh, "Other", &['b', 'c', 'd']);
+        let preferred = push_face(&mut fresh, "Preferred", &['b', 'd']);
+        fresh.set_fallback_families(["Preferred"]);
+        assert_eq!(fresh.fallback_font(primary, 'd'), Some(preferred));
+    }
+
+    #[test]
+    fn test_glyph_metrics() {
+        let mut storage = FontStorage::new();
+        let id = push_face(&mut storage, "Plain", &['a']);
+        let font = storage.font(id).unwrap();
+        let glyph_id = GlyphId::new(id, 1, 10.0).with_synthetic_bold(true);
+
+        // Faces without color tables draw every glyph from its outline.
+        assert!(storage.color_face(id).is_none());
+        let metrics = glyph_id.metrics(&font);
+        assert_eq!(storage.glyph_metrics(&glyph_id), Some((metrics, false)));
+        assert_eq!(storage.color_glyph_metrics(&glyph_id), None);
+        assert_eq!(
+            storage.rasterize_glyph(&glyph_id),
+            Some(glyph_id.rasterize(&font))
+        );
+
+        storage.remove_face(id);
+        assert_eq!(storage.glyph_metrics(&glyph_id), None);
+    }
+}
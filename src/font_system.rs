@@ -1,4 +1,4 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{num::NonZeroUsize, path::PathBuf, sync::Arc};
 
 use parking_lot::Mutex;
 
@@ -6,10 +6,13 @@ use crate::{
     font_storage::FontStorage,
     renderer::{
         CpuRenderer, GpuRenderer,
-        cpu_renderer::CpuCacheConfig,
+        cpu_renderer::{CpuCacheConfig, CpuPixel},
         gpu_renderer::{AtlasUpdate, GlyphInstance, GpuCacheConfig, StandaloneGlyph},
     },
-    text::{TextData, TextLayout, TextLayoutConfig},
+    text::{
+        LayoutCache, MarkupDefaults, MarkupError, MarkupUserData, TextData, TextFlow, TextLayout,
+        TextLayoutConfig, TextPosition,
+    },
 };
 
 #[cfg(feature = "wgpu")]
@@ -27,6 +30,8 @@ use crate::renderer::{WgpuRenderPassController, WgpuRenderer};
 pub struct FontSystem {
     /// The underlying font storage.
     pub font_storage: Mutex<FontStorage>,
+    /// The layout result cache (optional).
+    pub layout_cache: Mutex<Option<LayoutCache>>,
 
     /// The CPU renderer instance (optional).
     pub cpu_renderer: Mutex<Option<Box<CpuRenderer>>>,
@@ -48,6 +53,7 @@ impl FontSystem {
     pub fn new() -> Self {
         Self {
             font_storage: Mutex::new(FontStorage::new()),
+            layout_cache: Mutex::new(None),
             cpu_renderer: Mutex::new(None),
             gpu_renderer: Mutex::new(None),
             #[cfg(feature = "wgpu")]
@@ -123,6 +129,11 @@ impl FontSystem {
         self.font_storage.lock().set_monospace_family(family);
     }
 
+    /// Sets the families tried first when a font has no glyph for a character.
+    pub fn set_fallback_families<S: Into<String>>(&self, families: impl IntoIterator<Item = S>) {
+        self.font_storage.lock().set_fallback_families(families);
+    }
+
     /// Returns the name of a family.
     ///
     /// # Performance
@@ -171,6 +182,18 @@ impl FontSystem {
 
 /// text layout
 impl FontSystem {
+    /// Parses markup into text runs using the fonts in this system.
+    ///
+    /// See [`TextData::from_markup`] for the supported markup.
+    pub fn parse_markup<T: MarkupUserData>(
+        &self,
+        markup: &str,
+        defaults: &MarkupDefaults<T>,
+    ) -> Result<TextData<T>, MarkupError> {
+        let mut font_storage = self.font_storage.lock();
+        TextData::from_markup(markup, defaults, &mut font_storage)
+    }
+
     /// Performs text layout using the fonts in this system.
     pub fn layout_text<T: Clone>(
         &self,
@@ -180,6 +203,69 @@ impl FontSystem {
         let mut font_storage = self.font_storage.lock();
         text.layout(config, &mut font_storage)
     }
+
+    /// Lays out the text from `start` into each of `boxes` in turn.
+    ///
+    /// See [`TextData::layout_boxes`].
+    pub fn layout_text_boxes<T: Clone>(
+        &self,
+        text: &TextData<T>,
+        start: TextPosition,
+        boxes: &[[f32; 2]],
+        config: &TextLayoutConfig,
+    ) -> TextFlow<T> {
+        let mut font_storage = self.font_storage.lock();
+        text.layout_boxes(start, boxes, config, &mut font_storage)
+    }
+
+    /// Performs text layout, reusing the result of an identical earlier call
+    /// if the layout cache is initialized.
+    ///
+    /// Without a cache this is [`FontSystem::layout_text`] wrapped in an `Arc`.
+    pub fn layout_text_cached<T>(
+        &self,
+        text: &TextData<T>,
+        config: &TextLayoutConfig,
+    ) -> Arc<TextLayout<T>>
+    where
+        T: Clone + PartialEq + Send + Sync + 'static,
+    {
+        let mut font_storage = self.font_storage.lock();
+        match &mut *self.layout_cache.lock() {
+            Some(cache) => cache.layout(text, config, &mut font_storage),
+            None => Arc::new(text.layout(config, &mut font_storage)),
+        }
+    }
+
+    /// Returns the size of the laid out text as `[width, height]`.
+    ///
+    /// Uses the layout cache if it is initialized, see [`FontSystem::layout_text_cached`].
+    pub fn measure_text<T>(&self, text: &TextData<T>, config: &TextLayoutConfig) -> [f32; 2]
+    where
+        T: Clone + PartialEq + Send + Sync + 'static,
+    {
+        let layout = self.layout_text_cached(text, config);
+        [layout.total_width, layout.total_height]
+    }
+}
+
+/// layout cache
+impl FontSystem {
+    /// Initializes the layout cache, keeping at most `capacity` layouts.
+    ///
+    /// This will replace any existing layout cache.
+    pub fn layout_cache_init(&self, capacity: NonZeroUsize) {
+        *self.layout_cache.lock() = Some(LayoutCache::new(capacity));
+    }
+
+    /// Removes all layouts from the layout cache.
+    ///
+    /// The cache is also emptied automatically when fonts are added or removed.
+    pub fn layout_cache_clear(&self) {
+        if let Some(cache) = &mut *self.layout_cache.lock() {
+            cache.clear();
+        }
+    }
 }
 
 /// cpu renderer
@@ -225,6 +311,22 @@ impl FontSystem {
             log::warn!("Render called before cpu renderer initialized.");
         }
     }
+
+    /// Renders text using the CPU renderer, passing the colors of color glyphs.
+    ///
+    /// See [`CpuRenderer::render_color`].
+    pub fn cpu_render_color<T>(
+        &self,
+        layout: &TextLayout<T>,
+        image_size: [usize; 2],
+        f: &mut dyn FnMut([usize; 2], CpuPixel, &T),
+    ) {
+        if let Some(renderer) = &mut *self.cpu_renderer.lock() {
+            renderer.render_color(layout, image_size, &mut self.font_storage.lock(), f);
+        } else {
+            log::warn!("Render called before cpu renderer initialized.");
+        }
+    }
 }
 
 /// gpu renderer
@@ -1,15 +1,30 @@
+pub(crate) mod color;
+
+pub use color::ColorFace;
+
 /// Quantization factor for font sizes to improve cache hit rates.
 ///
 /// Font sizes are multiplied by this value and rounded to integers for cache lookups.
 /// This allows small floating-point differences in font sizes to share cached glyphs.
 pub const SUB_PIXEL_QUANTIZE: f32 = 256f32;
 
+/// Thickness added to synthetic bold glyphs, relative to the font size.
+///
+/// Glyphs grow by this amount to the right and upwards, and so does their advance.
+pub const SYNTHETIC_BOLD_STRENGTH: f32 = 1.0 / 24.0;
+
+/// Horizontal shift per pixel of height of synthetic oblique glyphs (tan 12°).
+pub const SYNTHETIC_OBLIQUE_SHEAR: f32 = 0.2126;
+
 /// The same glyph is not guaranteed to receive the same `GlyphId` across program runs.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct GlyphId {
     font_id: fontdb::ID,
     glyph_index: u16,
     font_size: u32, // font size * SUB_PIXEL_QUANTIZE as u32
+    rotated: bool,
+    bold: bool,
+    oblique: bool,
 }
 
 impl GlyphId {
@@ -21,9 +36,28 @@ impl GlyphId {
             font_id,
             glyph_index,
             font_size: (font_size * SUB_PIXEL_QUANTIZE).round() as u32,
+            rotated: false,
+            bold: false,
+            oblique: false,
         }
     }
 
+    /// Returns the same glyph rotated 90° clockwise (or not), as used for
+    /// sideways text in vertical layouts.
+    pub fn with_rotation(self, rotated: bool) -> Self {
+        Self { rotated, ..self }
+    }
+
+    /// Returns the same glyph emboldened (or not), for fonts without a bold face.
+    pub fn with_synthetic_bold(self, bold: bool) -> Self {
+        Self { bold, ..self }
+    }
+
+    /// Returns the same glyph slanted (or not), for fonts without an italic face.
+    pub fn with_synthetic_oblique(self, oblique: bool) -> Self {
+        Self { oblique, ..self }
+    }
+
     /// Returns the font ID.
     pub fn font_id(&self) -> fontdb::ID {
         self.font_id
@@ -38,4 +72,367 @@ impl GlyphId {
     pub fn font_size(&self) -> f32 {
         self.font_size as f32 / SUB_PIXEL_QUANTIZE
     }
+
+    /// Returns whether the glyph is drawn rotated 90° clockwise.
+    pub fn is_rotated(&self) -> bool {
+        self.rotated
+    }
+
+    /// Returns whether the glyph is emboldened synthetically.
+    pub fn is_synthetic_bold(&self) -> bool {
+        self.bold
+    }
+
+    /// Returns whether the glyph is slanted synthetically.
+    pub fn is_synthetic_oblique(&self) -> bool {
+        self.oblique
+    }
+
+    /// Returns the length synthetic bold adds to the advance of the glyph.
+    pub fn synthetic_advance(&self) -> f32 {
+        if self.bold {
+            self.font_size() * SYNTHETIC_BOLD_STRENGTH
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns how far the top-left corner of the bitmap produced by
+    /// [`Self::rasterize`] moves because of synthetic bold and oblique, as
+    /// `[x, y]` in bitmap space (y pointing down).
+    pub fn synthetic_offset(&self, font: &fontdue::Font) -> [f32; 2] {
+        self.synthetic_offset_of(font.metrics_indexed(self.glyph_index, self.font_size()))
+    }
+
+    /// Returns [`Self::synthetic_offset`] for a glyph whose plain bitmap has `metrics`.
+    fn synthetic_offset_of(&self, metrics: fontdue::Metrics) -> [f32; 2] {
+        if metrics.width == 0 || metrics.height == 0 {
+            return [0.0, 0.0];
+        }
+
+        let grow = self.synthetic_advance().ceil();
+        let (shift, _) = self.shear_extent(metrics.ymin, metrics.height + grow as usize);
+        if self.rotated {
+            // The glyph grows away from the top-left corner once rotated.
+            [0.0, shift as f32]
+        } else {
+            [shift as f32, -grow]
+        }
+    }
+
+    /// Returns the metrics of the glyph bitmap produced by [`Self::rasterize`].
+    ///
+    /// Width and height are swapped for rotated glyphs. The other fields keep
+    /// the values of the upright glyph.
+    pub fn metrics(&self, font: &fontdue::Font) -> fontdue::Metrics {
+        self.rotate_metrics(
+            self.synthetic_metrics(font.metrics_indexed(self.glyph_index, self.font_size())),
+        )
+    }
+
+    /// Rasterizes the glyph into a coverage bitmap (one byte per pixel, row-major).
+    ///
+    /// Renderers should use this instead of rasterizing the glyph index directly,
+    /// so transformations encoded in the id are applied.
+    pub fn rasterize(&self, font: &fontdue::Font) -> (fontdue::Metrics, Vec<u8>) {
+        let (metrics, bitmap) = font.rasterize_indexed(self.glyph_index, self.font_size());
+        let (metrics, bitmap) = self.synthesize(metrics, bitmap);
+        self.rotate(metrics, bitmap, 1)
+    }
+
+    /// Returns the metrics of the image of the glyph in the color tables of
+    /// `face` and whether it is a color image, or `None` if the glyph is drawn
+    /// with [`Self::rasterize`].
+    ///
+    /// Images come from the `COLR`, `CBDT` and `sbix` tables of `face`, which
+    /// must be the face of `font`. `COLR` glyphs painted only in the text color
+    /// are coverage images, to be tinted like outline glyphs. Synthetic bold and
+    /// oblique are not applied to these images.
+    pub fn color_metrics(
+        &self,
+        font: &fontdue::Font,
+        face: &ColorFace,
+    ) -> Option<(fontdue::Metrics, bool)> {
+        let (metrics, color) = face.metrics(font, self.glyph_index, self.font_size())?;
+        Some((self.rotate_metrics(metrics), color))
+    }
+
+    /// Rasterizes the image of the glyph in the color tables of `face`, or
+    /// returns `None` if the glyph is drawn with [`Self::rasterize`].
+    ///
+    /// Color images are premultiplied RGBA (four bytes per pixel, row-major) and
+    /// coverage images are laid out like [`Self::rasterize`], as told by the
+    /// returned flag. See [`Self::color_metrics`].
+    pub fn rasterize_color(
+        &self,
+        font: &fontdue::Font,
+        face: &ColorFace,
+    ) -> Option<(fontdue::Metrics, Vec<u8>, bool)> {
+        let (metrics, bitmap, color) = face.rasterize(font, self.glyph_index, self.font_size())?;
+        let (metrics, bitmap) = self.rotate(metrics, bitmap, if color { 4 } else { 1 });
+        Some((metrics, bitmap, color))
+    }
+
+    /// Swaps width and height for rotated glyphs.
+    fn rotate_metrics(&self, metrics: fontdue::Metrics) -> fontdue::Metrics {
+        if self.rotated {
+            fontdue::Metrics {
+                width: metrics.height,
+                height: metrics.width,
+                ..metrics
+            }
+        } else {
+            metrics
+        }
+    }
+
+    /// Rotates a rasterized glyph clockwise if the id asks for it.
+    fn rotate(
+        &self,
+        metrics: fontdue::Metrics,
+        bitmap: Vec<u8>,
+        bytes_per_pixel: usize,
+    ) -> (fontdue::Metrics, Vec<u8>) {
+        if !self.rotated {
+            return (metrics, bitmap);
+        }
+
+        let (width, height) = (metrics.width, metrics.height);
+        (
+            self.rotate_metrics(metrics),
+            rotate_clockwise(&bitmap, width, height, bytes_per_pixel),
+        )
+    }
+
+    /// Adjusts the metrics of the plain glyph for synthetic bold and oblique.
+    fn synthetic_metrics(&self, mut metrics: fontdue::Metrics) -> fontdue::Metrics {
+        let strength = self.synthetic_advance();
+        metrics.advance_width += strength;
+        if metrics.width == 0 || metrics.height == 0 {
+            return metrics;
+        }
+
+        let grow = strength.ceil() as usize;
+        metrics.width += grow;
+        metrics.height += grow;
+        metrics.bounds.width += strength;
+        metrics.bounds.height += strength;
+
+        let (shift, extra) = self.shear_extent(metrics.ymin, metrics.height);
+        metrics.xmin += shift;
+        metrics.width += extra;
+        metrics.bounds.xmin += shift as f32;
+        metrics.bounds.width += extra as f32;
+
+        metrics
+    }
+
+    /// Returns the shift of the bottom row and the width added by synthetic
+    /// oblique, for a bitmap of `height` rows starting `ymin` above the baseline.
+    fn shear_extent(&self, ymin: i32, height: usize) -> (i32, usize) {
+        if !self.oblique || height == 0 {
+            return (0, 0);
+        }
+
+        // Rows are sheared around their center.
+        let bottom = (ymin as f32 + 0.5) * SYNTHETIC_OBLIQUE_SHEAR;
+        let top = (ymin as f32 + height as f32 - 0.5) * SYNTHETIC_OBLIQUE_SHEAR;
+        let shift = bottom.floor();
+        (shift as i32, (top - shift).floor() as usize + 1)
+    }
+
+    /// Applies synthetic bold and oblique to a rasterized glyph.
+    fn synthesize(
+        &self,
+        metrics: fontdue::Metrics,
+        mut bitmap: Vec<u8>,
+    ) -> (fontdue::Metrics, Vec<u8>) {
+        let synthetic = self.synthetic_metrics(metrics);
+        if metrics.width == 0 || metrics.height == 0 {
+            return (synthetic, bitmap);
+        }
+
+        let (mut width, mut height) = (metrics.width, metrics.height);
+
+        if self.bold {
+            // Dilate towards the right and the top, the last pixel being
+            // partially covered for fractional strengths.
+            let strength = self.synthetic_advance();
+            let grow = strength.ceil() as usize;
+            let weight = |k: usize| (strength - k as f32 + 1.0).clamp(0.0, 1.0);
+
+            let mut wide = vec![0u8; (width + grow) * height];
+            for row in 0..height {
+                for col in 0..width + grow {
+                    wide[row * (width + grow) + col] = (0..=grow)
+                        .filter(|k| *k <= col && col - k < width)
+                        .map(|k| (bitmap[row * width + col - k] as f32 * weight(k)) as u8)
+                        .max()
+                        .unwrap_or(0);
+                }
+            }
+            width += grow;
+
+            // Source row `row` ends up at `row + grow` and spreads upwards.
+            let mut tall = vec![0u8; width * (height + grow)];
+            for row in 0..height + grow {
+                for col in 0..width {
+                    tall[row * width + col] = (0..=grow)
+                        .filter(|k| row + k >= grow && row + k - grow < height)
+                        .map(|k| (wide[(row + k - grow) * width + col] as f32 * weight(k)) as u8)
+                        .max()
+                        .unwrap_or(0);
+                }
+            }
+            height += grow;
+            bitmap = tall;
+        }
+
+        if self.oblique {
+            let (shift, extra) = self.shear_extent(metrics.ymin, height);
+            let sheared_width = width + extra;
+            let mut sheared = vec![0f32; sheared_width * height];
+            for row in 0..height {
+                let y = metrics.ymin as f32 + (height - row) as f32 - 0.5;
+                let offset = y * SYNTHETIC_OBLIQUE_SHEAR - shift as f32;
+                let whole = offset.floor().max(0.0) as usize;
+                let fraction = offset - whole as f32;
+                for col in 0..width {
+                    let value = bitmap[row * width + col] as f32;
+                    let index = row * sheared_width + col + whole;
+                    sheared[index] += value * (1.0 - fraction);
+                    if col + whole + 1 < sheared_width {
+                        sheared[index + 1] += value * fraction;
+                    }
+                }
+            }
+            width = sheared_width;
+            bitmap = sheared
+                .into_iter()
+                .map(|value| value.round().min(255.0) as u8)
+                .collect();
+        }
+
+        debug_assert_eq!((width, height), (synthetic.width, synthetic.height));
+        (synthetic, bitmap)
+    }
+}
+
+/// Rotates a bitmap 90° clockwise: the top row becomes the right column.
+fn rotate_clockwise(bitmap: &[u8], width: usize, height: usize, bytes_per_pixel: usize) -> Vec<u8> {
+    let mut rotated = vec![0u8; bitmap.len()];
+    for row in 0..height {
+        for col in 0..width {
+            let from = (row * width + col) * bytes_per_pixel;
+            let to = (col * height + (height - 1 - row)) * bytes_per_pixel;
+            rotated[to..to + bytes_per_pixel]
+                .copy_from_slice(&bitmap[from..from + bytes_per_pixel]);
+        }
+    }
+    rotated
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(font_size: f32) -> GlyphId {
+        GlyphId::new(
+            unsafe { std::mem::transmute::<u64, fontdb::ID>(1) },
+            1,
+            font_size,
+        )
+    }
+
+    /// Metrics of a plain glyph bitmap of `width` × `height` pixels.
+    fn plain_metrics(ymin: i32, width: usize, height: usize) -> fontdue::Metrics {
+        fontdue::Metrics {
+            xmin: 1,
+            ymin,
+            width,
+            height,
+            advance_width: 10.0,
+            advance_height: 0.0,
+            bounds: fontdue::OutlineBounds {
+                xmin: 1.0,
+                ymin: ymin as f32,
+                width: width as f32,
+                height: height as f32,
+            },
+        }
+    }
+
+    #[test]
+    fn test_synthetic_bold() {
+        // At 24px the glyph grows by one pixel to the right and upwards.
+        let id = glyph(24.0).with_synthetic_bold(true);
+        let plain = plain_metrics(0, 2, 2);
+        let (metrics, bitmap) = id.synthesize(plain, vec![255, 0, 0, 255]);
+        assert_eq!((metrics.width, metrics.height), (3, 3));
+        assert_eq!(metrics.advance_width, 11.0);
+        assert_eq!(id.synthetic_advance(), 1.0);
+        #[rustfmt::skip]
+        assert_eq!(bitmap, vec![
+            255, 255, 0,
+            255, 255, 255,
+            0, 255, 255,
+        ]);
+
+        // The bitmap grows upwards, so its top-left corner moves up.
+        assert_eq!(id.synthetic_offset_of(plain), [0.0, -1.0]);
+        assert_eq!((metrics.xmin, metrics.ymin), (plain.xmin, plain.ymin));
+
+        // Glyphs without a bitmap only advance further.
+        let (metrics, bitmap) = id.synthesize(plain_metrics(0, 0, 0), Vec::new());
+        assert_eq!((metrics.width, metrics.advance_width), (0, 11.0));
+        assert!(bitmap.is_empty());
+    }
+
+    #[test]
+    fn test_synthetic_oblique() {
+        let id = glyph(24.0).with_synthetic_oblique(true);
+        assert_eq!(id.synthetic_advance(), 0.0);
+
+        // Rows above the baseline shift right, rows below it shift left.
+        assert_eq!(id.shear_extent(0, 10), (0, 3));
+        assert_eq!(id.shear_extent(-5, 10), (-1, 2));
+        assert_eq!(id.shear_extent(-5, 0), (0, 0));
+        assert_eq!(glyph(24.0).shear_extent(0, 10), (0, 0));
+
+        for ymin in [-5, 0, 4] {
+            let plain = plain_metrics(ymin, 3, 10);
+            let (metrics, bitmap) = id.synthesize(plain, vec![255; 30]);
+            let (shift, extra) = id.shear_extent(ymin, 10);
+            assert_eq!((metrics.width, metrics.height), (3 + extra, 10));
+            assert_eq!(bitmap.len(), metrics.width * metrics.height);
+            assert_eq!(metrics.xmin, plain.xmin + shift);
+            assert_eq!(metrics.advance_width, plain.advance_width);
+            // The offset of the bitmap matches the moved origin of the metrics.
+            assert_eq!(id.synthetic_offset_of(plain), [shift as f32, 0.0]);
+
+            // Coverage is moved, not lost.
+            let total = |bitmap: &[u8]| bitmap.iter().map(|&v| v as u32).sum::<u32>();
+            assert!(total(&bitmap).abs_diff(255 * 30) <= 10);
+        }
+    }
+
+    #[test]
+    fn test_synthetic_bold_oblique() {
+        let id = glyph(24.0)
+            .with_synthetic_bold(true)
+            .with_synthetic_oblique(true);
+        let plain = plain_metrics(-2, 4, 6);
+        let (metrics, bitmap) = id.synthesize(plain, vec![128; 24]);
+        let (shift, extra) = id.shear_extent(-2, 7);
+        assert_eq!((metrics.width, metrics.height), (5 + extra, 7));
+        assert_eq!(bitmap.len(), metrics.width * metrics.height);
+        assert_eq!(metrics.advance_width, 11.0);
+        assert_eq!(id.synthetic_offset_of(plain), [shift as f32, -1.0]);
+        assert_eq!(metrics.xmin - plain.xmin, shift);
+
+        // Rotated glyphs grow away from the top-left corner.
+        let rotated = id.with_rotation(true);
+        assert_eq!(rotated.synthetic_offset_of(plain), [0.0, shift as f32]);
+    }
 }
@@ -0,0 +1,1432 @@
+//! Color glyph images from the `COLR`, `CBDT` and `sbix` tables.
+//!
+//! `COLR` glyphs are painted layer by layer with a small coverage rasterizer, so
+//! the transforms, clips and gradients of version 1 can be honored. Bitmap glyphs
+//! are decoded from their embedded PNG data and scaled to the requested size.
+//!
+//! Color images are premultiplied RGBA, four bytes per pixel, row-major.
+//! `COLR` glyphs painted only in the text color are reduced to coverage, one
+//! byte per pixel, so they can be tinted like outline glyphs.
+
+use std::{collections::HashMap, sync::Arc};
+
+use parking_lot::Mutex;
+use rustybuzz::ttf_parser::{
+    self, GlyphId as TtfGlyphId, OutlineBuilder, RasterImageFormat, RgbaColor, Transform,
+    colr::{ClipBox, CompositeMode, GradientExtend, Paint, Painter},
+};
+
+/// Color of the layers meant to be drawn in the text color.
+///
+/// Glyphs painted only in the text color are drawn as coverage, which renderers
+/// tint like outline glyphs. The text color is not known here, so in glyphs that
+/// also use palette colors these layers are drawn black.
+const FOREGROUND_COLOR: RgbaColor = RgbaColor {
+    red: 0,
+    green: 0,
+    blue: 0,
+    alpha: 255,
+};
+
+/// Second text color, used to tell the layers drawn in the text color apart
+/// from the palette ones.
+const FOREGROUND_PROBE: RgbaColor = RgbaColor {
+    red: 255,
+    green: 255,
+    blue: 255,
+    alpha: 255,
+};
+
+/// A face with `COLR`, `CBDT` or `sbix` tables, parsed once and kept together
+/// with the font data it reads from.
+///
+/// It also keeps how each glyph is drawn, which does not depend on the size, so
+/// the tables of a glyph are only walked the first time it is asked for.
+pub struct ColorFace {
+    // Borrows from `_data`, so it is declared (and dropped) first. Only reached
+    // through `Self::face`, which ties the borrow to `self`.
+    face: ttf_parser::Face<'static>,
+    _data: Arc<[u8]>,
+    glyphs: Mutex<HashMap<u16, ColorGlyph, fxhash::FxBuildHasher>>,
+}
+
+/// How a glyph of a [`ColorFace`] is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorGlyph {
+    /// From its outline, like glyphs of other faces.
+    Outline,
+    /// From `COLR` layers filling `rect` (in font units). `tinted` is set when
+    /// every layer is drawn in the text color.
+    Layered { rect: Rect, tinted: bool },
+    /// From the embedded bitmap strike closest to the size.
+    Bitmap,
+}
+
+impl ColorFace {
+    /// Parses the face at `index` in `data`, or returns `None` if it can not be
+    /// parsed or has no table this module can draw from.
+    pub(crate) fn parse(data: Arc<[u8]>, index: u32) -> Option<Self> {
+        // SAFETY: the bytes live in the heap allocation of `data`, which is never
+        // mutated and is owned by `Self` alongside the face, which is dropped first.
+        let bytes: &'static [u8] = unsafe { &*Arc::as_ptr(&data) };
+        let face = ttf_parser::Face::parse(bytes, index).ok()?;
+        let tables = face.tables();
+        if tables.colr.is_none() && tables.cbdt.is_none() && tables.sbix.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            face,
+            _data: data,
+            glyphs: Mutex::new(HashMap::default()),
+        })
+    }
+
+    fn face(&self) -> &ttf_parser::Face<'_> {
+        &self.face
+    }
+
+    fn glyph(&self, glyph_index: u16) -> ColorGlyph {
+        let face = self.face();
+        *self.glyphs.lock().entry(glyph_index).or_insert_with(|| {
+            let glyph = TtfGlyphId(glyph_index);
+            let tables = face.tables();
+            if face.is_color_glyph(glyph) {
+                colr_glyph(face, glyph)
+            } else if tables.cbdt.is_some() || tables.sbix.is_some() {
+                ColorGlyph::Bitmap
+            } else {
+                ColorGlyph::Outline
+            }
+        })
+    }
+
+    /// Returns the metrics of the image of a glyph and whether it is a color
+    /// image, or `None` if the glyph is drawn from its outline.
+    ///
+    /// `COLR` glyphs drawn only in the text color are coverage images. The
+    /// advances are taken from `font`; the bitmap box describes the image.
+    pub(crate) fn metrics(
+        &self,
+        font: &fontdue::Font,
+        glyph_index: u16,
+        font_size: f32,
+    ) -> Option<(fontdue::Metrics, bool)> {
+        let (bounds, color) = match self.glyph(glyph_index) {
+            ColorGlyph::Outline => return None,
+            ColorGlyph::Layered { rect, tinted } => {
+                let scale = font_size / self.face().units_per_em() as f32;
+                (PixelBounds::covering(rect, scale), !tinted)
+            }
+            ColorGlyph::Bitmap => (
+                bitmap_bounds(self.face(), TtfGlyphId(glyph_index), font_size)?,
+                true,
+            ),
+        };
+
+        let metrics = fontdue::Metrics {
+            xmin: bounds.xmin,
+            ymin: bounds.ymin,
+            width: bounds.width,
+            height: bounds.height,
+            bounds: fontdue::OutlineBounds {
+                xmin: bounds.xmin as f32,
+                ymin: bounds.ymin as f32,
+                width: bounds.width as f32,
+                height: bounds.height as f32,
+            },
+            ..font.metrics_indexed(glyph_index, font_size)
+        };
+        Some((metrics, color))
+    }
+
+    /// Rasterizes the image of a glyph, or returns `None` if it is drawn from
+    /// its outline.
+    ///
+    /// Color images are premultiplied RGBA, coverage images one byte per pixel,
+    /// as told by the returned flag. See [`Self::metrics`].
+    pub(crate) fn rasterize(
+        &self,
+        font: &fontdue::Font,
+        glyph_index: u16,
+        font_size: f32,
+    ) -> Option<(fontdue::Metrics, Vec<u8>, bool)> {
+        let (metrics, color) = self.metrics(font, glyph_index, font_size)?;
+        let bounds = PixelBounds {
+            xmin: metrics.xmin,
+            ymin: metrics.ymin,
+            width: metrics.width,
+            height: metrics.height,
+        };
+
+        let (face, glyph) = (self.face(), TtfGlyphId(glyph_index));
+        let pixels = match self.glyph(glyph_index) {
+            ColorGlyph::Outline => return None,
+            ColorGlyph::Layered { .. } => paint_colr(face, glyph, font_size, &bounds),
+            ColorGlyph::Bitmap => draw_bitmap(face, glyph, font_size, &bounds)?,
+        };
+
+        if color {
+            Some((metrics, pixels, true))
+        } else {
+            let coverage = pixels.chunks_exact(4).map(|pixel| pixel[3]).collect();
+            Some((metrics, coverage, false))
+        }
+    }
+}
+
+/// Bitmap box in whole pixels, `ymin` being the bottom edge above the baseline.
+struct PixelBounds {
+    xmin: i32,
+    ymin: i32,
+    width: usize,
+    height: usize,
+}
+
+impl PixelBounds {
+    /// Returns the pixels covering `rect`, given in font units scaled by `scale`.
+    fn covering(rect: Rect, scale: f32) -> Self {
+        let [x_min, y_min, x_max, y_max] = rect;
+        let xmin = (x_min * scale).floor() as i32;
+        let ymin = (y_min * scale).floor() as i32;
+        let width = ((x_max * scale).ceil() as i32 - xmin).max(0) as usize;
+        let height = ((y_max * scale).ceil() as i32 - ymin).max(0) as usize;
+
+        Self {
+            xmin,
+            ymin,
+            width,
+            height,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Embedded bitmaps (CBDT, sbix)
+// ---------------------------------------------------------------------------
+
+/// Returns the bitmap image of a glyph in the strike closest to `font_size`,
+/// if it is in a format that can be decoded.
+fn raster_image<'a>(
+    face: &'a ttf_parser::Face,
+    glyph: TtfGlyphId,
+    font_size: f32,
+) -> Option<ttf_parser::RasterGlyphImage<'a>> {
+    let pixels_per_em = font_size.round().clamp(1.0, u16::MAX as f32) as u16;
+    let image = face.glyph_raster_image(glyph, pixels_per_em)?;
+    match image.format {
+        RasterImageFormat::PNG | RasterImageFormat::BitmapPremulBgra32 => {
+            (image.pixels_per_em > 0).then_some(image)
+        }
+        // Monochrome and grayscale strikes are left to the outlines.
+        _ => None,
+    }
+}
+
+fn bitmap_bounds(
+    face: &ttf_parser::Face,
+    glyph: TtfGlyphId,
+    font_size: f32,
+) -> Option<PixelBounds> {
+    let image = raster_image(face, glyph, font_size)?;
+    let scale = font_size / image.pixels_per_em as f32;
+
+    Some(PixelBounds {
+        xmin: (image.x as f32 * scale).round() as i32,
+        ymin: (image.y as f32 * scale).round() as i32,
+        width: (image.width as f32 * scale).round().max(1.0) as usize,
+        height: (image.height as f32 * scale).round().max(1.0) as usize,
+    })
+}
+
+fn draw_bitmap(
+    face: &ttf_parser::Face,
+    glyph: TtfGlyphId,
+    font_size: f32,
+    bounds: &PixelBounds,
+) -> Option<Vec<u8>> {
+    let image = raster_image(face, glyph, font_size)?;
+    let (width, height, pixels) = match image.format {
+        RasterImageFormat::PNG => decode_png(image.data)?,
+        _ => {
+            let (width, height) = (image.width as usize, image.height as usize);
+            let pixels = image.data.get(..width * height * 4)?;
+            let rgba = pixels
+                .chunks_exact(4)
+                .flat_map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]])
+                .collect();
+            (width, height, rgba)
+        }
+    };
+
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some(resize(&pixels, width, height, bounds.width, bounds.height))
+}
+
+/// Decodes a PNG image into premultiplied RGBA.
+fn decode_png(data: &[u8]) -> Option<(usize, usize, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buffer = vec![0; reader.output_buffer_size()?];
+    let info = reader.next_frame(&mut buffer).ok()?;
+    let pixels = buffer.get(..info.buffer_size())?;
+
+    let premultiply = |[r, g, b, a]: [u8; 4]| {
+        let scale = |value: u8| ((value as u16 * a as u16 + 127) / 255) as u8;
+        [scale(r), scale(g), scale(b), a]
+    };
+    let rgba: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => pixels
+            .chunks_exact(4)
+            .flat_map(|p| premultiply([p[0], p[1], p[2], p[3]]))
+            .collect(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| premultiply([p[0], p[0], p[0], p[1]]))
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        png::ColorType::Indexed => return None,
+    };
+
+    Some((info.width as usize, info.height as usize, rgba))
+}
+
+/// Scales an RGBA image by averaging the source area under each target pixel.
+fn resize(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    to_width: usize,
+    to_height: usize,
+) -> Vec<u8> {
+    if (width, height) == (to_width, to_height) {
+        return pixels.to_vec();
+    }
+
+    let scale_x = width as f32 / to_width as f32;
+    let scale_y = height as f32 / to_height as f32;
+    // Source pixels overlapping `[start, end)` with the length of the overlap.
+    let spans = |start: f32, end: f32, limit: usize| {
+        (start.floor() as usize..(end.ceil() as usize).min(limit)).map(move |index| {
+            let overlap = end.min(index as f32 + 1.0) - start.max(index as f32);
+            (index, overlap)
+        })
+    };
+
+    let mut resized = vec![0u8; to_width * to_height * 4];
+    for row in 0..to_height {
+        let (top, bottom) = (row as f32 * scale_y, (row + 1) as f32 * scale_y);
+        for col in 0..to_width {
+            let (left, right) = (col as f32 * scale_x, (col + 1) as f32 * scale_x);
+            let mut sum = [0f32; 4];
+            let mut area = 0.0;
+            for (y, weight_y) in spans(top, bottom, height) {
+                for (x, weight_x) in spans(left, right, width) {
+                    let weight = weight_x * weight_y;
+                    let index = (y * width + x) * 4;
+                    for (channel, value) in sum.iter_mut().zip(&pixels[index..index + 4]) {
+                        *channel += *value as f32 * weight;
+                    }
+                    area += weight;
+                }
+            }
+            if area > 0.0 {
+                let index = (row * to_width + col) * 4;
+                for (target, value) in resized[index..index + 4].iter_mut().zip(sum) {
+                    *target = (value / area).round().min(255.0) as u8;
+                }
+            }
+        }
+    }
+    resized
+}
+
+// ---------------------------------------------------------------------------
+// Layered glyphs (COLR)
+// ---------------------------------------------------------------------------
+
+/// Applies `inner` first, then `outer`.
+fn concat(outer: &Transform, inner: &Transform) -> Transform {
+    Transform::new(
+        outer.a * inner.a + outer.c * inner.b,
+        outer.b * inner.a + outer.d * inner.b,
+        outer.a * inner.c + outer.c * inner.d,
+        outer.b * inner.c + outer.d * inner.d,
+        outer.a * inner.e + outer.c * inner.f + outer.e,
+        outer.b * inner.e + outer.d * inner.f + outer.f,
+    )
+}
+
+fn apply(transform: &Transform, x: f32, y: f32) -> (f32, f32) {
+    (
+        transform.a * x + transform.c * y + transform.e,
+        transform.b * x + transform.d * y + transform.f,
+    )
+}
+
+fn invert(transform: &Transform) -> Option<Transform> {
+    let det = transform.a * transform.d - transform.b * transform.c;
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let (a, b, c, d) = (
+        transform.d / det,
+        -transform.b / det,
+        -transform.c / det,
+        transform.a / det,
+    );
+    Some(Transform::new(
+        a,
+        b,
+        c,
+        d,
+        -(a * transform.e + c * transform.f),
+        -(b * transform.e + d * transform.f),
+    ))
+}
+
+/// Walks the paints of a `COLR` glyph for the area they fill and whether they
+/// are all drawn in the text color.
+fn colr_glyph(face: &ttf_parser::Face, glyph: TtfGlyphId) -> ColorGlyph {
+    let collect = |foreground| {
+        let mut collector = BoundsCollector {
+            face,
+            transforms: vec![Transform::default()],
+            outline: None,
+            clips: Vec::new(),
+            rect: None,
+            colors: Vec::new(),
+        };
+        face.paint_color_glyph(glyph, 0, foreground, &mut collector)
+            .map(|()| collector)
+    };
+    let (Some(plain), Some(probe)) = (collect(FOREGROUND_COLOR), collect(FOREGROUND_PROBE)) else {
+        return ColorGlyph::Outline;
+    };
+    let Some(rect) = plain.rect else {
+        return ColorGlyph::Outline;
+    };
+
+    // Only the colors taken from the text color change with it.
+    let tinted = !plain.colors.is_empty()
+        && plain
+            .colors
+            .iter()
+            .zip(&probe.colors)
+            .all(|(plain, probe)| plain != probe);
+    ColorGlyph::Layered { rect, tinted }
+}
+
+/// Rectangle as `[x_min, y_min, x_max, y_max]`, empty if a minimum exceeds its maximum.
+type Rect = [f32; 4];
+
+fn intersect(a: Rect, b: Option<&Rect>) -> Rect {
+    match b {
+        Some(b) => [
+            a[0].max(b[0]),
+            a[1].max(b[1]),
+            a[2].min(b[2]),
+            a[3].min(b[3]),
+        ],
+        None => a,
+    }
+}
+
+/// Painter collecting the union of the areas filled by paints, in font units.
+///
+/// Areas are tracked as boxes: an outline or clip box is bounded by the box
+/// around its transformed corners.
+struct BoundsCollector<'f, 'a> {
+    face: &'f ttf_parser::Face<'a>,
+    transforms: Vec<Transform>,
+    /// Box of the outline set by `outline_glyph` and not turned into a clip yet.
+    outline: Option<Rect>,
+    /// Boxes of the active clips, each one intersected with the previous.
+    clips: Vec<Rect>,
+    rect: Option<Rect>,
+    /// Colors of the paints, including the stops of gradients, in paint order.
+    colors: Vec<RgbaColor>,
+}
+
+impl BoundsCollector<'_, '_> {
+    fn transformed(&self, rect: Rect) -> Rect {
+        let transform = self.transforms.last().copied().unwrap_or_default();
+        let corners = [
+            (rect[0], rect[1]),
+            (rect[2], rect[1]),
+            (rect[0], rect[3]),
+            (rect[2], rect[3]),
+        ]
+        .map(|(x, y)| apply(&transform, x, y));
+        corners.iter().fold(
+            [f32::MAX, f32::MAX, f32::MIN, f32::MIN],
+            |[x_min, y_min, x_max, y_max], &(x, y)| {
+                [x_min.min(x), y_min.min(y), x_max.max(x), y_max.max(y)]
+            },
+        )
+    }
+}
+
+impl<'a> Painter<'a> for BoundsCollector<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: TtfGlyphId) {
+        self.outline = Some(match self.face.glyph_bounding_box(glyph_id) {
+            Some(rect) => self.transformed([
+                rect.x_min as f32,
+                rect.y_min as f32,
+                rect.x_max as f32,
+                rect.y_max as f32,
+            ]),
+            None => [0.0, 0.0, -1.0, -1.0],
+        });
+    }
+
+    fn paint(&mut self, paint: Paint<'a>) {
+        match paint {
+            Paint::Solid(color) => self.colors.push(color),
+            Paint::LinearGradient(gradient) => {
+                self.colors
+                    .extend(gradient.stops(0, &[]).map(|stop| stop.color));
+            }
+            Paint::RadialGradient(gradient) => {
+                self.colors
+                    .extend(gradient.stops(0, &[]).map(|stop| stop.color));
+            }
+            Paint::SweepGradient(gradient) => {
+                self.colors
+                    .extend(gradient.stops(0, &[]).map(|stop| stop.color));
+            }
+        }
+
+        // Paints fill the whole clip, and have no bounds without one.
+        let area = match self.outline.take() {
+            Some(outline) => intersect(outline, self.clips.last()),
+            None => match self.clips.last() {
+                Some(clip) => *clip,
+                None => return,
+            },
+        };
+        if area[0] >= area[2] || area[1] >= area[3] {
+            return;
+        }
+        let union = self.rect.get_or_insert(area);
+        *union = [
+            union[0].min(area[0]),
+            union[1].min(area[1]),
+            union[2].max(area[2]),
+            union[3].max(area[3]),
+        ];
+    }
+
+    fn push_clip(&mut self) {
+        let outline = self.outline.take().unwrap_or([0.0, 0.0, -1.0, -1.0]);
+        self.clips.push(intersect(outline, self.clips.last()));
+    }
+
+    fn push_clip_box(&mut self, clip_box: ClipBox) {
+        let rect = self.transformed([
+            clip_box.x_min,
+            clip_box.y_min,
+            clip_box.x_max,
+            clip_box.y_max,
+        ]);
+        self.clips.push(intersect(rect, self.clips.last()));
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn push_layer(&mut self, _: CompositeMode) {}
+    fn pop_layer(&mut self) {}
+
+    fn push_transform(&mut self, transform: Transform) {
+        let current = self.transforms.last().copied().unwrap_or_default();
+        self.transforms.push(concat(&current, &transform));
+    }
+
+    fn pop_transform(&mut self) {
+        if self.transforms.len() > 1 {
+            self.transforms.pop();
+        }
+    }
+}
+
+fn paint_colr(
+    face: &ttf_parser::Face,
+    glyph: TtfGlyphId,
+    font_size: f32,
+    bounds: &PixelBounds,
+) -> Vec<u8> {
+    let (width, height) = (bounds.width, bounds.height);
+    let scale = font_size / face.units_per_em() as f32;
+    // Font units (y up) to canvas pixels (y down).
+    let base = Transform::new(
+        scale,
+        0.0,
+        0.0,
+        -scale,
+        -bounds.xmin as f32,
+        (bounds.ymin + height as i32) as f32,
+    );
+
+    let mut painter = ColrPainter {
+        face,
+        width,
+        height,
+        transforms: vec![base],
+        outline: None,
+        clips: Vec::new(),
+        layers: vec![(CompositeMode::SourceOver, vec![[0.0; 4]; width * height])],
+    };
+    face.paint_color_glyph(glyph, 0, FOREGROUND_COLOR, &mut painter);
+
+    let canvas = painter.layers.swap_remove(0).1;
+    canvas
+        .into_iter()
+        .flat_map(|pixel| pixel.map(|value| (value * 255.0).round().clamp(0.0, 255.0) as u8))
+        .collect()
+}
+
+/// Painter drawing the layers of a `COLR` glyph onto a premultiplied canvas.
+struct ColrPainter<'f, 'a> {
+    face: &'f ttf_parser::Face<'a>,
+    width: usize,
+    height: usize,
+    /// Transforms from the current paint space to canvas pixels.
+    transforms: Vec<Transform>,
+    /// Glyph set by `outline_glyph` and not turned into a clip yet.
+    outline: Option<TtfGlyphId>,
+    /// Coverage of the active clips, each one intersected with the previous.
+    clips: Vec<Vec<f32>>,
+    /// Canvases of the open layers, with the mode they are composited with.
+    layers: Vec<(CompositeMode, Vec<[f32; 4]>)>,
+}
+
+impl ColrPainter<'_, '_> {
+    fn transform(&self) -> Transform {
+        self.transforms.last().copied().unwrap_or_default()
+    }
+
+    /// Rasterizes a path given in the current paint space.
+    fn coverage(&self, draw: impl FnOnce(&mut PathRasterizer)) -> Vec<f32> {
+        let mut rasterizer = PathRasterizer::new(self.width, self.height, self.transform());
+        draw(&mut rasterizer);
+        let mut coverage = rasterizer.finish();
+        if let Some(clip) = self.clips.last() {
+            for (value, clip) in coverage.iter_mut().zip(clip) {
+                *value *= clip;
+            }
+        }
+        coverage
+    }
+
+    fn glyph_coverage(&self, glyph_id: TtfGlyphId) -> Vec<f32> {
+        self.coverage(|rasterizer| {
+            self.face.outline_glyph(glyph_id, rasterizer);
+        })
+    }
+}
+
+impl<'a> Painter<'a> for ColrPainter<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: TtfGlyphId) {
+        self.outline = Some(glyph_id);
+    }
+
+    fn paint(&mut self, paint: Paint<'a>) {
+        // Version 0 layers paint the outline directly, without clipping first.
+        let coverage = match self.outline.take() {
+            Some(glyph_id) => self.glyph_coverage(glyph_id),
+            None => match self.clips.last() {
+                Some(clip) => clip.clone(),
+                None => vec![1.0; self.width * self.height],
+            },
+        };
+
+        let shader = Shader::new(&paint, &self.transform());
+        let width = self.width;
+        let Some((_, canvas)) = self.layers.last_mut() else {
+            return;
+        };
+        for (index, (pixel, coverage)) in canvas.iter_mut().zip(coverage).enumerate() {
+            if coverage <= 0.0 {
+                continue;
+            }
+            let Some(color) =
+                shader.color_at((index % width) as f32 + 0.5, (index / width) as f32 + 0.5)
+            else {
+                continue;
+            };
+            let source = color.map(|value| value * coverage);
+            *pixel = composite(CompositeMode::SourceOver, source, *pixel);
+        }
+    }
+
+    fn push_clip(&mut self) {
+        let coverage = match self.outline.take() {
+            Some(glyph_id) => self.glyph_coverage(glyph_id),
+            None => vec![0.0; self.width * self.height],
+        };
+        self.clips.push(coverage);
+    }
+
+    fn push_clip_box(&mut self, clip_box: ClipBox) {
+        let coverage = self.coverage(|rasterizer| {
+            rasterizer.move_to(clip_box.x_min, clip_box.y_min);
+            rasterizer.line_to(clip_box.x_max, clip_box.y_min);
+            rasterizer.line_to(clip_box.x_max, clip_box.y_max);
+            rasterizer.line_to(clip_box.x_min, clip_box.y_max);
+            rasterizer.close();
+        });
+        self.clips.push(coverage);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn push_layer(&mut self, mode: CompositeMode) {
+        self.layers
+            .push((mode, vec![[0.0; 4]; self.width * self.height]));
+    }
+
+    fn pop_layer(&mut self) {
+        if self.layers.len() < 2 {
+            return;
+        }
+        let Some((mode, source)) = self.layers.pop() else {
+            return;
+        };
+        if let Some((_, canvas)) = self.layers.last_mut() {
+            for (pixel, source) in canvas.iter_mut().zip(source) {
+                *pixel = composite(mode, source, *pixel);
+            }
+        }
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        let current = self.transform();
+        self.transforms.push(concat(&current, &transform));
+    }
+
+    fn pop_transform(&mut self) {
+        if self.transforms.len() > 1 {
+            self.transforms.pop();
+        }
+    }
+}
+
+/// Composites premultiplied `source` onto `backdrop`.
+///
+/// Blend modes other than multiply and screen fall back to source over.
+fn composite(mode: CompositeMode, source: [f32; 4], backdrop: [f32; 4]) -> [f32; 4] {
+    let (sa, da) = (source[3], backdrop[3]);
+    let porter_duff = |fs: f32, fd: f32| std::array::from_fn(|i| source[i] * fs + backdrop[i] * fd);
+
+    match mode {
+        CompositeMode::Clear => [0.0; 4],
+        CompositeMode::Source => source,
+        CompositeMode::Destination => backdrop,
+        CompositeMode::DestinationOver => porter_duff(1.0 - da, 1.0),
+        CompositeMode::SourceIn => porter_duff(da, 0.0),
+        CompositeMode::DestinationIn => porter_duff(0.0, sa),
+        CompositeMode::SourceOut => porter_duff(1.0 - da, 0.0),
+        CompositeMode::DestinationOut => porter_duff(0.0, 1.0 - sa),
+        CompositeMode::SourceAtop => porter_duff(da, 1.0 - sa),
+        CompositeMode::DestinationAtop => porter_duff(1.0 - da, sa),
+        CompositeMode::Xor => porter_duff(1.0 - da, 1.0 - sa),
+        CompositeMode::Plus => std::array::from_fn(|i| (source[i] + backdrop[i]).min(1.0)),
+        CompositeMode::Multiply => std::array::from_fn(|i| {
+            if i == 3 {
+                sa + da - sa * da
+            } else {
+                source[i] * backdrop[i] + source[i] * (1.0 - da) + backdrop[i] * (1.0 - sa)
+            }
+        }),
+        CompositeMode::Screen => {
+            std::array::from_fn(|i| source[i] + backdrop[i] - source[i] * backdrop[i])
+        }
+        _ => porter_duff(1.0, 1.0 - sa),
+    }
+}
+
+/// Color source of a paint, evaluated at canvas pixels.
+enum Shader {
+    Solid([f32; 4]),
+    Gradient {
+        /// Canvas pixels to the gradient space.
+        inverse: Transform,
+        kind: GradientKind,
+        extend: GradientExtend,
+        /// Sorted stops as (offset, unpremultiplied color).
+        stops: Vec<(f32, [f32; 4])>,
+    },
+    /// A gradient that can not be drawn (degenerate geometry or no stops).
+    Empty,
+}
+
+enum GradientKind {
+    /// Position along the line from `p0` to `p0 + direction`.
+    Linear {
+        p0: (f32, f32),
+        direction: (f32, f32),
+    },
+    /// Two point conical gradient.
+    Radial {
+        c0: (f32, f32),
+        r0: f32,
+        c1: (f32, f32),
+        r1: f32,
+    },
+    /// Counter-clockwise angle around `center`, in radians.
+    Sweep {
+        center: (f32, f32),
+        start: f32,
+        end: f32,
+    },
+}
+
+fn unpremultiplied(color: RgbaColor) -> [f32; 4] {
+    [color.red, color.green, color.blue, color.alpha].map(|value| value as f32 / 255.0)
+}
+
+impl Shader {
+    fn new(paint: &Paint, transform: &Transform) -> Self {
+        let (kind, extend, stops) = match paint {
+            Paint::Solid(color) => {
+                let [r, g, b, a] = unpremultiplied(*color);
+                return Self::Solid([r * a, g * a, b * a, a]);
+            }
+            Paint::LinearGradient(gradient) => {
+                // The color line runs from p0 to p1 projected onto the
+                // perpendicular of p0 -> p2.
+                let p0 = (gradient.x0, gradient.y0);
+                let to_p1 = (gradient.x1 - gradient.x0, gradient.y1 - gradient.y0);
+                let normal = (gradient.y2 - gradient.y0, gradient.x0 - gradient.x2);
+                let normal_length = normal.0 * normal.0 + normal.1 * normal.1;
+                let direction = if normal_length > f32::EPSILON {
+                    let projection = (to_p1.0 * normal.0 + to_p1.1 * normal.1) / normal_length;
+                    (normal.0 * projection, normal.1 * projection)
+                } else {
+                    to_p1
+                };
+                (
+                    GradientKind::Linear { p0, direction },
+                    gradient.extend,
+                    gradient.stops(0, &[]).collect::<Vec<_>>(),
+                )
+            }
+            Paint::RadialGradient(gradient) => (
+                GradientKind::Radial {
+                    c0: (gradient.x0, gradient.y0),
+                    r0: gradient.r0,
+                    c1: (gradient.x1, gradient.y1),
+                    r1: gradient.r1,
+                },
+                gradient.extend,
+                gradient.stops(0, &[]).collect(),
+            ),
+            Paint::SweepGradient(gradient) => (
+                // Angles are given in half turns.
+                GradientKind::Sweep {
+                    center: (gradient.center_x, gradient.center_y),
+                    start: gradient.start_angle * std::f32::consts::PI,
+                    end: gradient.end_angle * std::f32::consts::PI,
+                },
+                gradient.extend,
+                gradient.stops(0, &[]).collect(),
+            ),
+        };
+
+        let Some(inverse) = invert(transform) else {
+            return Self::Empty;
+        };
+        let mut stops: Vec<(f32, [f32; 4])> = stops
+            .into_iter()
+            .map(|stop| (stop.stop_offset, unpremultiplied(stop.color)))
+            .collect();
+        if stops.is_empty() {
+            return Self::Empty;
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Self::Gradient {
+            inverse,
+            kind,
+            extend,
+            stops,
+        }
+    }
+
+    /// Returns the premultiplied color at a canvas position, if the paint covers it.
+    fn color_at(&self, x: f32, y: f32) -> Option<[f32; 4]> {
+        let (inverse, kind, extend, stops) = match self {
+            Self::Solid(color) => return Some(*color),
+            Self::Empty => return None,
+            Self::Gradient {
+                inverse,
+                kind,
+                extend,
+                stops,
+            } => (inverse, kind, extend, stops),
+        };
+
+        let (px, py) = apply(inverse, x, y);
+        let t = match kind {
+            GradientKind::Linear { p0, direction } => {
+                let length = direction.0 * direction.0 + direction.1 * direction.1;
+                if length <= f32::EPSILON {
+                    return None;
+                }
+                ((px - p0.0) * direction.0 + (py - p0.1) * direction.1) / length
+            }
+            GradientKind::Radial { c0, r0, c1, r1 } => {
+                // Largest t with |p - c(t)| = r(t) and r(t) >= 0.
+                let cd = (c1.0 - c0.0, c1.1 - c0.1);
+                let pd = (px - c0.0, py - c0.1);
+                let dr = r1 - r0;
+                let a = cd.0 * cd.0 + cd.1 * cd.1 - dr * dr;
+                let b = pd.0 * cd.0 + pd.1 * cd.1 + r0 * dr;
+                let c = pd.0 * pd.0 + pd.1 * pd.1 - r0 * r0;
+                if a.abs() <= f32::EPSILON {
+                    if b.abs() <= f32::EPSILON {
+                        return None;
+                    }
+                    let t = c / (2.0 * b);
+                    (r0 + t * dr >= 0.0).then_some(t)?
+                } else {
+                    let discriminant = b * b - a * c;
+                    if discriminant < 0.0 {
+                        return None;
+                    }
+                    let root = discriminant.sqrt();
+                    let (t1, t2) = ((b + root) / a, (b - root) / a);
+                    let (high, low) = if t1 > t2 { (t1, t2) } else { (t2, t1) };
+                    if r0 + high * dr >= 0.0 {
+                        high
+                    } else if r0 + low * dr >= 0.0 {
+                        low
+                    } else {
+                        return None;
+                    }
+                }
+            }
+            GradientKind::Sweep { center, start, end } => {
+                if (end - start).abs() <= f32::EPSILON {
+                    return None;
+                }
+                let angle = (py - center.1).atan2(px - center.0);
+                let angle = angle.rem_euclid(std::f32::consts::TAU);
+                (angle - start) / (end - start)
+            }
+        };
+
+        let [r, g, b, a] = color_line(stops, *extend, t);
+        Some([r * a, g * a, b * a, a])
+    }
+}
+
+/// Returns the unpremultiplied color of a color line at `t`.
+fn color_line(stops: &[(f32, [f32; 4])], extend: GradientExtend, t: f32) -> [f32; 4] {
+    let (first, last) = (stops[0].0, stops[stops.len() - 1].0);
+    let span = last - first;
+    let t = if span <= f32::EPSILON {
+        t
+    } else {
+        let u = (t - first) / span;
+        let u = match extend {
+            GradientExtend::Pad => u.clamp(0.0, 1.0),
+            GradientExtend::Repeat => u.rem_euclid(1.0),
+            GradientExtend::Reflect => {
+                let u = u.rem_euclid(2.0);
+                if u > 1.0 { 2.0 - u } else { u }
+            }
+        };
+        first + u * span
+    };
+
+    let next = stops.partition_point(|(offset, _)| *offset <= t);
+    if next == 0 {
+        return stops[0].1;
+    }
+    if next == stops.len() {
+        return stops[stops.len() - 1].1;
+    }
+    let (from, to) = (stops[next - 1], stops[next]);
+    let fraction = (t - from.0) / (to.0 - from.0);
+    std::array::from_fn(|i| from.1[i] + (to.1[i] - from.1[i]) * fraction)
+}
+
+/// Coverage rasterizer for paths in font units.
+///
+/// Accumulates the signed area each edge covers and sums it along the rows,
+/// the same approach fontdue uses for outlines.
+struct PathRasterizer {
+    width: usize,
+    height: usize,
+    transform: Transform,
+    area: Vec<f32>,
+    start: (f32, f32),
+    current: (f32, f32),
+}
+
+impl PathRasterizer {
+    fn new(width: usize, height: usize, transform: Transform) -> Self {
+        Self {
+            width,
+            height,
+            transform,
+            // One spare column for the right edge and one cell of slack.
+            area: vec![0.0; (width + 1) * height + 1],
+            start: (0.0, 0.0),
+            current: (0.0, 0.0),
+        }
+    }
+
+    fn finish(self) -> Vec<f32> {
+        let stride = self.width + 1;
+        let mut coverage = vec![0.0; self.width * self.height];
+        for row in 0..self.height {
+            let mut sum = 0.0;
+            for col in 0..self.width {
+                sum += self.area[row * stride + col];
+                coverage[row * self.width + col] = sum.abs().min(1.0);
+            }
+        }
+        coverage
+    }
+
+    /// Adds a line in canvas pixels.
+    fn line(&mut self, from: (f32, f32), to: (f32, f32)) {
+        if (from.1 - to.1).abs() <= f32::EPSILON {
+            return;
+        }
+        let (direction, top, bottom) = if from.1 < to.1 {
+            (1.0, from, to)
+        } else {
+            (-1.0, to, from)
+        };
+        let dxdy = (bottom.0 - top.0) / (bottom.1 - top.1);
+        // Edges left of the canvas still count for the winding of the pixels
+        // right of them, so they are pressed against the left border.
+        let max_x = self.width as f32;
+        let stride = self.width + 1;
+
+        let first_row = top.1.max(0.0).floor() as usize;
+        let last_row = (bottom.1.ceil().max(0.0) as usize).min(self.height);
+        for row in first_row..last_row {
+            let y0 = (row as f32).max(top.1);
+            let y1 = ((row + 1) as f32).min(bottom.1);
+            if y1 <= y0 {
+                continue;
+            }
+            let x0 = (top.0 + (y0 - top.1) * dxdy).clamp(0.0, max_x);
+            let x1 = (top.0 + (y1 - top.1) * dxdy).clamp(0.0, max_x);
+            let delta = (y1 - y0) * direction;
+            let line = row * stride;
+
+            let (left, right) = if x0 < x1 { (x0, x1) } else { (x1, x0) };
+            let left_cell = left.floor();
+            let right_cell = right.ceil();
+            if right_cell - left_cell <= 1.0 {
+                // The edge stays in one cell: split by the mean x.
+                let mid = 0.5 * (x0 + x1) - left_cell;
+                let index = line + left_cell as usize;
+                self.area[index] += delta * (1.0 - mid);
+                self.area[index + 1] += delta * mid;
+            } else {
+                // Spread the coverage over the crossed cells.
+                let slope = (right - left).recip();
+                let left_fraction = left - left_cell;
+                let first = 0.5 * slope * (1.0 - left_fraction) * (1.0 - left_fraction);
+                let right_fraction = right - right_cell + 1.0;
+                let last = 0.5 * slope * right_fraction * right_fraction;
+                let (left_index, right_index) = (left_cell as usize, right_cell as usize);
+
+                self.area[line + left_index] += delta * first;
+                if right_index == left_index + 2 {
+                    self.area[line + left_index + 1] += delta * (1.0 - first - last);
+                } else {
+                    let second = slope * (1.5 - left_fraction);
+                    self.area[line + left_index + 1] += delta * (second - first);
+                    for index in left_index + 2..right_index - 1 {
+                        self.area[line + index] += delta * slope;
+                    }
+                    let before_last = second + (right_index - left_index - 3) as f32 * slope;
+                    self.area[line + right_index - 1] += delta * (1.0 - before_last - last);
+                }
+                self.area[line + right_index] += delta * last;
+            }
+        }
+    }
+
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        apply(&self.transform, x, y)
+    }
+}
+
+impl OutlineBuilder for PathRasterizer {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = self.point(x, y);
+        self.current = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.line(self.current, to);
+        self.current = to;
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p0, p1, p2) = (self.current, self.point(x1, y1), self.point(x, y));
+        let length = distance(p0, p1) + distance(p1, p2);
+        let steps = (length / 2.0).ceil().clamp(1.0, 64.0) as usize;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let u = 1.0 - t;
+            let to = (
+                u * u * p0.0 + 2.0 * u * t * p1.0 + t * t * p2.0,
+                u * u * p0.1 + 2.0 * u * t * p1.1 + t * t * p2.1,
+            );
+            self.line(self.current, to);
+            self.current = to;
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p0, p1, p2, p3) = (
+            self.current,
+            self.point(x1, y1),
+            self.point(x2, y2),
+            self.point(x, y),
+        );
+        let length = distance(p0, p1) + distance(p1, p2) + distance(p2, p3);
+        let steps = (length / 2.0).ceil().clamp(1.0, 64.0) as usize;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            let to = (
+                a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+                a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+            );
+            self.line(self.current, to);
+            self.current = to;
+        }
+    }
+
+    fn close(&mut self) {
+        self.line(self.current, self.start);
+        self.current = self.start;
+    }
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resize_averages_area() {
+        // 2x2 checker of opaque white and transparent pixels.
+        let pixels = [
+            255, 255, 255, 255, 0, 0, 0, 0, //
+            0, 0, 0, 0, 255, 255, 255, 255,
+        ];
+        assert_eq!(resize(&pixels, 2, 2, 1, 1), vec![128, 128, 128, 128]);
+        assert_eq!(resize(&pixels, 2, 2, 2, 2), pixels.to_vec());
+    }
+
+    #[test]
+    fn test_rasterize_square_coverage() {
+        let mut rasterizer = PathRasterizer::new(4, 4, Transform::default());
+        rasterizer.move_to(1.0, 1.0);
+        rasterizer.line_to(3.0, 1.0);
+        rasterizer.line_to(3.0, 3.5);
+        rasterizer.line_to(1.0, 3.5);
+        rasterizer.close();
+        let coverage = rasterizer.finish();
+
+        let row = |y: usize| coverage[y * 4..y * 4 + 4].to_vec();
+        assert_eq!(row(0), vec![0.0; 4]);
+        assert_eq!(row(1), vec![0.0, 1.0, 1.0, 0.0]);
+        assert_eq!(row(3), vec![0.0, 0.5, 0.5, 0.0]);
+    }
+
+    use crate::font_storage::tests::{font_data_with_tables, outline_tables, u16s, u32s};
+
+    const RED: u16 = 0;
+    const HALF_BLUE: u16 = 1;
+    const BLUE: u16 = 2;
+    const TEXT_COLOR: u16 = 0xFFFF;
+
+    /// Builds a face of five glyphs after `.notdef`, each an outline 500 units
+    /// wide and 700 high, with the palette above and `tables`.
+    fn color_face(tables: Vec<([u8; 4], Vec<u8>)>) -> (fontdue::Font, ColorFace) {
+        let mut cpal = Vec::new();
+        u16s(&mut cpal, &[0, 3, 1, 3]);
+        u32s(&mut cpal, &[14]);
+        u16s(&mut cpal, &[0]);
+        // Color records are BGRA.
+        cpal.extend([0, 0, 255, 255, 255, 0, 0, 128, 255, 0, 0, 255]);
+
+        let tables = outline_tables(6)
+            .into_iter()
+            .chain([(*b"CPAL", cpal)])
+            .chain(tables)
+            .collect();
+        let data = font_data_with_tables(&['a', 'b', 'c', 'd', 'e'], tables);
+        let font =
+            fontdue::Font::from_bytes(data.as_slice(), fontdue::FontSettings::default()).unwrap();
+        (font, ColorFace::parse(data.into(), 0).unwrap())
+    }
+
+    /// Builds a version 0 `COLR` table from base glyphs and their layers, given
+    /// as (glyph, palette index).
+    fn colr_v0(bases: &[(u16, &[(u16, u16)])]) -> ([u8; 4], Vec<u8>) {
+        let layer_count: usize = bases.iter().map(|(_, layers)| layers.len()).sum();
+        let mut colr = Vec::new();
+        u16s(&mut colr, &[0, bases.len() as u16]);
+        u32s(&mut colr, &[14, 14 + 6 * bases.len() as u32]);
+        u16s(&mut colr, &[layer_count as u16]);
+        let mut first_layer = 0;
+        for (glyph, layers) in bases {
+            u16s(&mut colr, &[*glyph, first_layer, layers.len() as u16]);
+            first_layer += layers.len() as u16;
+        }
+        for (glyph, palette) in bases.iter().flat_map(|(_, layers)| *layers) {
+            u16s(&mut colr, &[*glyph, *palette]);
+        }
+        (*b"COLR", colr)
+    }
+
+    /// Builds a version 1 `COLR` table from base glyphs and their paint graphs.
+    fn colr_v1(bases: Vec<(u16, Vec<u8>)>) -> ([u8; 4], Vec<u8>) {
+        let mut colr = Vec::new();
+        u16s(&mut colr, &[1, 0]);
+        u32s(&mut colr, &[0, 0]);
+        u16s(&mut colr, &[0]);
+        u32s(&mut colr, &[34, 0, 0, 0, 0]);
+
+        u32s(&mut colr, &[bases.len() as u32]);
+        let mut offset = 4 + 6 * bases.len();
+        for (glyph, paint) in &bases {
+            u16s(&mut colr, &[*glyph]);
+            u32s(&mut colr, &[offset as u32]);
+            offset += paint.len();
+        }
+        for (_, paint) in bases {
+            colr.extend(paint);
+        }
+        (*b"COLR", colr)
+    }
+
+    fn offset24(out: &mut Vec<u8>, offset: usize) {
+        out.extend(&(offset as u32).to_be_bytes()[1..]);
+    }
+
+    fn paint_solid(palette: u16) -> Vec<u8> {
+        let mut paint = vec![2];
+        u16s(&mut paint, &[palette, 0x4000]);
+        paint
+    }
+
+    fn paint_glyph(glyph: u16, child: Vec<u8>) -> Vec<u8> {
+        let mut paint = vec![10];
+        offset24(&mut paint, 6);
+        u16s(&mut paint, &[glyph]);
+        paint.extend(child);
+        paint
+    }
+
+    /// A linear (format 4) or radial (format 6) gradient from red to blue,
+    /// padded past its ends.
+    fn paint_gradient(format: u8, geometry: [i16; 6]) -> Vec<u8> {
+        let mut paint = vec![format];
+        offset24(&mut paint, 16);
+        u16s(&mut paint, &geometry.map(|value| value as u16));
+        paint.push(0);
+        u16s(&mut paint, &[2, 0, RED, 0x4000, 0x4000, BLUE, 0x4000]);
+        paint
+    }
+
+    fn paint_composite(source: Vec<u8>, mode: u8, backdrop: Vec<u8>) -> Vec<u8> {
+        let mut paint = vec![32];
+        offset24(&mut paint, 8);
+        paint.push(mode);
+        offset24(&mut paint, 8 + source.len());
+        paint.extend(source);
+        paint.extend(backdrop);
+        paint
+    }
+
+    /// Rasterizes a glyph and checks that every pixel is `expected`.
+    fn assert_filled(face: &ColorFace, font: &fontdue::Font, glyph: u16, expected: &[u8]) {
+        let (metrics, pixels, _) = face.rasterize(font, glyph, 10.0).unwrap();
+        assert_eq!(
+            pixels.len(),
+            metrics.width * metrics.height * expected.len()
+        );
+        for pixel in pixels.chunks_exact(expected.len()) {
+            assert_eq!(pixel, expected);
+        }
+    }
+
+    #[test]
+    fn test_colr_layers() {
+        let (font, face) = color_face(vec![colr_v0(&[
+            (1, &[(2, RED), (3, HALF_BLUE)]),
+            (4, &[(2, TEXT_COLOR)]),
+            (5, &[(2, RED), (3, TEXT_COLOR)]),
+        ])]);
+
+        // Layers cover the outlines of their glyphs: 5 × 7 pixels at 10px.
+        let (metrics, color) = face.metrics(&font, 1, 10.0).unwrap();
+        assert_eq!(
+            (metrics.xmin, metrics.ymin, metrics.width, metrics.height),
+            (0, 0, 5, 7)
+        );
+        assert_eq!((metrics.advance_width, color), (5.0, true));
+        // Later layers are drawn over earlier ones.
+        assert_filled(&face, &font, 1, &[127, 0, 128, 255]);
+
+        // Glyphs only drawn in the text color are coverage, to be tinted.
+        assert_eq!(face.metrics(&font, 4, 10.0), Some((metrics, false)));
+        assert_filled(&face, &font, 4, &[255]);
+        // Mixed with palette colors, the text color is drawn black.
+        assert_eq!(face.metrics(&font, 5, 10.0), Some((metrics, true)));
+        assert_filled(&face, &font, 5, &[0, 0, 0, 255]);
+
+        // Layer glyphs themselves are drawn from their outlines.
+        assert_eq!(face.metrics(&font, 2, 10.0), None);
+
+        // How a glyph is drawn is kept once per glyph, whatever the size.
+        face.metrics(&font, 1, 30.0).unwrap();
+        assert_eq!(face.glyphs.lock().len(), 4);
+    }
+
+    #[test]
+    fn test_colr_gradients() {
+        let (font, face) = color_face(vec![colr_v1(vec![
+            (1, paint_glyph(2, paint_gradient(4, [0, 0, 500, 0, 0, 700]))),
+            (
+                4,
+                paint_glyph(2, paint_gradient(6, [250, 350, 0, 250, 350, 250])),
+            ),
+        ])]);
+
+        // At 20px the glyph is 10 pixels wide, the gradient running along it.
+        let (metrics, pixels, color) = face.rasterize(&font, 1, 20.0).unwrap();
+        assert_eq!((metrics.width, metrics.height, color), (10, 14, true));
+        let pixel = |pixels: &[u8], col: usize, row: usize| {
+            let index = (row * metrics.width + col) * 4;
+            pixels[index..index + 4].to_vec()
+        };
+        for row in [0, 13] {
+            assert_eq!(pixel(&pixels, 0, row), vec![242, 0, 13, 255]);
+            assert_eq!(pixel(&pixels, 9, row), vec![13, 0, 242, 255]);
+        }
+
+        // The radial gradient grows from the center and is padded past its circle.
+        let (_, pixels, _) = face.rasterize(&font, 4, 20.0).unwrap();
+        assert_eq!(pixel(&pixels, 4, 6), vec![219, 0, 36, 255]);
+        assert_eq!(pixel(&pixels, 0, 0), vec![0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_colr_composite() {
+        const SCREEN: u8 = 13;
+        let (font, face) = color_face(vec![colr_v1(vec![(
+            1,
+            paint_composite(
+                paint_glyph(2, paint_solid(BLUE)),
+                SCREEN,
+                paint_glyph(3, paint_solid(RED)),
+            ),
+        )])]);
+
+        assert_filled(&face, &font, 1, &[255, 0, 255, 255]);
+    }
+
+    /// Encodes a PNG image of `size` × `size` pixels of one color.
+    fn png_image(size: u32, rgba: [u8; 4]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, size, size);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&rgba.repeat((size * size) as usize))
+            .unwrap();
+        writer.finish().unwrap();
+        data
+    }
+
+    /// Builds an `sbix` table with an image of glyph 1 in each strike, given
+    /// as (pixels per em, image).
+    fn sbix(strikes: &[(u16, Vec<u8>)]) -> ([u8; 4], Vec<u8>) {
+        const GLYPH_COUNT: usize = 6;
+        let mut sbix = Vec::new();
+        u16s(&mut sbix, &[1, 1]);
+        u32s(&mut sbix, &[strikes.len() as u32]);
+        let mut offset = 8 + 4 * strikes.len();
+        for (_, image) in strikes {
+            u32s(&mut sbix, &[offset as u32]);
+            offset += 4 + 4 * (GLYPH_COUNT + 1) + 8 + image.len();
+        }
+        for (pixels_per_em, image) in strikes {
+            u16s(&mut sbix, &[*pixels_per_em, 72]);
+            let start = 4 + 4 * (GLYPH_COUNT + 1);
+            let end = start + 8 + image.len();
+            u32s(&mut sbix, &[start as u32, start as u32]);
+            u32s(&mut sbix, &[end as u32; GLYPH_COUNT - 1]);
+            // The image starts 1 pixel right of the origin and 1 below the baseline.
+            u16s(&mut sbix, &[1, (-1i16) as u16]);
+            sbix.extend(b"png ");
+            sbix.extend(image);
+        }
+        (*b"sbix", sbix)
+    }
+
+    #[test]
+    fn test_bitmap_strikes() {
+        let red = png_image(2, [255, 0, 0, 255]);
+        let blue = png_image(4, [0, 0, 255, 255]);
+        let (font, face) = color_face(vec![sbix(&[(20, red), (40, blue)])]);
+
+        let image = |font_size| {
+            let (metrics, pixels, color) = face.rasterize(&font, 1, font_size).unwrap();
+            assert!(color);
+            (
+                metrics.xmin,
+                metrics.ymin,
+                metrics.width,
+                metrics.height,
+                pixels[..4].to_vec(),
+            )
+        };
+        let red = vec![255, 0, 0, 255];
+        let blue = vec![0, 0, 255, 255];
+
+        // The strike of the size is used as is.
+        assert_eq!(image(20.0), (1, -1, 2, 2, red.clone()));
+        assert_eq!(image(40.0), (1, -1, 4, 4, blue.clone()));
+        // Other sizes scale the smallest strike at least as large, or the largest one.
+        assert_eq!(image(10.0), (1, -1, 1, 1, red));
+        assert_eq!(image(30.0), (1, -1, 3, 3, blue.clone()));
+        assert_eq!(image(80.0), (2, -2, 8, 8, blue));
+
+        // Glyphs missing from the strikes are drawn from their outlines.
+        assert_eq!(face.metrics(&font, 2, 20.0), None);
+    }
+}
@@ -3,7 +3,7 @@ pub mod cpu_renderer;
 /// Hardware-agnostic GPU renderer.
 pub mod gpu_renderer;
 
-pub use cpu_renderer::{CpuCacheConfig, CpuRenderer};
+pub use cpu_renderer::{CpuCacheConfig, CpuPixel, CpuRenderer};
 pub use gpu_renderer::{AtlasUpdate, GlyphInstance, GpuCacheConfig, GpuRenderer, StandaloneGlyph};
 
 #[cfg(feature = "wgpu")]
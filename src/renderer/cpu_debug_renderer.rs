@@ -52,7 +52,8 @@ impl CpuDebugRenderer {
             font_storage,
             &mut |updates: &[AtlasUpdate]| {
                 let mut atlases = self.atlases.borrow_mut();
-                for update in updates {
+                // Only the coverage atlases are emulated; color glyphs are skipped.
+                for update in updates.iter().filter(|update| !update.color) {
                     let atlas = &mut atlases[update.texture_index];
                     let atlas_width = self.atlas_configs[update.texture_index].texture_size.get();
 
@@ -72,7 +73,7 @@ impl CpuDebugRenderer {
             &mut |instances: &[GlyphInstance<T>]| {
                 let mut target_buffer = target_cell.borrow_mut();
                 let atlases = self.atlases.borrow();
-                for instance in instances {
+                for instance in instances.iter().filter(|instance| !instance.color) {
                     let color: [f32; 4] = instance.user_data.into();
                     let atlas = &atlases[instance.texture_index];
                     let atlas_width = self.atlas_configs[instance.texture_index]
@@ -151,6 +152,9 @@ impl CpuDebugRenderer {
                 }
             },
             &mut |standalone: &StandaloneGlyph<T>| {
+                if standalone.color {
+                    return;
+                }
                 let mut target_buffer = target_cell.borrow_mut();
                 let color: [f32; 4] = standalone.user_data.into();
                 let src_w = standalone.width;
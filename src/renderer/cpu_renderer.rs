@@ -1,9 +1,28 @@
 use crate::font_storage::FontStorage;
-use crate::text::{GlyphPosition, TextLayout};
+use crate::text::{DecorationPosition, GlyphPosition, TextLayout};
 
 mod glyph_cache;
 pub use glyph_cache::{CpuCache, CpuCacheConfig, CpuCacheItem};
 
+/// A pixel passed to the callback of [`CpuRenderer::render_color`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuPixel {
+    /// Coverage of an outline glyph or a decoration line, usually tinted with the user data.
+    Coverage(u8),
+    /// Premultiplied RGBA of a color glyph (emoji), to be drawn without tinting.
+    Color([u8; 4]),
+}
+
+impl CpuPixel {
+    /// Returns the coverage, or the alpha of a color pixel.
+    pub fn alpha(self) -> u8 {
+        match self {
+            Self::Coverage(alpha) => alpha,
+            Self::Color([_, _, _, alpha]) => alpha,
+        }
+    }
+}
+
 /// CPU-based text renderer.
 ///
 /// ## Overview
@@ -85,12 +104,33 @@ impl CpuRenderer {
     }
 
     /// Renders the provided [`TextLayout`] by calling the closure for each pixel.
+    ///
+    /// Decoration lines are drawn as solid rectangles, with partial coverage on
+    /// their edges. Color glyphs are reduced to their alpha; use
+    /// [`Self::render_color`] to receive their colors.
     pub fn render<T>(
         &mut self,
         layout: &TextLayout<T>,
         image_size: [usize; 2],
         font_storage: &mut FontStorage,
         f: &mut dyn FnMut([usize; 2], u8, &T),
+    ) {
+        self.render_color(
+            layout,
+            image_size,
+            font_storage,
+            &mut |pos, pixel, user_data| f(pos, pixel.alpha(), user_data),
+        );
+    }
+
+    /// Renders the provided [`TextLayout`] like [`Self::render`], passing the
+    /// pixels of color glyphs as [`CpuPixel::Color`].
+    pub fn render_color<T>(
+        &mut self,
+        layout: &TextLayout<T>,
+        image_size: [usize; 2],
+        font_storage: &mut FontStorage,
+        f: &mut dyn FnMut([usize; 2], CpuPixel, &T),
     ) {
         let width = image_size[0];
         let height = image_size[1];
@@ -103,33 +143,64 @@ impl CpuRenderer {
             if line.bottom <= 0.0 || line.top >= height as f32 {
                 continue;
             }
+            for decoration in &line.decorations {
+                Self::render_decoration(decoration, image_size, f);
+            }
             for glyph in &line.glyphs {
                 self.render_glyph(glyph, font_storage, image_size, f);
             }
         }
     }
 
+    fn render_decoration<T>(
+        decoration: &DecorationPosition<T>,
+        image_size: [usize; 2],
+        f: &mut dyn FnMut([usize; 2], CpuPixel, &T),
+    ) {
+        let left = decoration.x.max(0.0);
+        let top = decoration.y.max(0.0);
+        let right = (decoration.x + decoration.width).min(image_size[0] as f32);
+        let bottom = (decoration.y + decoration.height).min(image_size[1] as f32);
+        if left >= right || top >= bottom {
+            return;
+        }
+
+        for iy in top.floor() as usize..bottom.ceil() as usize {
+            let coverage_y = bottom.min(iy as f32 + 1.0) - top.max(iy as f32);
+            for ix in left.floor() as usize..right.ceil() as usize {
+                let coverage_x = right.min(ix as f32 + 1.0) - left.max(ix as f32);
+                let alpha = (coverage_x * coverage_y * 255.0).round() as u8;
+                if alpha == 0 {
+                    continue;
+                }
+
+                f([ix, iy], CpuPixel::Coverage(alpha), &decoration.user_data);
+            }
+        }
+    }
+
     fn render_glyph<T>(
         &mut self,
         glyph_pos: &GlyphPosition<T>,
         font_storage: &mut FontStorage,
         image_size: [usize; 2],
-        f: &mut dyn FnMut([usize; 2], u8, &T),
+        f: &mut dyn FnMut([usize; 2], CpuPixel, &T),
     ) {
         let cached = match self.cache.get(&glyph_pos.glyph_id, font_storage) {
             Some(cached) => cached,
             None => {
-                let Some(font) = font_storage.font(glyph_pos.glyph_id.font_id()) else {
+                let glyph_id = &glyph_pos.glyph_id;
+                let Some((_, color)) = font_storage.glyph_metrics(glyph_id) else {
+                    return;
+                };
+                let Some((metrics, bitmap)) = font_storage.rasterize_glyph(glyph_id) else {
                     return;
                 };
-                let (metrics, bitmap) = font.rasterize_indexed(
-                    glyph_pos.glyph_id.glyph_index(),
-                    glyph_pos.glyph_id.font_size(),
-                );
                 CpuCacheItem {
                     width: metrics.width,
                     height: metrics.height,
                     data: std::borrow::Cow::Owned(bitmap),
+                    color,
                 }
             }
         };
@@ -154,8 +225,16 @@ impl CpuRenderer {
             }
 
             for col in 0..glyph_width {
-                let src_alpha = cached.data[row * glyph_width + col];
-                if src_alpha == 0 {
+                let index = row * glyph_width + col;
+                let pixel = if cached.color {
+                    let Some(rgba) = cached.data.get(index * 4..index * 4 + 4) else {
+                        continue;
+                    };
+                    CpuPixel::Color([rgba[0], rgba[1], rgba[2], rgba[3]])
+                } else {
+                    CpuPixel::Coverage(cached.data[index])
+                };
+                if pixel.alpha() == 0 {
                     continue;
                 }
 
@@ -171,7 +250,7 @@ impl CpuRenderer {
 
                 // Use the shared accumulate method which handles bounds checking (again) and saturation.
                 // Double bounds checking is acceptable here for code reuse and safety.
-                f([ix as usize, iy as usize], src_alpha, &glyph_pos.user_data);
+                f([ix as usize, iy as usize], pixel, &glyph_pos.user_data);
             }
         }
     }
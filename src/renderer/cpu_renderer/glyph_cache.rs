@@ -196,7 +196,12 @@ pub struct CpuCacheItem<'a> {
     /// Height of the glyph bitmap.
     pub height: usize,
     /// The bitmap data.
+    ///
+    /// One coverage byte per pixel, or four bytes of premultiplied RGBA per
+    /// pixel if `color` is set.
     pub data: Cow<'a, [u8]>,
+    /// Whether the glyph is a color image (emoji) rather than a coverage mask.
+    pub color: bool,
 }
 
 /// Configuration for the CPU glyph cache.
@@ -204,7 +209,8 @@ pub struct CpuCacheItem<'a> {
 pub struct CpuCacheConfig {
     /// Size of the memory block for caching.
     ///
-    /// This specifies the total number of bytes for the glyph bitmap (`width * height`).
+    /// This specifies the total number of bytes for the glyph bitmap (`width * height`,
+    /// or `width * height * 4` for color glyphs).
     pub block_size: NonZeroUsize,
     /// Maximum number of blocks to cache.
     pub capacity: NonZeroUsize,
@@ -246,13 +252,9 @@ impl CpuCache {
         glyph_id: &GlyphId,
         font_storage: &mut FontStorage,
     ) -> Option<CpuCacheItem<'_>> {
-        let glyph_index = glyph_id.glyph_index();
-        let font_size = glyph_id.font_size();
-        let font_id = glyph_id.font_id();
-
-        let font = font_storage.font(font_id)?;
-        let glyph_metrics = font.metrics_indexed(glyph_index, font_size);
-        let glyph_bitmap_size = glyph_metrics.width * glyph_metrics.height;
+        let (glyph_metrics, color) = font_storage.glyph_metrics(glyph_id)?;
+        let bytes_per_pixel = if color { 4 } else { 1 };
+        let glyph_bitmap_size = glyph_metrics.width * glyph_metrics.height * bytes_per_pixel;
 
         let cache = self
             .caches
@@ -260,14 +262,17 @@ impl CpuCache {
             .find(|cache| cache.block_size >= glyph_bitmap_size)?;
 
         let data = cache.get_or_insert_with(glyph_id, || {
-            let bitmap = font.rasterize_indexed(glyph_index, font_size);
-            bitmap.1
+            font_storage
+                .rasterize_glyph(glyph_id)
+                .map(|bitmap| bitmap.1)
+                .unwrap_or_default()
         });
 
         Some(CpuCacheItem {
             width: glyph_metrics.width,
             height: glyph_metrics.height,
             data: Cow::Borrowed(data),
+            color,
         })
     }
 }
@@ -31,9 +31,9 @@ pub fn render_layout_to_bitmap<T>(
 
 /// Renders a single glyph into the target bitmap.
 ///
-/// The glyph is rasterized using `fontdue` at the size encoded in the
-/// `GlyphId`. Coverage values are added to the existing pixel contents and
-/// clamped to 255 to keep the bitmap valid.
+/// The glyph is rasterized with [`GlyphId::rasterize`](crate::GlyphId::rasterize)
+/// at the size encoded in the `GlyphId`. Coverage values are added to the
+/// existing pixel contents and clamped to 255 to keep the bitmap valid.
 fn render_glyph_into_bitmap<T>(
     bitmap: &mut Bitmap,
     glyph_pos: &GlyphPosition<T>,
@@ -41,14 +41,12 @@ fn render_glyph_into_bitmap<T>(
 ) {
     let glyph_id = glyph_pos.glyph_id;
     let font_id = glyph_id.font_id();
-    let glyph_index = glyph_id.glyph_index();
-    let font_size = glyph_id.font_size();
 
     let Some(font) = font_storage.font(font_id) else {
         return;
     };
 
-    let (metrics, coverage) = font.rasterize_indexed(glyph_index, font_size);
+    let (metrics, coverage) = glyph_id.rasterize(&font);
 
     if metrics.width == 0 || metrics.height == 0 {
         return;
@@ -21,7 +21,12 @@ pub struct AtlasUpdate {
     /// Height of the update region.
     pub height: usize,
     /// Bitmap data to upload (row-major).
+    ///
+    /// One coverage byte per pixel, or four bytes of premultiplied RGBA per
+    /// pixel if `color` is set.
     pub pixels: Vec<u8>,
+    /// Whether the update targets the color atlas rather than the coverage atlas.
+    pub color: bool,
 }
 
 /// Describes a glyph instance to be drawn.
@@ -34,6 +39,11 @@ pub struct GlyphInstance<T> {
     pub screen_rect: Box2D<f32, euclid::UnknownUnit>,
     /// User data associated with this glyph.
     pub user_data: T,
+    /// Whether the glyph is sampled from the color atlas.
+    ///
+    /// Color glyphs (emoji) carry their own colors and should not be tinted
+    /// with `user_data`.
+    pub color: bool,
 }
 
 /// Describes a standalone large glyph to be drawn separately.
@@ -42,12 +52,14 @@ pub struct StandaloneGlyph<T> {
     pub width: usize,
     /// Height of the glyph image.
     pub height: usize,
-    /// Bitmap data of the glyph.
+    /// Bitmap data of the glyph, laid out like [`AtlasUpdate::pixels`].
     pub pixels: Vec<u8>,
     /// Screen coordinates where the glyph should be drawn.
     pub screen_rect: Box2D<f32, euclid::UnknownUnit>,
     /// User data associated with this glyph.
     pub user_data: T,
+    /// Whether `pixels` is premultiplied RGBA of a color glyph.
+    pub color: bool,
 }
 
 /// Generic GPU renderer that manages an atlas and produces draw commands.
@@ -60,6 +72,13 @@ pub struct StandaloneGlyph<T> {
 /// 1.  **Atlas Management**: Packing glyphs into texture atlases efficiently.
 /// 2.  **Quad Generation**: Calculating vertices and UV coordinates for each glyph.
 ///
+/// Color glyphs (emoji) are packed into a second set of atlases with the same
+/// configuration, holding RGBA instead of coverage. Updates and instances for
+/// them have their `color` flag set.
+///
+/// Decoration lines are drawn as coverage instances sampling a single opaque
+/// texel, kept in the first tile of the first atlas with more than one tile.
+///
 /// It **does not** issue actual draw calls or manage GPU resources directly (buffers, textures).
 /// Instead, it invokes callbacks provided by the user to perform these actions.
 /// This allows it to be used with any graphics backend (WGPU, OpenGL, Vulkan, DirectX, etc.).
@@ -116,19 +135,31 @@ pub struct StandaloneGlyph<T> {
 /// ```
 pub struct GpuRenderer {
     cache: GpuCache,
+    color_cache: GpuCache,
+    /// Opaque texel sampled by decoration lines.
+    solid_texel: Option<GpuCacheItem>,
+    /// Whether the opaque texel has been uploaded since the cache was cleared.
+    solid_texel_uploaded: bool,
 }
 
 impl GpuRenderer {
     /// Creates a new GPU renderer with the provided cache configuration.
     pub fn new(configs: &[GpuCacheConfig]) -> Self {
+        let mut cache = GpuCache::new(configs);
+        let solid_texel = cache.reserve_solid_texel();
         Self {
-            cache: GpuCache::new(configs),
+            cache,
+            color_cache: GpuCache::new(configs),
+            solid_texel,
+            solid_texel_uploaded: false,
         }
     }
 
     /// Clears the cache.
     pub fn clear_cache(&mut self) {
         self.cache.clear();
+        self.color_cache.clear();
+        self.solid_texel_uploaded = false;
     }
 
     /// Renders the layout, producing atlas updates and draw calls via callbacks.
@@ -162,6 +193,10 @@ impl GpuRenderer {
 
     /// Renders the layout, producing atlas updates and draw calls via callbacks.
     ///
+    /// Decoration lines are drawn as instances of an opaque texel. Only when no
+    /// atlas has a tile to spare for it are they passed to `draw_standalone`,
+    /// as fully opaque bitmaps covering their rectangle.
+    ///
     /// This method allows callbacks to return errors, which will be propagated.
     pub fn try_render<T: Clone + Copy, E>(
         &mut self,
@@ -175,17 +210,67 @@ impl GpuRenderer {
         let mut instance_list: Vec<GlyphInstance<T>> = Vec::new();
 
         for line in &layout.lines {
+            for decoration in &line.decorations {
+                let screen_rect = Box2D::new(
+                    Point2D::new(decoration.x, decoration.y),
+                    Point2D::new(
+                        decoration.x + decoration.width,
+                        decoration.y + decoration.height,
+                    ),
+                );
+                let Some(solid_texel) = &self.solid_texel else {
+                    let width = decoration.width.ceil().max(1.0) as usize;
+                    let height = decoration.height.ceil().max(1.0) as usize;
+                    draw_standalone(&StandaloneGlyph {
+                        width,
+                        height,
+                        pixels: vec![255; width * height],
+                        screen_rect,
+                        user_data: decoration.user_data,
+                        color: false,
+                    })?;
+                    continue;
+                };
+
+                if !self.solid_texel_uploaded {
+                    update_atlas_list.push(AtlasUpdate {
+                        texture_index: solid_texel.texture_index,
+                        x: solid_texel.glyph_box.min.x,
+                        y: solid_texel.glyph_box.min.y,
+                        width: 1,
+                        height: 1,
+                        pixels: vec![255],
+                        color: false,
+                    });
+                    self.solid_texel_uploaded = true;
+                }
+                // Every fragment samples the center of the texel.
+                let center = solid_texel.glyph_uv().center();
+                instance_list.push(GlyphInstance {
+                    texture_index: solid_texel.texture_index,
+                    uv_rect: Box2D::new(center, center),
+                    screen_rect,
+                    user_data: decoration.user_data,
+                    color: false,
+                });
+            }
+
             'glyph_loop: for glyph in &line.glyphs {
                 let GlyphPosition::<T> {
                     glyph_id,
                     x,
                     y,
                     user_data,
+                    ..
                 } = glyph;
-                let Some(font) = font_storage.font(glyph_id.font_id()) else {
+                let Some((metrics, color)) = font_storage.glyph_metrics(glyph_id) else {
                     continue 'glyph_loop;
                 };
-                let metrics = font.metrics_indexed(glyph_id.glyph_index(), glyph_id.font_size());
+                let cache = if color {
+                    &mut self.color_cache
+                } else {
+                    &mut self.cache
+                };
 
                 let (
                     GpuCacheItem {
@@ -194,7 +279,7 @@ impl GpuRenderer {
                         glyph_box,
                     },
                     get_or_push_result,
-                ) = match self.cache.get_or_push_and_protect(glyph_id, font_storage) {
+                ) = match cache.get_or_push_and_protect(glyph_id, font_storage) {
                     Some(glyph_cache_item) => glyph_cache_item,
                     None => {
                         // upload all new glyph data to atlas
@@ -209,12 +294,22 @@ impl GpuRenderer {
                             instance_list.clear();
                         }
 
+                        // Instances of both atlases were drawn.
                         self.cache.new_batch();
+                        self.color_cache.new_batch();
+                        let cache = if color {
+                            &mut self.color_cache
+                        } else {
+                            &mut self.cache
+                        };
                         let Some(glyph_cache_item) =
-                            self.cache.get_or_push_and_protect(glyph_id, font_storage)
+                            cache.get_or_push_and_protect(glyph_id, font_storage)
                         else {
-                            let (metrics, glyph_data) = font
-                                .rasterize_indexed(glyph_id.glyph_index(), glyph_id.font_size());
+                            let Some((metrics, glyph_data)) =
+                                font_storage.rasterize_glyph(glyph_id)
+                            else {
+                                continue 'glyph_loop;
+                            };
 
                             let isolate = StandaloneGlyph {
                                 width: metrics.width,
@@ -228,6 +323,7 @@ impl GpuRenderer {
                                     ),
                                 ),
                                 user_data: *user_data,
+                                color,
                             };
 
                             draw_standalone(&isolate)?;
@@ -260,13 +356,16 @@ impl GpuRenderer {
                     uv_rect,
                     screen_rect,
                     user_data: *user_data,
+                    color,
                 };
 
                 instance_list.push(glyph_instance);
 
                 if let glyph_cache::GetOrPushResult::NeedToUpload = get_or_push_result {
-                    let (_, glyph_data) =
-                        font.rasterize_indexed(glyph_id.glyph_index(), glyph_id.font_size());
+                    let glyph_data = match font_storage.rasterize_glyph(glyph_id) {
+                        Some((_, glyph_data)) => glyph_data,
+                        None => vec![0; glyph_box.area() * if color { 4 } else { 1 }],
+                    };
 
                     update_atlas_list.push(AtlasUpdate {
                         texture_index,
@@ -275,6 +374,7 @@ impl GpuRenderer {
                         width: glyph_box.width(),
                         height: glyph_box.height(),
                         pixels: glyph_data,
+                        color,
                     });
                 }
             }
@@ -291,3 +391,88 @@ impl GpuRenderer {
         Ok(())
     }
 }
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::text::{
+        DecorationKind, DecorationPosition, LineBreakKind, TextLayoutConfig, TextLayoutLine,
+    };
+
+    fn underlined(width: f32) -> TextLayout<[f32; 4]> {
+        TextLayout {
+            config: TextLayoutConfig::default(),
+            total_width: width,
+            total_height: 20.0,
+            lines: vec![TextLayoutLine {
+                line_height: 20.0,
+                line_width: width,
+                top: 0.0,
+                bottom: 20.0,
+                left: 0.0,
+                right: width,
+                source: Default::default()..Default::default(),
+                break_kind: LineBreakKind::EndOfText,
+                glyphs: Vec::new(),
+                clusters: Vec::new(),
+                objects: Vec::new(),
+                decorations: vec![DecorationPosition {
+                    kind: DecorationKind::Underline,
+                    element: 0,
+                    x: 5.0,
+                    y: 16.0,
+                    width,
+                    height: 1.5,
+                    user_data: [1.0; 4],
+                }],
+            }],
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn test_decorations_as_instances() {
+        let mut renderer = GpuRenderer::new(&[GpuCacheConfig {
+            texture_size: NonZeroUsize::new(64).unwrap(),
+            tile_size: NonZeroUsize::new(16).unwrap(),
+            tiles_per_axis: NonZeroUsize::new(4).unwrap(),
+        }]);
+        let mut font_storage = FontStorage::new();
+
+        // Wider than any texture: it still takes a single instance.
+        for (width, uploads) in [(100_000.0, 1), (10.0, 0)] {
+            let mut updates = Vec::new();
+            let mut instances = Vec::new();
+            renderer.render(
+                &underlined(width),
+                &mut font_storage,
+                |batch| {
+                    updates.extend(
+                        batch
+                            .iter()
+                            .map(|u| (u.x, u.y, u.width, u.height, u.pixels.clone())),
+                    )
+                },
+                |batch| instances.extend(batch.iter().map(|i| (i.uv_rect, i.screen_rect))),
+                |_| panic!("decorations are not drawn standalone"),
+            );
+
+            // The opaque texel is uploaded once, and sampled at its center.
+            assert_eq!(updates.len(), uploads);
+            if uploads > 0 {
+                assert_eq!(updates[0], (0, 0, 1, 1, vec![255]));
+            }
+            let center = Point2D::new(0.5 / 64.0, 0.5 / 64.0);
+            assert_eq!(
+                instances,
+                vec![(
+                    Box2D::new(center, center),
+                    Box2D::new(Point2D::new(5.0, 16.0), Point2D::new(5.0 + width, 17.5)),
+                )]
+            );
+        }
+    }
+}
@@ -7,6 +7,14 @@ use crate::glyph_id::GlyphId;
 
 const ATLAS_MARGIN: usize = 2;
 
+/// Returns the metrics of the bitmap uploaded for a glyph: its color image if
+/// it has one, its coverage mask otherwise.
+fn glyph_metrics(glyph_id: &GlyphId, font_storage: &mut FontStorage) -> Option<fontdue::Metrics> {
+    font_storage
+        .glyph_metrics(glyph_id)
+        .map(|(metrics, _)| metrics)
+}
+
 /// protect `push_front`, `move_to_front` and `attach_to_head` from incorrect usage.
 mod cache_state {
     use super::*;
@@ -27,6 +35,8 @@ mod cache_state {
         lru_tail: Option<usize>,
         lru_map: HashMap<GlyphId, usize, fxhash::FxBuildHasher>,
         lru_empties: Vec<usize>,
+        /// Number of leading slots kept out of the LRU.
+        reserved: usize,
 
         current_batch_id: usize,
     }
@@ -44,14 +54,28 @@ mod cache_state {
                     fxhash::FxBuildHasher::default(),
                 ),
                 lru_empties: (0..capacity).collect(),
+                reserved: 0,
                 current_batch_id: 0,
             }
         }
 
+        /// Keeps the first slot out of the LRU for good, leaving at least one
+        /// slot for glyphs. Returns its index.
+        pub fn reserve_first_slot(&mut self) -> Option<usize> {
+            if self.reserved == 0 {
+                if self.capacity < 2 || !self.lru_map.is_empty() {
+                    return None;
+                }
+                self.reserved = 1;
+                self.lru_empties.retain(|&index| index != 0);
+            }
+            Some(0)
+        }
+
         pub fn clear(&mut self) {
             self.lru_map.clear();
             self.lru_empties.clear();
-            self.lru_empties.extend(0..self.capacity);
+            self.lru_empties.extend(self.reserved..self.capacity);
             self.lru_head = None;
             self.lru_tail = None;
             self.current_batch_id = 0;
@@ -379,6 +403,22 @@ impl CacheAtlas {
     }
 }
 
+/// Reserves a 1×1 texel in the first tile of the first atlas holding more
+/// than one tile, for [`GpuCache::reserve_solid_texel`].
+fn reserve_solid_texel(caches: &mut [CacheAtlas]) -> Option<GpuCacheItem> {
+    caches
+        .iter_mut()
+        .enumerate()
+        .find_map(|(texture_index, cache)| {
+            cache.cache_state.reserve_first_slot()?;
+            Some(GpuCacheItem {
+                texture_index,
+                texture_size: cache.texture_size,
+                glyph_box: Box2D::new(Point2D::new(0, 0), Point2D::new(1, 1)),
+            })
+        })
+}
+
 /// Information about a cached glyph.
 pub struct GpuCacheItem {
     /// Index of the texture in the atlas array.
@@ -451,17 +491,16 @@ impl FixedGpuCache {
         }
     }
 
+    fn reserve_solid_texel(&mut self) -> Option<GpuCacheItem> {
+        reserve_solid_texel(&mut self.caches)
+    }
+
     fn get_or_push_and_protect(
         &mut self,
         glyph_id: &GlyphId,
         font_storage: &mut FontStorage,
     ) -> Option<(GpuCacheItem, GetOrPushResult)> {
-        let glyph_index = glyph_id.glyph_index();
-        let font_size = glyph_id.font_size();
-        let font_id = glyph_id.font_id();
-
-        let font = font_storage.font(font_id)?;
-        let glyph_metrics = font.metrics_indexed(glyph_index, font_size);
+        let glyph_metrics = glyph_metrics(glyph_id, font_storage)?;
         let glyph_bitmap_size = glyph_metrics.width.max(glyph_metrics.height) + ATLAS_MARGIN;
 
         let cache_index = self
@@ -493,12 +532,7 @@ impl FixedGpuCache {
         glyph_id: &GlyphId,
         font_storage: &mut FontStorage,
     ) -> Option<GpuCacheItem> {
-        let glyph_index = glyph_id.glyph_index();
-        let font_size = glyph_id.font_size();
-        let font_id = glyph_id.font_id();
-
-        let font = font_storage.font(font_id)?;
-        let glyph_metrics = font.metrics_indexed(glyph_index, font_size);
+        let glyph_metrics = glyph_metrics(glyph_id, font_storage)?;
         let glyph_bitmap_size = glyph_metrics.width.max(glyph_metrics.height) + ATLAS_MARGIN;
 
         let cache_index = self
@@ -527,12 +561,7 @@ impl FixedGpuCache {
         glyph_id: &GlyphId,
         font_storage: &mut FontStorage,
     ) -> Option<GpuCacheItem> {
-        let glyph_index = glyph_id.glyph_index();
-        let font_size = glyph_id.font_size();
-        let font_id = glyph_id.font_id();
-
-        let font = font_storage.font(font_id)?;
-        let glyph_metrics = font.metrics_indexed(glyph_index, font_size);
+        let glyph_metrics = glyph_metrics(glyph_id, font_storage)?;
         let glyph_bitmap_size = glyph_metrics.width.max(glyph_metrics.height) + ATLAS_MARGIN;
 
         let cache_index = self
@@ -585,17 +614,16 @@ impl FallbackGpuCache {
         }
     }
 
+    fn reserve_solid_texel(&mut self) -> Option<GpuCacheItem> {
+        reserve_solid_texel(&mut self.caches)
+    }
+
     fn get_or_push_and_protect(
         &mut self,
         glyph_id: &GlyphId,
         font_storage: &mut FontStorage,
     ) -> Option<(GpuCacheItem, GetOrPushResult)> {
-        let glyph_index = glyph_id.glyph_index();
-        let font_size = glyph_id.font_size();
-        let font_id = glyph_id.font_id();
-
-        let font = font_storage.font(font_id)?;
-        let glyph_metrics = font.metrics_indexed(glyph_index, font_size);
+        let glyph_metrics = glyph_metrics(glyph_id, font_storage)?;
         let glyph_bitmap_size = glyph_metrics.width.max(glyph_metrics.height) + ATLAS_MARGIN;
 
         let start_index = self
@@ -662,12 +690,7 @@ impl FallbackGpuCache {
         glyph_id: &GlyphId,
         font_storage: &mut FontStorage,
     ) -> Option<GpuCacheItem> {
-        let glyph_index = glyph_id.glyph_index();
-        let font_size = glyph_id.font_size();
-        let font_id = glyph_id.font_id();
-
-        let font = font_storage.font(font_id)?;
-        let glyph_metrics = font.metrics_indexed(glyph_index, font_size);
+        let glyph_metrics = glyph_metrics(glyph_id, font_storage)?;
         let glyph_bitmap_size = glyph_metrics.width.max(glyph_metrics.height) + ATLAS_MARGIN;
 
         let start_index = self
@@ -700,12 +723,7 @@ impl FallbackGpuCache {
         glyph_id: &GlyphId,
         font_storage: &mut FontStorage,
     ) -> Option<GpuCacheItem> {
-        let glyph_index = glyph_id.glyph_index();
-        let font_size = glyph_id.font_size();
-        let font_id = glyph_id.font_id();
-
-        let font = font_storage.font(font_id)?;
-        let glyph_metrics = font.metrics_indexed(glyph_index, font_size);
+        let glyph_metrics = glyph_metrics(glyph_id, font_storage)?;
         let glyph_bitmap_size = glyph_metrics.width.max(glyph_metrics.height) + ATLAS_MARGIN;
 
         let start_index = self
@@ -775,6 +793,19 @@ impl GpuCache {
         }
     }
 
+    /// Reserves a texel that glyphs never use, for drawing solid rectangles.
+    ///
+    /// The texel is taken from a tile of the first atlas with more than one
+    /// tile, which is kept out of the cache from then on, even after
+    /// [`Self::clear`]. Calling this again returns the same texel. `None` is
+    /// returned when glyphs were cached already or no atlas can spare a tile.
+    pub fn reserve_solid_texel(&mut self) -> Option<GpuCacheItem> {
+        match self {
+            Self::Fixed(c) => c.reserve_solid_texel(),
+            Self::Fallback(c) => c.reserve_solid_texel(),
+        }
+    }
+
     /// Gets existing or adds new glyph, marking it used.
     pub fn get_or_push_and_protect(
         &mut self,
@@ -83,6 +83,9 @@ struct Globals {
 ///
 /// It supports **Premultiplied Alpha** blending for correct color composition.
 ///
+/// Color glyphs (emoji) are kept in a separate `Rgba8Unorm` atlas array, created
+/// the first time one is drawn, and are not tinted with the user data color.
+///
 /// ## Integration
 ///
 /// This component can be used in two ways:
@@ -144,6 +147,8 @@ struct Globals {
 ///   - Example: 50% transparent white should be `[0.5, 0.5, 0.5, 0.5]`, NOT `[1.0, 1.0, 1.0, 0.5]`.
 /// - **Compositing**: The renderer performs standard usage of the alpha masking from the font atlas.
 ///   It applies the mask to the input color. The pipeline is configured with `PREMULTIPLIED_ALPHA_BLENDING`.
+/// - **Color Glyphs**: Only the alpha of the input color is used, as an opacity
+///   applied to the colors of the glyph image.
 ///
 /// # Performance Optimizations
 ///
@@ -186,10 +191,12 @@ pub struct WgpuRenderer {
 /// to update resources (like buffers and caches) while retaining an immutable interface
 /// where possible, or satisfying the borrowing rules of helper methods.
 struct WgpuResources {
-    /// Cache of pipelines for different texture formats (e.g., specific swapchain formats).
-    pipelines: std::cell::RefCell<HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>>,
+    /// Cache of pipelines for different texture formats (e.g., specific swapchain formats),
+    /// for coverage (`false`) and color (`true`) glyphs.
+    pipelines: std::cell::RefCell<HashMap<(wgpu::TextureFormat, bool), wgpu::RenderPipeline>>,
     /// Cache of pipelines for standalone large glyphs.
-    standalone_pipelines: std::cell::RefCell<HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>>,
+    standalone_pipelines:
+        std::cell::RefCell<HashMap<(wgpu::TextureFormat, bool), wgpu::RenderPipeline>>,
 
     pipeline_layout: wgpu::PipelineLayout,
     standalone_pipeline_layout: wgpu::PipelineLayout,
@@ -198,12 +205,14 @@ struct WgpuResources {
 
     /// The texture atlas array used for caching small glyphs.
     atlas_texture: wgpu::Texture,
+    /// The RGBA atlas array for color glyphs, created when the first one is drawn.
+    color_atlas: std::cell::RefCell<Option<ColorAtlas>>,
     sampler: wgpu::Sampler,
 
     /// Shared instance buffer for drawing glyph quads. Resizes automatically.
     instance_buffer: std::cell::RefCell<wgpu::Buffer>,
 
-    _bind_group_layout: wgpu::BindGroupLayout,
+    bind_group_layout: wgpu::BindGroupLayout,
     standalone_bind_group_layout: wgpu::BindGroupLayout,
 
     /// Uniform buffer for global data (screen size, etc.).
@@ -212,6 +221,8 @@ struct WgpuResources {
 
     /// Resources for drawing a single large glyph that doesn't fit in the atlas.
     standalone_resources: std::cell::RefCell<Option<StandaloneResources>>,
+    /// Same as `standalone_resources`, for color glyphs.
+    color_standalone_resources: std::cell::RefCell<Option<StandaloneResources>>,
 
     /// **Staging Vector for Instance Data**
     /// Reused across frames to avoid repeated allocations (`Vec::new()`) when building instance data.
@@ -222,6 +233,12 @@ struct WgpuResources {
     pixel_staging: std::cell::RefCell<Vec<u8>>,
 }
 
+/// The atlas array holding color glyphs, laid out like the coverage atlas.
+struct ColorAtlas {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
 /// Resources required for rendering a standalone large glyph.
 struct StandaloneResources {
     texture: wgpu::Texture,
@@ -426,20 +443,24 @@ impl WgpuRenderer {
             shader,
             standalone_shader,
             atlas_texture,
+            color_atlas: std::cell::RefCell::new(None),
             sampler,
             instance_buffer: std::cell::RefCell::new(instance_buffer),
-            _bind_group_layout: bind_group_layout,
+            bind_group_layout,
             standalone_bind_group_layout,
             globals_buffer,
             globals_bind_group,
             standalone_resources: std::cell::RefCell::new(None),
+            color_standalone_resources: std::cell::RefCell::new(None),
             instance_data_staging: std::cell::RefCell::new(Vec::new()),
             pixel_staging: std::cell::RefCell::new(Vec::new()),
         };
 
         for &format in formats {
-            resources.get_pipeline(device, format);
-            resources.get_standalone_pipeline(device, format);
+            for color in [false, true] {
+                resources.get_pipeline(device, format, color);
+                resources.get_standalone_pipeline(device, format, color);
+            }
         }
 
         Self {
@@ -653,9 +674,10 @@ impl WgpuResources {
         &self,
         device: &wgpu::Device,
         format: wgpu::TextureFormat,
+        color: bool,
     ) -> wgpu::RenderPipeline {
         // Optimistic check
-        if let Some(pipeline) = self.pipelines.borrow().get(&format) {
+        if let Some(pipeline) = self.pipelines.borrow().get(&(format, color)) {
             return pipeline.clone();
         }
 
@@ -673,7 +695,7 @@ impl WgpuResources {
             },
             fragment: Some(wgpu::FragmentState {
                 module: &self.shader,
-                entry_point: Some("fs_main"),
+                entry_point: Some(if color { "fs_color" } else { "fs_main" }),
                 targets: &[Some(wgpu::ColorTargetState {
                     format,
                     blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
@@ -696,7 +718,9 @@ impl WgpuResources {
             cache: None,
         });
 
-        self.pipelines.borrow_mut().insert(format, pipeline.clone());
+        self.pipelines
+            .borrow_mut()
+            .insert((format, color), pipeline.clone());
         pipeline
     }
 
@@ -704,8 +728,9 @@ impl WgpuResources {
         &self,
         device: &wgpu::Device,
         format: wgpu::TextureFormat,
+        color: bool,
     ) -> wgpu::RenderPipeline {
-        if let Some(pipeline) = self.standalone_pipelines.borrow().get(&format) {
+        if let Some(pipeline) = self.standalone_pipelines.borrow().get(&(format, color)) {
             return pipeline.clone();
         }
 
@@ -722,7 +747,7 @@ impl WgpuResources {
             },
             fragment: Some(wgpu::FragmentState {
                 module: &self.standalone_shader,
-                entry_point: Some("fs_main"),
+                entry_point: Some(if color { "fs_color" } else { "fs_main" }),
                 targets: &[Some(wgpu::ColorTargetState {
                     format,
                     blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
@@ -747,7 +772,7 @@ impl WgpuResources {
 
         self.standalone_pipelines
             .borrow_mut()
-            .insert(format, pipeline.clone());
+            .insert((format, color), pipeline.clone());
         pipeline
     }
 
@@ -780,13 +805,20 @@ impl WgpuResources {
     /// To avoid recreating the texture every time the glyph size changes slightly, the texture dimensions
     /// are rounded up to the next power of two (e.g., 100x100 -> 128x128). This significantly stabilizes
     /// GPU resource churn for variable-sized large glyphs.
+    ///
+    /// Color glyphs use their own `Rgba8Unorm` texture.
     fn ensure_standalone_resources(
         &self,
         device: &wgpu::Device,
         needed_width: u32,
         needed_height: u32,
+        color: bool,
     ) -> std::cell::RefMut<'_, Option<StandaloneResources>> {
-        let mut resources_ref = self.standalone_resources.borrow_mut();
+        let mut resources_ref = if color {
+            self.color_standalone_resources.borrow_mut()
+        } else {
+            self.standalone_resources.borrow_mut()
+        };
 
         let recreate = if let Some(res) = resources_ref.as_ref() {
             res.size.width < needed_width || res.size.height < needed_height
@@ -818,7 +850,7 @@ impl WgpuResources {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::R8Unorm,
+                format: texture_format(color),
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
             });
@@ -866,8 +898,9 @@ impl WgpuResources {
         pixels: &'a [u8],
         width: u32,
         height: u32,
+        bytes_per_pixel: u32,
     ) -> (std::borrow::Cow<'a, [u8]>, u32) {
-        let bytes_per_row = width;
+        let bytes_per_row = width * bytes_per_pixel;
         // Align to 256 bytes: (val + 255) & !255 checks the next multiple of 256.
         let padded_bytes_per_row = (bytes_per_row + 255) & !255;
         let padding = padded_bytes_per_row - bytes_per_row;
@@ -881,8 +914,8 @@ impl WgpuResources {
             pixel_staging.reserve((padded_bytes_per_row * height) as usize);
 
             for row in 0..height {
-                let src_start = (row * width) as usize;
-                let src_end = src_start + width as usize;
+                let src_start = (row * bytes_per_row) as usize;
+                let src_end = src_start + bytes_per_row as usize;
                 if src_end <= pixels.len() {
                     pixel_staging.extend_from_slice(&pixels[src_start..src_end]);
                     // Append zeros for alignment
@@ -911,8 +944,19 @@ impl WgpuResources {
                 continue;
             }
 
-            let (data, padded_bytes_per_row) =
-                Self::prepare_padded_data(&mut pixel_staging, &update.pixels, width, height);
+            let color_atlas = update.color.then(|| self.ensure_color_atlas(device));
+            let (texture, bytes_per_pixel) = match &color_atlas {
+                Some(color_atlas) => (&color_atlas.texture, 4),
+                None => (&self.atlas_texture, 1),
+            };
+
+            let (data, padded_bytes_per_row) = Self::prepare_padded_data(
+                &mut pixel_staging,
+                &update.pixels,
+                width,
+                height,
+                bytes_per_pixel,
+            );
 
             let staging_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Atlas Staging Buffer"),
@@ -930,7 +974,7 @@ impl WgpuResources {
                     },
                 },
                 wgpu::TexelCopyTextureInfo {
-                    texture: &self.atlas_texture,
+                    texture,
                     mip_level: 0,
                     origin: wgpu::Origin3d {
                         x: update.x as u32,
@@ -948,6 +992,54 @@ impl WgpuResources {
         }
     }
 
+    /// Returns the color atlas, creating it with the size of the coverage atlas.
+    fn ensure_color_atlas(&self, device: &wgpu::Device) -> std::cell::RefMut<'_, ColorAtlas> {
+        std::cell::RefMut::map(self.color_atlas.borrow_mut(), |color_atlas| {
+            color_atlas.get_or_insert_with(|| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Color Glyph Atlas Array"),
+                    size: self.atlas_texture.size(),
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: texture_format(true),
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2Array),
+                    ..Default::default()
+                });
+
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Color Atlas Bind Group"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: self.globals_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(&view),
+                        },
+                    ],
+                });
+
+                ColorAtlas {
+                    texture,
+                    bind_group,
+                }
+            })
+        })
+    }
+
+    /// Draws the instances, one draw call per run of coverage or color glyphs.
     fn draw_instances<T: Into<[f32; 4]> + Copy, E>(
         &self,
         device: &wgpu::Device,
@@ -955,9 +1047,23 @@ impl WgpuResources {
         current_offset: &std::cell::Cell<u64>,
         instances: &[GlyphInstance<T>],
     ) -> Result<(), E> {
-        if instances.is_empty() {
-            return Ok(());
+        for run in instances.chunk_by(|a, b| a.color == b.color) {
+            self.draw_instance_run(device, controller, current_offset, run)?;
         }
+        Ok(())
+    }
+
+    fn draw_instance_run<T: Into<[f32; 4]> + Copy, E>(
+        &self,
+        device: &wgpu::Device,
+        controller: &mut impl WgpuRenderPassController<E>,
+        current_offset: &std::cell::Cell<u64>,
+        instances: &[GlyphInstance<T>],
+    ) -> Result<(), E> {
+        let Some(first) = instances.first() else {
+            return Ok(());
+        };
+        let color = first.color;
 
         let mut instance_buffer = self.instance_buffer.borrow_mut();
 
@@ -1003,13 +1109,19 @@ impl WgpuResources {
             bytes.len() as u64,
         );
 
+        let color_atlas = color.then(|| self.ensure_color_atlas(device));
+        let bind_group = match &color_atlas {
+            Some(color_atlas) => &color_atlas.bind_group,
+            None => &self.globals_bind_group,
+        };
+
         let format = controller.format()?;
         let mut rpass = controller.create_pass()?;
 
         // Use cached pipeline or create new one based on format
-        let pipeline = self.get_pipeline(device, format);
+        let pipeline = self.get_pipeline(device, format, color);
         rpass.set_pipeline(&pipeline);
-        rpass.set_bind_group(0, &self.globals_bind_group, &[]);
+        rpass.set_bind_group(0, bind_group, &[]);
         rpass.set_vertex_buffer(
             0,
             instance_buffer.slice(offset..offset + bytes.len() as u64),
@@ -1030,7 +1142,8 @@ impl WgpuResources {
         let needed_width = standalone.width as u32;
         let needed_height = standalone.height as u32;
 
-        let resources_ref = self.ensure_standalone_resources(device, needed_width, needed_height);
+        let resources_ref =
+            self.ensure_standalone_resources(device, needed_width, needed_height, standalone.color);
         let resources = resources_ref
             .as_ref()
             .expect("Logic bug: resources_ref should be initialized.");
@@ -1040,8 +1153,13 @@ impl WgpuResources {
         let height = standalone.height as u32;
 
         let mut pixel_staging = self.pixel_staging.borrow_mut();
-        let (data, padded_bytes_per_row) =
-            Self::prepare_padded_data(&mut pixel_staging, &standalone.pixels, width, height);
+        let (data, padded_bytes_per_row) = Self::prepare_padded_data(
+            &mut pixel_staging,
+            &standalone.pixels,
+            width,
+            height,
+            if standalone.color { 4 } else { 1 },
+        );
 
         let staging_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Standalone Staging Buffer"),
@@ -1116,7 +1234,7 @@ impl WgpuResources {
         let format = controller.format()?;
         let mut rpass = controller.create_pass()?;
 
-        let pipeline = self.get_standalone_pipeline(device, format);
+        let pipeline = self.get_standalone_pipeline(device, format, standalone.color);
         rpass.set_pipeline(&pipeline);
         rpass.set_bind_group(0, &resources.bind_group, &[]);
         rpass.set_vertex_buffer(
@@ -1129,3 +1247,12 @@ impl WgpuResources {
         Ok(())
     }
 }
+
+/// Format of the textures holding coverage or color glyphs.
+fn texture_format(color: bool) -> wgpu::TextureFormat {
+    if color {
+        wgpu::TextureFormat::Rgba8Unorm
+    } else {
+        wgpu::TextureFormat::R8Unorm
+    }
+}
@@ -54,3 +54,9 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let alpha = textureSample(font_texture, font_sampler, in.tex_coords, i32(in.layer)).r;
     return in.color * alpha;
 }
+
+// Color glyphs carry premultiplied colors; only the opacity of the instance applies.
+@fragment
+fn fs_color(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(font_texture, font_sampler, in.tex_coords, i32(in.layer)) * in.color.a;
+}
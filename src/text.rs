@@ -1,10 +1,24 @@
+/// Caching of layout results.
+pub mod cache;
 /// Defines the input data structures for text layout.
 pub mod data;
+/// Hyphenation patterns used when wrapping words.
+pub mod hyphenation;
 /// The core text layout engine and configuration.
 pub mod layout;
+/// Parsing of rich-text markup into text runs.
+pub mod markup;
 
-pub use data::{TextData, TextElement};
+pub use cache::LayoutCache;
+pub use data::{
+    BaselineShift, InlineObject, ParagraphStyle, RubyText, Spacing, SyntheticStyle, TextData,
+    TextDecoration, TextElement,
+};
+pub use hyphenation::Hyphenator;
 pub use layout::{
-    GlyphPosition, HorizontalAlign, TextLayout, TextLayoutConfig, TextLayoutLine, VerticalAlign,
-    WrapStyle,
+    BaseDirection, ClusterPosition, DecorationKind, DecorationPosition, GlyphPosition,
+    HitTestResult, HorizontalAlign, InlineObjectPosition, Kinsoku, LayoutRect, LayoutShape,
+    LineBreakKind, TabAlign, TabStop, TabWidth, TextFlow, TextLayout, TextLayoutConfig,
+    TextLayoutLine, TextOverflow, TextPosition, VerticalAlign, WrapStyle, WritingMode,
 };
+pub use markup::{MarkupDefaults, MarkupError, MarkupErrorKind, MarkupUserData};
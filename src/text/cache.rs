@@ -0,0 +1,210 @@
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+    num::NonZeroUsize,
+    sync::Arc,
+};
+
+use crate::{
+    font_storage::FontStorage,
+    text::{TextData, TextLayout, TextLayoutConfig},
+};
+
+/// Least recently used cache of layout results.
+///
+/// Layouts are looked up by a hash of the text, the configuration and the
+/// [font generation](FontStorage::generation), then compared in full, so a
+/// hash collision never returns the wrong layout. The cache is emptied when
+/// the generation of the font storage changes.
+///
+/// Layouts with different user data types can share one cache.
+pub struct LayoutCache {
+    capacity: usize,
+    /// Generation of the font storage the cached layouts were computed with.
+    generation: u64,
+    /// Counter used to order entries by last use.
+    tick: u64,
+    len: usize,
+    entries: HashMap<u64, Vec<Entry>, fxhash::FxBuildHasher>,
+    /// Keys of the entries by last use, oldest first. Ticks are unique.
+    lru: BTreeMap<u64, u64>,
+}
+
+struct Entry {
+    last_used: u64,
+    /// A `CachedLayout<T>` for the user data type of the layout.
+    layout: Box<dyn Any + Send>,
+}
+
+struct CachedLayout<T: Clone> {
+    text: TextData<T>,
+    config: TextLayoutConfig,
+    layout: Arc<TextLayout<T>>,
+}
+
+impl LayoutCache {
+    /// Creates an empty cache holding at most `capacity` layouts.
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            capacity: capacity.get(),
+            generation: 0,
+            tick: 0,
+            len: 0,
+            entries: HashMap::with_hasher(fxhash::FxBuildHasher::default()),
+            lru: BTreeMap::new(),
+        }
+    }
+
+    /// Removes all cached layouts.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.lru.clear();
+        self.len = 0;
+    }
+
+    /// Returns the number of cached layouts.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the layout of `text`, computing it with [`TextData::layout`] on a miss.
+    ///
+    /// The returned layout is shared with the cache, so it is never modified.
+    pub fn layout<T>(
+        &mut self,
+        text: &TextData<T>,
+        config: &TextLayoutConfig,
+        font_storage: &mut FontStorage,
+    ) -> Arc<TextLayout<T>>
+    where
+        T: Clone + PartialEq + Send + Sync + 'static,
+    {
+        if self.generation != font_storage.generation() {
+            self.clear();
+            self.generation = font_storage.generation();
+        }
+
+        self.tick += 1;
+        let key = input_hash(text, config, self.generation);
+
+        if let Some(entries) = self.entries.get_mut(&key) {
+            for entry in entries {
+                if let Some(cached) = entry.layout.downcast_ref::<CachedLayout<T>>()
+                    && cached.text == *text
+                    && cached.config == *config
+                {
+                    self.lru.remove(&entry.last_used);
+                    self.lru.insert(self.tick, key);
+                    entry.last_used = self.tick;
+                    return Arc::clone(&cached.layout);
+                }
+            }
+        }
+
+        let layout = Arc::new(text.layout(config, font_storage));
+
+        if self.len >= self.capacity {
+            self.evict();
+        }
+        self.lru.insert(self.tick, key);
+        self.entries.entry(key).or_default().push(Entry {
+            last_used: self.tick,
+            layout: Box::new(CachedLayout {
+                text: text.clone(),
+                config: config.clone(),
+                layout: Arc::clone(&layout),
+            }),
+        });
+        self.len += 1;
+
+        layout
+    }
+
+    /// Removes the least recently used layout.
+    fn evict(&mut self) {
+        let Some((last_used, key)) = self.lru.pop_first() else {
+            return;
+        };
+
+        if let Some(entries) = self.entries.get_mut(&key)
+            && let Some(index) = entries
+                .iter()
+                .position(|entry| entry.last_used == last_used)
+        {
+            entries.swap_remove(index);
+            if entries.is_empty() {
+                self.entries.remove(&key);
+            }
+            self.len -= 1;
+        }
+    }
+}
+
+/// Hashes the parts of the layout input that usually differ between layouts.
+///
+/// Entries with the same hash are compared in full, so the remaining fields
+/// (and the user data, which is not required to be `Hash`) are left out.
+fn input_hash<T: Clone>(text: &TextData<T>, config: &TextLayoutConfig, generation: u64) -> u64 {
+    let mut hasher = fxhash::FxHasher::default();
+
+    generation.hash(&mut hasher);
+    for element in &text.texts {
+        element.font_id.hash(&mut hasher);
+        element.font_size.to_bits().hash(&mut hasher);
+        element.content.hash(&mut hasher);
+    }
+    text.paragraphs.len().hash(&mut hasher);
+    text.ruby.len().hash(&mut hasher);
+
+    config.max_width.map(f32::to_bits).hash(&mut hasher);
+    config.max_height.map(f32::to_bits).hash(&mut hasher);
+    config.horizontal_align.hash(&mut hasher);
+    config.vertical_align.hash(&mut hasher);
+    config.writing_mode.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_cache_reuse_and_eviction() {
+        let mut font_storage = FontStorage::new();
+        let mut cache = LayoutCache::new(NonZeroUsize::new(2).unwrap());
+        let text = TextData::<u8>::new();
+        let config = |max_width| TextLayoutConfig {
+            max_width: Some(max_width),
+            ..Default::default()
+        };
+
+        let first = cache.layout(&text, &config(100.0), &mut font_storage);
+        let again = cache.layout(&text, &config(100.0), &mut font_storage);
+        assert!(Arc::ptr_eq(&first, &again));
+
+        // Layouts with other user data types are separate entries.
+        let other = cache.layout(&TextData::<u16>::new(), &config(100.0), &mut font_storage);
+        assert_eq!(other.total_width, first.total_width);
+        assert_eq!(cache.len(), 2);
+
+        // The `u16` layout is the least recently used one now.
+        cache.layout(&text, &config(100.0), &mut font_storage);
+        cache.layout(&text, &config(200.0), &mut font_storage);
+        assert_eq!(cache.len(), 2);
+        let again = cache.layout(&text, &config(100.0), &mut font_storage);
+        assert!(Arc::ptr_eq(&first, &again));
+
+        font_storage.set_fallback_families(["DejaVu Sans"]);
+        let again = cache.layout(&text, &config(100.0), &mut font_storage);
+        assert!(!Arc::ptr_eq(&first, &again));
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::text::HorizontalAlign;
+
 /// Collection of text runs that will be laid out together.
 ///
 /// The layout code walks over the stored [`TextElement`] values in order and
@@ -7,6 +9,12 @@
 pub struct TextData<T: Clone> {
     /// The list of text elements to be processed.
     pub texts: Vec<TextElement<T>>,
+    /// Explicit paragraphs as the index of their first text element and their
+    /// style, in increasing order of index. See [`TextData::start_paragraph`].
+    pub paragraphs: Vec<(usize, ParagraphStyle)>,
+    /// Ruby annotations as the index of their base text element and the
+    /// annotation, in increasing order of index. See [`TextData::append_ruby`].
+    pub ruby: Vec<(usize, RubyText<T>)>,
 }
 
 /// Single run of text that references a font and size.
@@ -23,6 +31,226 @@ pub struct TextElement<T> {
     pub content: String,
     /// Custom user data associated with this text run (e.g., color, style).
     pub user_data: T,
+    /// Inline object (image, widget, ...) laid out in place of the text.
+    ///
+    /// The whole element then flows, wraps and aligns like a single glyph and
+    /// [`TextLayoutLine::objects`](crate::text::TextLayoutLine::objects) reports
+    /// where it was placed. `font_id` and `font_size` are not used. `content`
+    /// is still what bidi, line breaking and source positions see, so it should
+    /// usually be U+FFFC OBJECT REPLACEMENT CHARACTER (`"\u{FFFC}"`).
+    pub inline_object: Option<InlineObject>,
+    /// Extra space added after every character of the run (tracking).
+    ///
+    /// Negative values tighten the text.
+    pub letter_spacing: Spacing,
+    /// Extra space added to word separators (see
+    /// [`TextLayoutConfig::word_separators`](crate::text::TextLayoutConfig::word_separators)),
+    /// on top of the letter spacing.
+    pub word_spacing: Spacing,
+    /// Raises or lowers the run relative to the baseline of the line.
+    ///
+    /// Shifted runs do not change the line height unless
+    /// [`TextLayoutConfig::baseline_shift_extends_line`](crate::text::TextLayoutConfig::baseline_shift_extends_line)
+    /// is set. Not applied to inline objects.
+    pub baseline_shift: BaselineShift,
+    /// Lines drawn along the run, reported in
+    /// [`TextLayoutLine::decorations`](crate::text::TextLayoutLine::decorations).
+    ///
+    /// Not applied to inline objects.
+    pub decoration: TextDecoration,
+    /// Bold and oblique styles simulated for fonts that lack those faces.
+    ///
+    /// Glyphs are emboldened or slanted when rasterized (see
+    /// [`GlyphId::with_synthetic_bold`](crate::GlyphId::with_synthetic_bold)) and
+    /// the advances grow with synthetic bold.
+    pub synthetic_style: SyntheticStyle,
+    /// Language of the run as a BCP 47 tag (`"en-US"`), used to choose
+    /// [`TextLayoutConfig::hyphenation_languages`](crate::text::TextLayoutConfig::hyphenation_languages).
+    pub language: Option<String>,
+}
+
+impl<T> TextElement<T> {
+    /// Creates a run of `content` with the default style: no inline object,
+    /// spacing, baseline shift, decoration or synthetic style.
+    ///
+    /// The other fields are set with the `with_*` methods.
+    pub fn new(
+        font_id: fontdb::ID,
+        font_size: f32,
+        content: impl Into<String>,
+        user_data: T,
+    ) -> Self {
+        Self {
+            font_id,
+            font_size,
+            content: content.into(),
+            user_data,
+            inline_object: None,
+            letter_spacing: Spacing::default(),
+            word_spacing: Spacing::default(),
+            baseline_shift: BaselineShift::default(),
+            decoration: TextDecoration::default(),
+            synthetic_style: SyntheticStyle::default(),
+            language: None,
+        }
+    }
+
+    /// Sets [`Self::inline_object`].
+    pub fn with_inline_object(mut self, object: InlineObject) -> Self {
+        self.inline_object = Some(object);
+        self
+    }
+
+    /// Sets [`Self::letter_spacing`].
+    pub fn with_letter_spacing(mut self, spacing: Spacing) -> Self {
+        self.letter_spacing = spacing;
+        self
+    }
+
+    /// Sets [`Self::word_spacing`].
+    pub fn with_word_spacing(mut self, spacing: Spacing) -> Self {
+        self.word_spacing = spacing;
+        self
+    }
+
+    /// Sets [`Self::baseline_shift`].
+    pub fn with_baseline_shift(mut self, shift: BaselineShift) -> Self {
+        self.baseline_shift = shift;
+        self
+    }
+
+    /// Sets [`Self::decoration`].
+    pub fn with_decoration(mut self, decoration: TextDecoration) -> Self {
+        self.decoration = decoration;
+        self
+    }
+
+    /// Sets [`Self::synthetic_style`].
+    pub fn with_synthetic_style(mut self, style: SyntheticStyle) -> Self {
+        self.synthetic_style = style;
+        self
+    }
+
+    /// Sets [`Self::language`].
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+}
+
+/// Decoration lines requested for a run. Any combination can be set.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextDecoration {
+    /// Line below the baseline.
+    pub underline: bool,
+    /// Line through the middle of lowercase letters.
+    pub strikethrough: bool,
+    /// Line along the ascent of the font.
+    pub overline: bool,
+}
+
+/// Styles simulated when rasterizing the glyphs of a run.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SyntheticStyle {
+    /// Thicken the glyphs.
+    pub bold: bool,
+    /// Slant the glyphs to the right.
+    pub oblique: bool,
+}
+
+impl SyntheticStyle {
+    /// Returns the styles to simulate when `face` was chosen for a query with
+    /// `weight` and `style`, e.g. bold when the family has no bold face.
+    pub fn for_face(face: &fontdb::FaceInfo, weight: fontdb::Weight, style: fontdb::Style) -> Self {
+        Self {
+            bold: weight.0 >= fontdb::Weight::SEMIBOLD.0
+                && face.weight.0 < fontdb::Weight::SEMIBOLD.0,
+            oblique: style != fontdb::Style::Normal && face.style == fontdb::Style::Normal,
+        }
+    }
+}
+
+/// Offset of a run from the baseline of the line.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum BaselineShift {
+    /// The run sits on the baseline.
+    #[default]
+    Baseline,
+    /// Raised by the superscript offset recommended by the font.
+    Superscript,
+    /// Lowered by the subscript offset recommended by the font.
+    Subscript,
+    /// Raised by a distance in pixels. Negative values lower the run.
+    Pixels(f32),
+}
+
+/// Length of extra spacing, in pixels or relative to the font size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Spacing {
+    /// Fixed distance in pixels.
+    Pixels(f32),
+    /// Multiple of the font size of the run.
+    Em(f32),
+}
+
+impl Default for Spacing {
+    fn default() -> Self {
+        Self::Pixels(0.0)
+    }
+}
+
+impl Spacing {
+    /// Resolves the spacing to pixels for a run of the given font size.
+    pub fn to_pixels(self, font_size: f32) -> f32 {
+        match self {
+            Self::Pixels(pixels) => pixels,
+            Self::Em(em) => em * font_size,
+        }
+    }
+}
+
+/// Size of an inline object, relative to the baseline it sits on.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct InlineObject {
+    /// Advance of the object along the line.
+    pub width: f32,
+    /// Height of the object above the baseline.
+    pub ascent: f32,
+    /// Depth of the object below the baseline, as a positive value.
+    pub descent: f32,
+}
+
+/// Annotation set above a base text run (ruby, furigana).
+#[derive(Clone, Debug, PartialEq)]
+pub struct RubyText<T> {
+    /// The ID of the font of the annotation.
+    pub font_id: fontdb::ID,
+    /// The size of the font in pixels.
+    pub font_size: f32,
+    /// The annotation text.
+    pub content: String,
+    /// Custom user data of the annotation glyphs.
+    pub user_data: T,
+}
+
+/// Layout properties of a paragraph.
+///
+/// Indents are measured from the start edge of the line (the right edge for
+/// right-to-left paragraphs) and reduce the length available to the line.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct ParagraphStyle {
+    /// Indent of the first line of the paragraph.
+    pub first_line_indent: f32,
+    /// Indent of the other lines of the paragraph.
+    pub hanging_indent: f32,
+    /// Space above the first line of the paragraph.
+    pub space_before: f32,
+    /// Space below the last line of the paragraph.
+    pub space_after: f32,
+    /// Overrides [`TextLayoutConfig::horizontal_align`](crate::text::TextLayoutConfig::horizontal_align).
+    pub horizontal_align: Option<HorizontalAlign>,
+    /// Overrides [`TextLayoutConfig::line_height_scale`](crate::text::TextLayoutConfig::line_height_scale).
+    pub line_height_scale: Option<f32>,
 }
 
 impl<T: Clone> Default for TextData<T> {
@@ -34,7 +262,11 @@ impl<T: Clone> Default for TextData<T> {
 impl<T: Clone> TextData<T> {
     /// Creates an empty container that can receive text runs.
     pub fn new() -> Self {
-        Self { texts: vec![] }
+        Self {
+            texts: vec![],
+            paragraphs: vec![],
+            ruby: vec![],
+        }
     }
 
     /// Adds a new text run to the layout queue.
@@ -45,8 +277,36 @@ impl<T: Clone> TextData<T> {
         self.texts.push(text);
     }
 
-    /// Removes all queued text runs so the builder can be reused.
+    /// Starts a new paragraph with `style` at the next appended text run.
+    ///
+    /// The paragraph begins on a new line and lasts until the next explicit
+    /// paragraph. Line break characters inside it split it into several
+    /// paragraphs of the same style. Text before the first explicit paragraph
+    /// uses the default style.
+    pub fn start_paragraph(&mut self, style: ParagraphStyle) {
+        self.paragraphs.push((self.texts.len(), style));
+    }
+
+    /// Adds a text run annotated with ruby text.
+    ///
+    /// The annotation is centered over the base (on its right in vertical
+    /// lines), the pair is never broken across lines and the line grows to make
+    /// room for the annotation. The base is laid out with its own font only, as
+    /// a single cluster. Ignored for inline objects.
+    ///
+    /// The base is not split at line break characters or tabs: they are shaped
+    /// with the rest of it instead of breaking the line or reaching a tab stop,
+    /// so bases should not contain them.
+    pub fn append_ruby(&mut self, base: TextElement<T>, annotation: RubyText<T>) {
+        self.ruby.push((self.texts.len(), annotation));
+        self.texts.push(base);
+    }
+
+    /// Removes all queued text runs, paragraphs and annotations so the builder
+    /// can be reused.
     pub fn clear(&mut self) {
         self.texts.clear();
+        self.paragraphs.clear();
+        self.ruby.clear();
     }
 }
@@ -0,0 +1,222 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+/// Hyphenation dictionary based on Liang's algorithm (the one used by TeX).
+///
+/// Patterns are language specific, so load the set matching the text being laid
+/// out (e.g. `hyph-en-us.pat.txt` from the `hyph-utf8` project) and assign it to
+/// [`TextLayoutConfig::hyphenation`](crate::text::TextLayoutConfig::hyphenation).
+///
+/// The dictionary is stored behind an `Arc`, so cloning is cheap, and so is
+/// comparing clones.
+#[derive(Clone, Debug)]
+pub struct Hyphenator {
+    patterns: Arc<Patterns>,
+    /// Minimum number of characters kept before a hyphenation point.
+    pub left_min: usize,
+    /// Minimum number of characters kept after a hyphenation point.
+    pub right_min: usize,
+}
+
+impl PartialEq for Hyphenator {
+    fn eq(&self, other: &Self) -> bool {
+        self.left_min == other.left_min
+            && self.right_min == other.right_min
+            && (Arc::ptr_eq(&self.patterns, &other.patterns) || self.patterns == other.patterns)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Patterns {
+    /// Pattern letters mapped to the level of each inter-letter position
+    /// (one more entry than there are letters).
+    levels: HashMap<String, Vec<u8>, fxhash::FxBuildHasher>,
+    /// Whole words with explicit hyphenation points, as character indices.
+    exceptions: HashMap<String, Vec<usize>, fxhash::FxBuildHasher>,
+    /// Number of letters of the longest pattern.
+    max_len: usize,
+}
+
+impl Hyphenator {
+    /// Parses a pattern list.
+    ///
+    /// Patterns are separated by whitespace, using the TeX notation (`.ach4`,
+    /// `a1b`, ...). Words containing `-` (`as-so-ciate`) are read as exceptions
+    /// with explicit hyphenation points. Text after `%` on a line is ignored.
+    pub fn new(patterns: &str) -> Self {
+        let mut parsed = Patterns::default();
+
+        for token in patterns
+            .lines()
+            .map(|line| line.split('%').next().unwrap_or_default())
+            .flat_map(str::split_whitespace)
+        {
+            if token.contains('-') {
+                let mut word = String::new();
+                let mut points = Vec::new();
+                for ch in token.chars() {
+                    if ch == '-' {
+                        points.push(word.chars().count());
+                    } else {
+                        word.extend(ch.to_lowercase());
+                    }
+                }
+                parsed.exceptions.insert(word, points);
+                continue;
+            }
+
+            let mut letters = String::new();
+            let mut levels = vec![0u8];
+            for ch in token.chars() {
+                match ch.to_digit(10) {
+                    Some(level) => {
+                        if let Some(last) = levels.last_mut() {
+                            *last = level as u8;
+                        }
+                    }
+                    None => {
+                        letters.extend(ch.to_lowercase());
+                        levels.push(0);
+                    }
+                }
+            }
+
+            parsed.max_len = parsed.max_len.max(letters.chars().count());
+            parsed.levels.insert(letters, levels);
+        }
+
+        Self {
+            patterns: Arc::new(parsed),
+            left_min: 2,
+            right_min: 3,
+        }
+    }
+
+    /// Parses a pattern list from UTF-8 bytes. See [`Hyphenator::new`] for the format.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, std::io::Error> {
+        let patterns = std::str::from_utf8(data)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(Self::new(patterns))
+    }
+
+    /// Reads and parses a pattern file. See [`Hyphenator::new`] for the format.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Returns the byte offsets inside `word` where a hyphen may be inserted.
+    ///
+    /// Only runs of alphabetic characters are hyphenated, so surrounding
+    /// punctuation such as quotes or commas does not affect the result.
+    pub fn hyphenate(&self, word: &str) -> Vec<usize> {
+        let mut offsets = Vec::new();
+        let mut run: Vec<(usize, char)> = Vec::new();
+
+        for (offset, ch) in word
+            .char_indices()
+            .chain(std::iter::once((word.len(), ' ')))
+        {
+            if ch.is_alphabetic() {
+                run.push((offset, ch));
+                continue;
+            }
+
+            offsets.extend(
+                self.hyphenate_letters(&run)
+                    .into_iter()
+                    .map(|index| run[index].0),
+            );
+            run.clear();
+        }
+
+        offsets
+    }
+
+    /// Returns the character indices inside `run` that start a new syllable.
+    fn hyphenate_letters(&self, run: &[(usize, char)]) -> Vec<usize> {
+        let len = run.len();
+        if len < self.left_min + self.right_min {
+            return Vec::new();
+        }
+
+        let lower: String = run
+            .iter()
+            .map(|(_, ch)| ch.to_lowercase().next().unwrap_or(*ch))
+            .collect();
+
+        let points: Vec<usize> = match self.patterns.exceptions.get(&lower) {
+            Some(points) => points.clone(),
+            None => {
+                // `.word.`, where the dots mark the word boundaries.
+                let chars: Vec<char> = std::iter::once('.')
+                    .chain(lower.chars())
+                    .chain(std::iter::once('.'))
+                    .collect();
+                let mut levels = vec![0u8; chars.len() + 1];
+
+                let mut key = String::new();
+                for start in 0..chars.len() {
+                    key.clear();
+                    for &ch in chars[start..].iter().take(self.patterns.max_len) {
+                        key.push(ch);
+                        if let Some(pattern) = self.patterns.levels.get(&key) {
+                            for (i, level) in pattern.iter().enumerate() {
+                                levels[start + i] = levels[start + i].max(*level);
+                            }
+                        }
+                    }
+                }
+
+                // `levels[i + 1]` is the position before the `i`-th letter of the word.
+                (1..len).filter(|&i| levels[i + 1] % 2 == 1).collect()
+            }
+        };
+
+        points
+            .into_iter()
+            .filter(|&i| i >= self.left_min && i + self.right_min <= len)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hyphenate_patterns() {
+        // Liang's example patterns for "hyphenation".
+        let hyphenator = Hyphenator::new("hy3ph he2n hena4 hen5at 1na n2at 1tio 2io o2n");
+        assert_eq!(hyphenator.hyphenate("hyphenation"), vec![2, 6]);
+        assert_eq!(hyphenator.hyphenate("\"Hyphenation,\""), vec![3, 7]);
+    }
+
+    #[test]
+    fn test_hyphenate_exceptions() {
+        let hyphenator = Hyphenator::new("% exceptions\nta-ble\nas-so-ciate");
+        assert_eq!(hyphenator.hyphenate("associate"), vec![2, 4]);
+        assert_eq!(hyphenator.hyphenate("Table"), vec![2]);
+
+        let hyphenator = Hyphenator {
+            right_min: 4,
+            ..hyphenator
+        };
+        assert!(hyphenator.hyphenate("table").is_empty());
+    }
+
+    #[test]
+    fn test_hyphenator_language() {
+        let english = Hyphenator::new("ta-ble");
+        let german = Hyphenator::new("ta-bel-le");
+        let mut config = crate::text::TextLayoutConfig {
+            hyphenation: Some(english.clone()),
+            ..Default::default()
+        };
+        config
+            .hyphenation_languages
+            .insert("de".to_string(), german.clone());
+
+        assert_eq!(config.hyphenator(Some("DE-ch")), Some(&german));
+        assert_eq!(config.hyphenator(Some("fr")), Some(&english));
+        assert_eq!(config.hyphenator(None), Some(&english));
+    }
+}
@@ -1,10 +1,23 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use crate::{glyph_id::GlyphId, text::TextData};
+use crate::{
+    glyph_id::GlyphId,
+    text::{
+        BaselineShift, Hyphenator, InlineObject, ParagraphStyle, RubyText, SyntheticStyle, TextData,
+    },
+};
 
-/// Default tab size in spaces.
-/// TODO: Move this into TextLayoutConfig when bumping the major version.
-const TAB_SIZE_IN_SPACES: f32 = 4.0;
+mod bidi;
+mod flow;
+mod hit_test;
+mod line_break;
+mod shape;
+mod shaping;
+mod vertical;
+
+pub use flow::TextFlow;
+pub use hit_test::{HitTestResult, LayoutRect};
+pub use shape::LayoutShape;
 
 /// Configuration knobs used by the text layout pipeline.
 ///
@@ -14,7 +27,8 @@ const TAB_SIZE_IN_SPACES: f32 = 4.0;
 pub struct TextLayoutConfig {
     /// Maximum width of the layout box. If text exceeds this, it may wrap or overflow.
     pub max_width: Option<f32>,
-    /// Maximum height of the layout box.
+    /// Maximum height of the layout box. Lines beyond it are kept unless
+    /// [`Self::overflow`] says otherwise.
     pub max_height: Option<f32>,
     /// Horizontal alignment of the text within the layout box.
     pub horizontal_align: HorizontalAlign,
@@ -30,6 +44,68 @@ pub struct TextLayoutConfig {
     pub word_separators: HashSet<char, fxhash::FxBuildHasher>,
     /// Characters that trigger a hard line break.
     pub linebreak_char: HashSet<char, fxhash::FxBuildHasher>,
+    /// Base direction of each paragraph, used by the bidi algorithm (UAX #9).
+    pub base_direction: BaseDirection,
+    /// Hyphenation patterns used to split words that overflow a line, for text
+    /// with no [`TextElement::language`](crate::text::TextElement::language) or
+    /// one missing from [`Self::hyphenation_languages`].
+    ///
+    /// Only used by [`WrapStyle::WordWrap`] and [`WrapStyle::UnicodeWrap`]. Soft hyphens
+    /// (U+00AD) in the text are always hyphenation points, even when this is `None`.
+    pub hyphenation: Option<Hyphenator>,
+    /// Hyphenation patterns per language tag (`"en-us"`, `"de"`, ...), chosen
+    /// by the [`TextElement::language`](crate::text::TextElement::language) of
+    /// the word. See [`Self::hyphenator`].
+    pub hyphenation_languages: HashMap<String, Hyphenator, fxhash::FxBuildHasher>,
+    /// Whether [`HorizontalAlign::Justify`] may spread space between characters on lines
+    /// that have no inter-word spaces (e.g. CJK text).
+    pub justify_inter_character: bool,
+    /// Direction in which characters and lines progress.
+    pub writing_mode: WritingMode,
+    /// Maximum length of digit runs set horizontally within a vertical line
+    /// (tate-chu-yoko). `0` disables it. Only used by [`WritingMode::VerticalRightToLeft`].
+    pub tate_chu_yoko: usize,
+    /// How text that does not fit in the layout box is handled.
+    pub overflow: TextOverflow,
+    /// Maximum number of lines. Later lines are dropped, and with
+    /// [`TextOverflow::Ellipsis`] the last kept line ends with an ellipsis.
+    pub max_lines: Option<usize>,
+    /// Distance between the default tab stops, used past the last of [`Self::tab_stops`].
+    pub tab_width: TabWidth,
+    /// Explicit tab stops, measured from the start of the line.
+    pub tab_stops: Vec<TabStop>,
+    /// Whether runs with a [`BaselineShift`] make room for their shifted glyphs
+    /// in the line height. Otherwise they may overlap the neighbouring lines.
+    pub baseline_shift_extends_line: bool,
+    /// Characters that may not start or end a line (kinsoku shori), used by
+    /// [`WrapStyle::CharWrap`] and [`WrapStyle::UnicodeWrap`].
+    pub kinsoku: Kinsoku,
+    /// Region the lines flow into, replacing [`Self::max_width`] as the span
+    /// of each line. Only used by [`WritingMode::HorizontalTopToBottom`].
+    pub shape: Option<LayoutShape>,
+}
+
+impl TextLayoutConfig {
+    /// Returns the hyphenation patterns for text in `language`.
+    ///
+    /// Tags are compared case-insensitively. A tag missing from
+    /// [`Self::hyphenation_languages`] falls back to its primary language
+    /// (`"de"` for `"de-CH"`), then to [`Self::hyphenation`].
+    pub fn hyphenator(&self, language: Option<&str>) -> Option<&Hyphenator> {
+        let mut tag = language;
+        while let Some(current) = tag {
+            if let Some(hyphenator) = self
+                .hyphenation_languages
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(current))
+                .map(|(_, hyphenator)| hyphenator)
+            {
+                return Some(hyphenator);
+            }
+            tag = current.rsplit_once('-').map(|(primary, _)| primary);
+        }
+        self.hyphenation.as_ref()
+    }
 }
 
 impl Default for TextLayoutConfig {
@@ -37,14 +113,26 @@ impl Default for TextLayoutConfig {
         Self {
             max_width: None,
             max_height: None,
-            horizontal_align: HorizontalAlign::Left,
+            horizontal_align: HorizontalAlign::Start,
             vertical_align: VerticalAlign::Top,
             line_height_scale: 1.0,
             wrap_style: WrapStyle::NoWrap,
             wrap_hard_break: true,
-            // TODO: implement tab handling.
             word_separators: [' ', '\t', '\n', '\r'].iter().cloned().collect(),
             linebreak_char: ['\n', '\r'].iter().cloned().collect(),
+            base_direction: BaseDirection::Auto,
+            hyphenation: None,
+            hyphenation_languages: HashMap::default(),
+            justify_inter_character: false,
+            writing_mode: WritingMode::HorizontalTopToBottom,
+            tate_chu_yoko: 0,
+            overflow: TextOverflow::Visible,
+            max_lines: None,
+            tab_width: TabWidth::Spaces(4.0),
+            tab_stops: Vec::new(),
+            baseline_shift_extends_line: false,
+            kinsoku: Kinsoku::default(),
+            shape: None,
         }
     }
 }
@@ -53,12 +141,182 @@ impl Default for TextLayoutConfig {
 #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum HorizontalAlign {
     /// Align text to the left.
-    #[default]
     Left,
     /// Center text horizontally.
     Center,
     /// Align text to the right.
     Right,
+    /// Align text to the start of the paragraph direction
+    /// (left for left-to-right paragraphs, right for right-to-left ones).
+    #[default]
+    Start,
+    /// Align text to the end of the paragraph direction
+    /// (right for left-to-right paragraphs, left for right-to-left ones).
+    End,
+    /// Stretch inter-word spaces so lines fill the layout width.
+    ///
+    /// The last line of each paragraph is aligned like [`HorizontalAlign::Start`].
+    /// Lines without spaces are only stretched when
+    /// [`TextLayoutConfig::justify_inter_character`] is enabled.
+    Justify,
+}
+
+/// Writing mode of a layout.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WritingMode {
+    /// Characters advance to the right (or left, for right-to-left text) and
+    /// lines are stacked from top to bottom.
+    #[default]
+    HorizontalTopToBottom,
+    /// Characters advance downwards and lines (columns) are stacked from right
+    /// to left, as in Japanese tategaki.
+    ///
+    /// CJK characters are set upright, using vertical glyph forms where the font
+    /// has them. Other characters are rotated 90° clockwise.
+    ///
+    /// The layout is described in logical terms: `max_height` limits the length
+    /// of the columns and `max_width` the area they are stacked in.
+    /// `horizontal_align` aligns the text within each column ([`HorizontalAlign::Start`]
+    /// and [`HorizontalAlign::Left`] are the top) and `vertical_align` places the
+    /// columns in the area ([`VerticalAlign::Top`] is the right edge).
+    VerticalRightToLeft,
+}
+
+/// Handling of text that overflows the layout box.
+///
+/// Only whole lines are dropped: glyphs of a line wider than the box are kept
+/// unless the line is shortened with an ellipsis. Clipping them is left to the
+/// renderer.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextOverflow {
+    /// Lay out all lines, even beyond `max_height`.
+    #[default]
+    Visible,
+    /// Drop the lines that do not fit within `max_height` (`max_width` for
+    /// [`WritingMode::VerticalRightToLeft`]).
+    Clip,
+    /// Like [`TextOverflow::Clip`], and replace the end of the last visible line
+    /// with an ellipsis ("…") when lines were dropped or the line is longer
+    /// than the layout box.
+    Ellipsis,
+}
+
+/// Distance between default tab stops.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TabWidth {
+    /// Multiple of the width of a space in the font of the tab character.
+    Spaces(f32),
+    /// Fixed distance in pixels.
+    Pixels(f32),
+}
+
+impl Default for TabWidth {
+    fn default() -> Self {
+        Self::Spaces(4.0)
+    }
+}
+
+/// Explicit tab stop, like the ones of a word processor ruler.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TabStop {
+    /// Distance of the stop from the start of the line.
+    pub position: f32,
+    /// How the text following the tab is aligned on the stop.
+    pub align: TabAlign,
+}
+
+/// Alignment of the text between a tab and the next tab (or the end of the line)
+/// relative to its tab stop.
+///
+/// Line wrapping measures the text as if every stop were [`TabAlign::Left`],
+/// so lines using the other alignments may wrap slightly early.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TabAlign {
+    /// The text starts at the stop.
+    #[default]
+    Left,
+    /// The text is centered on the stop.
+    Center,
+    /// The text ends at the stop.
+    Right,
+    /// The first occurrence of the character is placed at the stop (e.g. `'.'`
+    /// to align numbers). Text without it is aligned like [`TabAlign::Right`].
+    Decimal(char),
+}
+
+/// Line breaking rules for Japanese text (kinsoku shori).
+///
+/// A character that may not start a line is kept on the line of the character
+/// before it, and one that may not end a line moves to the next line with the
+/// character after it. The default has no rules.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Kinsoku {
+    /// Characters that may not start a line (closing brackets, `、`, `。`, ...).
+    pub no_line_start: HashSet<char, fxhash::FxBuildHasher>,
+    /// Characters that may not end a line (opening brackets).
+    pub no_line_end: HashSet<char, fxhash::FxBuildHasher>,
+    /// Whether commas and full stops (`、`, `。`, `，`, `．`, `,`, `.`) at the end of
+    /// a line may hang past its end (burasage) instead of taking the character
+    /// before them to the next line.
+    pub hanging_punctuation: bool,
+}
+
+impl Kinsoku {
+    const CLOSING_BRACKETS: &'static str = ")]}）］｝〕〉》」』】〙〗〟’”｠»";
+    const OPENING_BRACKETS: &'static str = "([{（［｛〔〈《「『【〘〖〝‘“｟«";
+    const HANGING: &'static str = "、。，．,.";
+
+    /// Rules of strict Japanese typesetting.
+    ///
+    /// Besides closing brackets and punctuation, lines may not start with small
+    /// kana, the prolonged sound mark, iteration marks or dashes.
+    pub fn strict() -> Self {
+        Self::with_line_start(concat!(
+            "、。，．,.",
+            "・：；:;？！?!‼⁇⁈⁉",
+            "ぁぃぅぇぉっゃゅょゎゕゖ",
+            "ァィゥェォッャュョヮヵヶ",
+            "ㇰㇱㇲㇳㇴㇵㇶㇷㇸㇹㇺㇻㇼㇽㇾㇿ",
+            "ーゝゞヽヾ々〻",
+            "‐゠–〜～",
+        ))
+    }
+
+    /// Rules of loose Japanese typesetting, for narrow lines.
+    ///
+    /// Only closing brackets, commas, full stops, `？` and `！` may not start a line.
+    pub fn loose() -> Self {
+        Self::with_line_start("、。，．,.？！?!‼⁇⁈⁉")
+    }
+
+    fn with_line_start(punctuation: &str) -> Self {
+        Self {
+            no_line_start: Self::CLOSING_BRACKETS
+                .chars()
+                .chain(punctuation.chars())
+                .collect(),
+            no_line_end: Self::OPENING_BRACKETS.chars().collect(),
+            hanging_punctuation: false,
+        }
+    }
+
+    /// Checks whether `ch` may hang past the end of a line.
+    fn hangs(&self, ch: char) -> bool {
+        self.hanging_punctuation && Self::HANGING.contains(ch)
+    }
+}
+
+/// Base direction of the paragraphs in a layout.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BaseDirection {
+    /// Detect the direction from the first strong character of each paragraph,
+    /// falling back to left-to-right.
+    #[default]
+    Auto,
+    /// All paragraphs are left-to-right.
+    LeftToRight,
+    /// All paragraphs are right-to-left.
+    RightToLeft,
 }
 
 #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -77,10 +335,19 @@ pub enum VerticalAlign {
 /// Wrapping rules that define where line breaks may occur.
 pub enum WrapStyle {
     /// Wrap text at word boundaries.
+    ///
+    /// Words that overflow a line are hyphenated when possible
+    /// (see [`TextLayoutConfig::hyphenation`]).
     #[default]
     WordWrap,
     /// Wrap text at any character.
     CharWrap,
+    /// Wrap text at the break opportunities of the Unicode line breaking
+    /// algorithm (UAX #14).
+    ///
+    /// In addition to word boundaries this breaks between CJK ideographs and
+    /// after hyphens or slashes.
+    UnicodeWrap,
     /// Do not wrap text.
     NoWrap,
 }
@@ -96,6 +363,9 @@ pub struct TextLayout<T> {
     pub total_width: f32,
     /// The lines of text in the layout.
     pub lines: Vec<TextLayoutLine<T>>,
+    /// Whether lines were dropped or shortened because of
+    /// [`TextLayoutConfig::max_lines`] or [`TextLayoutConfig::overflow`].
+    pub truncated: bool,
 }
 
 impl<T> TextLayout<T> {
@@ -111,6 +381,9 @@ impl<T> TextLayout<T> {
 }
 
 /// A single row of positioned glyphs in the final layout.
+///
+/// In [`WritingMode::VerticalRightToLeft`] a line is a column: `line_height` is
+/// its thickness and `line_width` its length.
 #[derive(Clone, Debug, PartialEq)]
 pub struct TextLayoutLine<T> {
     /// The height of this line.
@@ -121,8 +394,119 @@ pub struct TextLayoutLine<T> {
     pub top: f32,
     /// The Y coordinate of the bottom of this line.
     pub bottom: f32,
+    /// The X coordinate of the left edge of this line.
+    pub left: f32,
+    /// The X coordinate of the right edge of this line.
+    pub right: f32,
+    /// Range of the source text covered by this line.
+    ///
+    /// It includes the line break character ending the line and whitespace
+    /// dropped at a wrap, so consecutive lines cover the text without gaps.
+    /// Text hidden by truncation is not included.
+    pub source: std::ops::Range<TextPosition>,
+    /// How this line ends.
+    pub break_kind: LineBreakKind,
     /// The glyphs contained in this line.
     pub glyphs: Vec<GlyphPosition<T>>,
+    /// The clusters of this line in logical order.
+    pub clusters: Vec<ClusterPosition>,
+    /// The inline objects of this line, for the application to draw.
+    pub objects: Vec<InlineObjectPosition<T>>,
+    /// The decoration lines of this line.
+    pub decorations: Vec<DecorationPosition<T>>,
+}
+
+/// Placement of an [`InlineObject`] in the final layout.
+///
+/// The rectangle is in the same global coordinates as [`GlyphPosition`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct InlineObjectPosition<T> {
+    /// Index of the [`TextElement`](crate::text::TextElement) holding the object.
+    pub element: usize,
+    /// The X coordinate of the left edge of the object.
+    pub x: f32,
+    /// The Y coordinate of the top edge of the object.
+    pub y: f32,
+    /// The width of the object.
+    pub width: f32,
+    /// The height of the object (`ascent + descent`).
+    pub height: f32,
+    /// Custom user data of the element holding the object.
+    pub user_data: T,
+}
+
+/// Kind of a decoration line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DecorationKind {
+    /// See [`TextDecoration::underline`](crate::text::TextDecoration::underline).
+    Underline,
+    /// See [`TextDecoration::strikethrough`](crate::text::TextDecoration::strikethrough).
+    Strikethrough,
+    /// See [`TextDecoration::overline`](crate::text::TextDecoration::overline).
+    Overline,
+}
+
+/// Placement of a decoration line in the final layout.
+///
+/// Each rectangle covers consecutive clusters of one element on one line, in
+/// the same global coordinates as [`GlyphPosition`]. Vertical lines are
+/// decorated like rotated horizontal lines: the underline is on the left.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecorationPosition<T> {
+    /// Which decoration line this is.
+    pub kind: DecorationKind,
+    /// Index of the [`TextElement`](crate::text::TextElement) being decorated.
+    pub element: usize,
+    /// The X coordinate of the left edge of the rectangle.
+    pub x: f32,
+    /// The Y coordinate of the top edge of the rectangle.
+    pub y: f32,
+    /// The width of the rectangle.
+    pub width: f32,
+    /// The height of the rectangle.
+    pub height: f32,
+    /// Custom user data of the decorated element.
+    pub user_data: T,
+}
+
+/// Reason a line of a layout ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LineBreakKind {
+    /// The text was wrapped (or hyphenated) to fit the line length.
+    Soft,
+    /// A line break character ends the line.
+    Hard,
+    /// An explicit paragraph of the [`TextData`] starts after the line.
+    Paragraph,
+    /// The line ends the text.
+    EndOfText,
+}
+
+/// Position in the source text of a layout.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextPosition {
+    /// Index of the [`TextElement`](crate::text::TextElement) in the [`TextData`].
+    pub element: usize,
+    /// Byte offset inside [`TextElement::content`](crate::text::TextElement::content).
+    pub offset: usize,
+}
+
+/// A shaped cluster of a line: the smallest piece of text that can be placed
+/// or selected on its own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterPosition {
+    /// Index of the [`TextElement`](crate::text::TextElement) the cluster comes from.
+    pub element: usize,
+    /// Byte range of the cluster inside the element's content. Inserted text,
+    /// such as a hyphen or an ellipsis, has an empty range.
+    pub source: std::ops::Range<usize>,
+    /// Start of the cluster along the line: the X coordinate of its left edge,
+    /// or the Y coordinate of its top edge in [`WritingMode::VerticalRightToLeft`].
+    pub offset: f32,
+    /// Length of the cluster along the line.
+    pub advance: f32,
+    /// Whether the cluster is part of a right-to-left run.
+    pub rtl: bool,
 }
 
 /// **Y-axis goes down**
@@ -137,6 +521,11 @@ pub struct GlyphPosition<T> {
     pub x: f32,
     /// The absolute Y coordinate of the glyph.
     pub y: f32,
+    /// Index of the [`TextElement`](crate::text::TextElement) that produced the glyph.
+    pub element: usize,
+    /// Byte range of the source cluster inside the element's content. Glyphs of
+    /// inserted text (hyphens, ellipses) have an empty range.
+    pub source: std::ops::Range<usize>,
     /// Custom user data associated with this glyph.
     pub user_data: T,
 }
@@ -148,6 +537,8 @@ impl<T: std::hash::Hash> std::hash::Hash for GlyphPosition<T> {
         self.glyph_id.hash(state);
         self.x.to_bits().hash(state);
         self.y.to_bits().hash(state);
+        self.element.hash(state);
+        self.source.hash(state);
         self.user_data.hash(state);
     }
 }
@@ -156,6 +547,63 @@ impl<T: std::hash::Hash> std::hash::Hash for GlyphPosition<T> {
 struct LineRecord<T> {
     buffer: Option<layout_utl::LayoutBuffer<T>>,
     metrics: Option<fontdue::LineMetrics>,
+    paragraph_level: unicode_bidi::Level,
+    break_kind: LineBreakKind,
+    source: std::ops::Range<TextPosition>,
+    style: ParagraphStyle,
+    /// Whether the line is the first one of its paragraph.
+    paragraph_start: bool,
+    indent: f32,
+    /// Length of the punctuation at the end of the line that may hang past
+    /// its end (burasage), which is not counted when the line is fitted.
+    hang: f32,
+    /// Position of the line in [`TextLayoutConfig::shape`].
+    placement: Option<shape::LinePlacement>,
+}
+
+impl<T: Clone> LineRecord<T> {
+    /// Returns the ascent, descent and line gap of the line.
+    fn metrics(&self) -> (f32, f32, f32) {
+        if let Some(buffer) = &self.buffer {
+            buffer.line_metrics()
+        } else if let Some(metrics) = self.metrics {
+            // Empty line but with valid metrics (e.g., from newline char).
+            (metrics.ascent, metrics.descent, metrics.line_gap)
+        } else {
+            // Fallback for completely empty state (should happen rarely).
+            (0.0, 0.0, 0.0)
+        }
+    }
+
+    /// Returns the height of the line box, scaled by the line height scale.
+    fn line_height(&self, config: &TextLayoutConfig) -> f32 {
+        let (ascent, descent, line_gap) = self.metrics();
+        let scale = self
+            .style
+            .line_height_scale
+            .unwrap_or(config.line_height_scale);
+        ((ascent - descent + line_gap) * scale).max(0.0)
+    }
+
+    /// Returns the maximum length of the line, `limit` unless it is placed in a shape.
+    fn inline_limit(&self, limit: Option<f32>) -> Option<f32> {
+        self.placement.map_or(limit, |placement| placement.width)
+    }
+
+    /// Returns the paragraph spacing before and after the line.
+    fn spacing(&self) -> (f32, f32) {
+        let before = if self.paragraph_start {
+            self.style.space_before
+        } else {
+            0.0
+        };
+        let after = if self.break_kind == LineBreakKind::Soft {
+            0.0
+        } else {
+            self.style.space_after
+        };
+        (before, after)
+    }
 }
 
 impl<T: Clone> TextData<T> {
@@ -163,7 +611,9 @@ impl<T: Clone> TextData<T> {
     ///
     /// This helper simply forwards to `layout` because the layout stage must
     /// still run to honor wrapping, alignment, and kerning rules. The resulting
-    /// size is returned as `[width, height]` for convenience.
+    /// size is returned as `[width, height]` for convenience. Use a
+    /// [`LayoutCache`](crate::text::LayoutCache) to avoid repeating the work
+    /// for unchanged text.
     pub fn measure(
         &self,
         config: &TextLayoutConfig,
@@ -176,56 +626,220 @@ impl<T: Clone> TextData<T> {
     /// Performs glyph layout according to the provided configuration.
     ///
     /// The implementation follows a two-stage pipeline:
-    /// 1. Each input character is translated into glyph fragments that are
-    ///    buffered into line records while respecting wrap style and width
-    ///    constraints.
-    /// 2. The buffered lines are converted into final glyph positions with
-    ///    alignment offsets applied.
+    /// 1. Each text run is split by font coverage (characters missing from the
+    ///    element's font use a fallback face, see
+    ///    [`FontStorage::fallback_font`](crate::font_storage::FontStorage::fallback_font)),
+    ///    shaped (GSUB/GPOS) into glyph clusters and buffered into line records
+    ///    while respecting wrap style and width constraints.
+    /// 2. The buffered lines are reordered visually (UAX #9) and converted into
+    ///    final glyph positions with alignment offsets applied.
     ///
-    /// Breaking the work into stages keeps the code readable and allows future
-    /// extensions such as hyphenation without rewriting the core placement
-    /// logic.
+    /// Breaking the work into stages keeps the code readable: hyphenation, for
+    /// example, only changes how words are split into line records and leaves
+    /// the placement logic untouched.
     pub fn layout(
         &self,
         config: &TextLayoutConfig,
         font_storage: &mut crate::font_storage::FontStorage,
     ) -> TextLayout<T> {
-        LayoutEngine::new(config, font_storage).layout(&self.texts)
+        let analysis = TextAnalysis::new(&self.texts, &self.paragraphs, config);
+        LayoutEngine::new(
+            config,
+            font_storage,
+            &self.texts,
+            &self.paragraphs,
+            &self.ruby,
+            &analysis,
+        )
+        .layout()
+    }
+}
+
+/// Properties of the whole text that do not depend on the layout box, so
+/// several layouts of one text can share them.
+struct TextAnalysis {
+    bidi: bidi::BidiLevels,
+    breaks: line_break::BreakOpportunities,
+}
+
+impl TextAnalysis {
+    fn new<T>(
+        texts: &[crate::text::TextElement<T>],
+        paragraphs: &[(usize, ParagraphStyle)],
+        config: &TextLayoutConfig,
+    ) -> Self {
+        let bidi = bidi::BidiLevels::new(
+            texts,
+            paragraphs,
+            config.base_direction,
+            &config.linebreak_char,
+        );
+        let breaks = if config.wrap_style == WrapStyle::UnicodeWrap {
+            line_break::BreakOpportunities::new(texts)
+        } else {
+            line_break::BreakOpportunities::empty()
+        };
+        Self { bidi, breaks }
     }
 }
 
 struct LayoutEngine<'a, T> {
     config: &'a TextLayoutConfig,
     font_storage: &'a mut crate::font_storage::FontStorage,
+    texts: &'a [crate::text::TextElement<T>],
+    paragraphs: &'a [(usize, ParagraphStyle)],
+    ruby: &'a [(usize, RubyText<T>)],
 
     // State
     lines: Vec<LineRecord<T>>,
     line_buf: Option<layout_utl::LayoutBuffer<T>>,
+    /// Extent of the stacked lines, including the space after the last one.
+    extent: f32,
+    /// Whether a line that will be truncated has been pushed, so the rest of
+    /// the text is not laid out.
+    full: bool,
     word_buf: Option<Vec<layout_utl::GlyphFragment<T>>>,
     last_line_metrics: Option<fontdue::LineMetrics>,
+    /// Source position the line being built starts at.
+    line_start: TextPosition,
+
+    // Current element
+    element_index: usize,
+    /// Byte offset of the current element in the concatenated text.
+    text_offset: usize,
+    baseline_shift: f32,
+    kern_from: Option<KernGlyph>,
+    /// Decoration lines of each element, resolved when it is laid out.
+    decoration_strokes: Vec<Vec<layout_utl::DecorationStroke>>,
+
+    // Bidi
+    bidi: &'a bidi::BidiLevels,
+    paragraph_level: unicode_bidi::Level,
+
+    // Paragraphs
+    paragraph_style: ParagraphStyle,
+    first_line: bool,
+
+    // Line breaking
+    breaks: &'a line_break::BreakOpportunities,
+
+    // Shape
+    flow: Option<shape::ShapeFlow>,
+    line_placement: Option<shape::LinePlacement>,
+}
+
+/// Glyph at the end of a shaped font run.
+///
+/// Runs are shaped separately, so the kerning between the last glyph of a run
+/// and the first glyph of the next one is applied by the layout engine when
+/// both use the same font at the same size.
+#[derive(Clone, Copy)]
+struct KernGlyph {
+    font_id: fontdb::ID,
+    font_size: f32,
+    glyph_index: u16,
 }
 
 impl<'a, T: Clone> LayoutEngine<'a, T> {
     fn new(
         config: &'a TextLayoutConfig,
         font_storage: &'a mut crate::font_storage::FontStorage,
+        texts: &'a [crate::text::TextElement<T>],
+        paragraphs: &'a [(usize, ParagraphStyle)],
+        ruby: &'a [(usize, RubyText<T>)],
+        analysis: &'a TextAnalysis,
     ) -> Self {
+        let paragraph_level = analysis.bidi.paragraph_level_at(0);
+        let flow = config
+            .shape
+            .clone()
+            .filter(|_| config.writing_mode == WritingMode::HorizontalTopToBottom)
+            .map(|shape| shape::ShapeFlow::new(shape, config.max_width, config.max_height));
+
         Self {
             config,
             font_storage,
+            texts,
+            paragraphs,
+            ruby,
             lines: Vec::new(),
             // Buffer for the line currently being built.
             line_buf: None,
+            extent: 0.0,
+            full: false,
             // Buffer for the word currently being built.
             word_buf: None,
             // Metrics of the last processed line, used for handling empty lines/newlines.
             last_line_metrics: None,
+            // Resolved embedding levels of the whole text.
+            bidi: &analysis.bidi,
+            // Index of the current text run.
+            element_index: 0,
+            // Offset of the current text run in the concatenated text.
+            text_offset: 0,
+            // Baseline shift of the current text run in pixels, raised being positive.
+            baseline_shift: 0.0,
+            // Last glyph laid out, which the first glyph of the next font run
+            // is kerned against.
+            kern_from: None,
+            // Decoration lines of each text run, across the line like glyphs.
+            decoration_strokes: vec![Vec::new(); texts.len()],
+            // Base level of the paragraph currently being built.
+            paragraph_level,
+            // Position right after the last hard line break.
+            line_start: TextPosition::default(),
+            // Style of the paragraph currently being built.
+            paragraph_style: ParagraphStyle::default(),
+            // Whether the line being built is the first one of its paragraph.
+            first_line: true,
+            // Break opportunities for `WrapStyle::UnicodeWrap`.
+            breaks: &analysis.breaks,
+            // Lines placed in `TextLayoutConfig::shape`.
+            flow,
+            // Placement of the line being built, once it is known.
+            line_placement: None,
         }
     }
 
-    fn layout(mut self, texts: &[crate::text::TextElement<T>]) -> TextLayout<T> {
-        for text in texts {
-            self.process_text_run(text);
+    fn layout(self) -> TextLayout<T> {
+        self.layout_from(TextPosition::default())
+    }
+
+    /// Lays out the text from `start` on, continuing the text before it.
+    ///
+    /// The paragraph containing `start` keeps its style and direction. Its
+    /// first line indent and space before only apply when `start` is at the
+    /// start of the paragraph or right after a line break.
+    fn layout_from(mut self, start: TextPosition) -> TextLayout<T> {
+        // Both lists are sorted by element, so they are walked along the text.
+        let mut paragraphs = self.paragraphs.iter().peekable();
+        let mut ruby = self.ruby.iter().peekable();
+        for (element_index, text) in self.texts.iter().enumerate() {
+            if self.full {
+                break;
+            }
+            self.element_index = element_index;
+            // Paragraphs before `start` only set the style of the one it is in.
+            while let Some((_, style)) = paragraphs.next_if(|(start, _)| *start <= element_index) {
+                self.start_paragraph(*style);
+            }
+            let mut annotation = None;
+            while let Some((base, next)) = ruby.next_if(|(base, _)| *base <= element_index) {
+                if *base == element_index {
+                    annotation = Some(next);
+                }
+            }
+
+            if element_index >= start.element {
+                let offset = if element_index == start.element {
+                    self.resume_at(start);
+                    start.offset
+                } else {
+                    0
+                };
+                self.process_text_run(text, annotation.filter(|_| offset == 0), offset);
+            }
+            self.text_offset += text.content.len();
         }
 
         // Flush remaining word buffer
@@ -235,47 +849,142 @@ impl<'a, T: Clone> LayoutEngine<'a, T> {
 
         // Ensure the last line is finalized, even if empty (to preserve vertical spacing).
         self.finalize_line(self.last_line_metrics);
+        if let Some(last) = self.lines.last_mut() {
+            last.break_kind = LineBreakKind::EndOfText;
+            last.source.end = TextPosition {
+                element: self.texts.len().saturating_sub(1),
+                offset: self.texts.last().map_or(0, |text| text.content.len()),
+            };
+        }
+
+        let truncated = self.truncate_lines();
+        self.build_result(truncated)
+    }
+
+    /// Sets up the state for a layout starting at `start`, in the current element.
+    fn resume_at(&mut self, start: TextPosition) {
+        self.line_start = start;
+        self.paragraph_level = self
+            .bidi
+            .paragraph_level_at(self.text_offset + start.offset);
+
+        // The previous character tells whether a line was wrapped at `start`.
+        let paragraph_start = start.offset == 0
+            && self
+                .paragraphs
+                .iter()
+                .any(|(index, _)| *index == start.element);
+        let previous = self.texts[..=start.element]
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(back, text)| {
+                let end = if back == 0 {
+                    start.offset
+                } else {
+                    text.content.len()
+                };
+                text.content[..end].chars().next_back()
+            });
+        self.first_line =
+            paragraph_start || previous.is_none_or(|ch| self.config.linebreak_char.contains(&ch));
+    }
+
+    /// Starts an explicit paragraph at the current element, on a new line.
+    fn start_paragraph(&mut self, style: ParagraphStyle) {
+        if let Some(word) = self.word_buf.take() {
+            self.append_fragments_with_rules(&word, true);
+        }
+        if !self.first_line || self.line_buf.is_some() {
+            let buffer = self.line_buf.take();
+            self.push_record(buffer, self.last_line_metrics, LineBreakKind::Paragraph);
+            self.line_start = TextPosition {
+                element: self.element_index,
+                offset: 0,
+            };
+        }
+
+        self.paragraph_style = style;
+        self.paragraph_level = self.bidi.paragraph_level_at(self.text_offset);
+    }
 
-        self.build_result()
+    /// Returns the indent of the line being built.
+    fn indent(&self) -> f32 {
+        if self.first_line {
+            self.paragraph_style.first_line_indent
+        } else {
+            self.paragraph_style.hanging_indent
+        }
     }
 
-    fn process_text_run(&mut self, text: &crate::text::TextElement<T>) {
-        use std::sync::Arc;
+    /// Lays out the content of `text` from byte `start` on.
+    fn process_text_run(
+        &mut self,
+        text: &crate::text::TextElement<T>,
+        annotation: Option<&RubyText<T>>,
+        start: usize,
+    ) {
+        if let Some(object) = text.inline_object {
+            // An object is laid out whole, so a layout starting inside it skips it.
+            if start == 0 {
+                // Glyphs on both sides of the object are not kerned together.
+                self.kern_from = None;
+                self.process_inline_object(text, object);
+            }
+            return;
+        }
 
         let Some(font) = self.font_storage.font(text.font_id) else {
             return;
         };
+        let Some(face_data) = self.font_storage.face_data(text.font_id) else {
+            return;
+        };
         let Some(line_metric) = font.horizontal_line_metrics(text.font_size) else {
             return;
         };
-        if text.content.is_empty() {
+        let line_metric = self.block_metrics(line_metric);
+        if text.content.len() <= start {
             return;
         }
 
         self.last_line_metrics = Some(line_metric);
 
-        let create_fragment = |ch: char| {
-            let glyph_idx = font.lookup_glyph_index(ch);
-            let metrics = font.metrics_indexed(glyph_idx, text.font_size);
-            layout_utl::GlyphFragment {
-                ch,
-                glyph_idx,
-                metrics,
-                line_metrics: line_metric,
-                font_id: text.font_id,
-                font_size: text.font_size,
-                font: Arc::clone(&font),
-                user_data: text.user_data.clone(),
-            }
+        let shaper = shaping::Shaper::new(&face_data, &font, text.font_size);
+        self.baseline_shift = match text.baseline_shift {
+            BaselineShift::Baseline => 0.0,
+            BaselineShift::Superscript => shaper.script_offsets().0,
+            BaselineShift::Subscript => -shaper.script_offsets().1,
+            BaselineShift::Pixels(pixels) => pixels,
         };
+        self.decoration_strokes[self.element_index] = self.decoration_strokes(text, &shaper);
+
+        if let Some(annotation) = annotation {
+            self.kern_from = None;
+            self.process_ruby(text, annotation, &shaper, &line_metric);
+            return;
+        }
 
-        for ch in text.content.chars() {
-            match layout_utl::classify_char(
+        // Hard line breaks and tabs are handled by the layout engine itself, so the
+        // text is shaped in segments between them.
+        let mut segment_start = start;
+        for (offset, ch) in text
+            .content
+            .char_indices()
+            .skip_while(|&(offset, _)| offset < start)
+        {
+            let behavior = layout_utl::classify_char(
                 ch,
                 &self.config.word_separators,
                 &self.config.linebreak_char,
-            ) {
+            );
+
+            match behavior {
                 layout_utl::CharBehavior::LineBreak => {
+                    self.process_shaped_segment(&shaper, segment_start..offset, text, &line_metric);
+                    segment_start = offset + ch.len_utf8();
+                    self.kern_from = None;
+
                     // Newline characters always terminate the current line.
                     // If there is a pending word, append it to the current line first.
                     if let Some(word) = self.word_buf.take() {
@@ -285,20 +994,24 @@ impl<'a, T: Clone> LayoutEngine<'a, T> {
                     // We explicitly do not append the newline glyph to the layout.
                     // Instead, we just finalize the line with the current metrics.
                     self.finalize_line(Some(line_metric));
-                }
-                layout_utl::CharBehavior::WordBreak { render_glyph } => {
-                    // A separator (e.g., space) marks the end of a word.
-                    if let Some(word) = self.word_buf.take() {
-                        self.append_fragments_with_rules(&word, true);
-                    }
+                    self.line_start = TextPosition {
+                        element: self.element_index,
+                        offset: segment_start,
+                    };
 
-                    if render_glyph {
-                        let fragment = create_fragment(ch);
-                        // Append the separator itself (not part of the `word_buf`).
-                        self.append_fragments_with_rules(std::slice::from_ref(&fragment), false);
+                    // The next character starts a new bidi paragraph.
+                    self.paragraph_level = self
+                        .bidi
+                        .paragraph_level_at(self.text_offset + segment_start);
+                    if self.full {
+                        return;
                     }
                 }
                 layout_utl::CharBehavior::Tab => {
+                    self.process_shaped_segment(&shaper, segment_start..offset, text, &line_metric);
+                    segment_start = offset + ch.len_utf8();
+                    self.kern_from = None;
+
                     // Tab character works as a word separator and also adds spacing.
                     if let Some(word) = self.word_buf.take() {
                         self.append_fragments_with_rules(&word, true);
@@ -310,123 +1023,759 @@ impl<'a, T: Clone> LayoutEngine<'a, T> {
                     }
 
                     if let Some(line) = self.line_buf.as_mut() {
-                        // Calculate tab width based on space width.
-                        let space_glyph_idx = font.lookup_glyph_index(' ');
-                        let space_metrics = font.metrics_indexed(space_glyph_idx, text.font_size);
-                        let tab_width = space_metrics.advance_width * TAB_SIZE_IN_SPACES;
-
-                        // Move next_origin_x to the next tab stop.
                         let current_x = line.next_origin_x;
-                        let next_stop = (current_x / tab_width).floor() * tab_width + tab_width;
-                        line.next_origin_x = next_stop;
-                    }
-                }
-                layout_utl::CharBehavior::Regular => {
-                    let fragment = create_fragment(ch);
-                    if matches!(self.config.wrap_style, WrapStyle::CharWrap) {
-                        // In CharWrap mode, we treat every character as an independent unit,
-                        // bypassing the word buffer.
-                        self.append_fragments_with_rules(std::slice::from_ref(&fragment), true);
-                    } else {
-                        // Accumulate characters into the word buffer until a break occurs.
-                        match &mut self.word_buf {
-                            Some(buffer) => buffer.push(fragment),
-                            None => self.word_buf = Some(vec![fragment]),
+                        let explicit_stop = self
+                            .config
+                            .tab_stops
+                            .iter()
+                            .filter(|stop| stop.position > current_x)
+                            .min_by(|a, b| a.position.total_cmp(&b.position));
+                        let stop = match explicit_stop {
+                            Some(stop) => Some(*stop),
+                            None => {
+                                // Past the explicit stops, use the next multiple of the tab width.
+                                let tab_width = match self.config.tab_width {
+                                    TabWidth::Spaces(spaces) => {
+                                        let space_glyph_idx = font.lookup_glyph_index(' ');
+                                        font.metrics_indexed(space_glyph_idx, text.font_size)
+                                            .advance_width
+                                            * spaces
+                                    }
+                                    TabWidth::Pixels(pixels) => pixels,
+                                };
+                                (tab_width > 0.0).then(|| TabStop {
+                                    position: (current_x / tab_width).floor() * tab_width
+                                        + tab_width,
+                                    align: TabAlign::Left,
+                                })
+                            }
+                        };
+
+                        // Move next_origin_x to the next tab stop. The gap is resized
+                        // for the stop's alignment once the line is complete.
+                        line.push_gap(
+                            stop.map_or(0.0, |stop| stop.position - current_x),
+                            self.paragraph_level,
+                            self.element_index,
+                            offset..segment_start,
+                        );
+                        if let Some(gap) = line.clusters.last_mut() {
+                            gap.tab_stop = stop;
                         }
                     }
                 }
-                layout_utl::CharBehavior::Ignore => {
-                    // Skip control characters or invalid inputs.
-                }
+                _ => {}
             }
         }
+
+        self.process_shaped_segment(
+            &shaper,
+            segment_start..text.content.len(),
+            text,
+            &line_metric,
+        );
     }
 
-    fn append_fragments_with_rules(
+    /// Resolves the decoration lines requested by `text` to offsets across the line,
+    /// in the direction of the glyph offsets.
+    fn decoration_strokes(
+        &self,
+        text: &crate::text::TextElement<T>,
+        shaper: &shaping::Shaper<'_>,
+    ) -> Vec<layout_utl::DecorationStroke> {
+        let requested = [
+            (text.decoration.underline, DecorationKind::Underline),
+            (text.decoration.strikethrough, DecorationKind::Strikethrough),
+            (text.decoration.overline, DecorationKind::Overline),
+        ];
+        if requested.iter().all(|(enabled, _)| !enabled) {
+            return Vec::new();
+        }
+
+        let metrics = shaper.decoration_metrics();
+        // Distance of the baseline from the column center of sideways text.
+        let vertical_baseline = shaper
+            .line_metrics()
+            .map_or(0.0, |m| -(m.ascent + m.descent) / 2.0);
+
+        requested
+            .into_iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, kind)| {
+                let stroke = match kind {
+                    DecorationKind::Underline => metrics.underline,
+                    DecorationKind::Strikethrough => metrics.strikethrough,
+                    DecorationKind::Overline => metrics.overline,
+                };
+                let top = stroke.top + self.baseline_shift;
+                let across = match self.config.writing_mode {
+                    WritingMode::HorizontalTopToBottom => -top,
+                    WritingMode::VerticalRightToLeft => vertical_baseline + top - stroke.thickness,
+                };
+                layout_utl::DecorationStroke {
+                    kind,
+                    across,
+                    thickness: stroke.thickness,
+                }
+            })
+            .collect()
+    }
+
+    /// Lays out a base text run and its ruby annotation as a single cluster.
+    ///
+    /// The narrower of the two is centered on the wider one. The annotation
+    /// sits on the ascent of the base (on its right in vertical lines), which
+    /// grows by the height of the annotation.
+    fn process_ruby(
         &mut self,
-        fragments: &[layout_utl::GlyphFragment<T>],
-        allow_leading_space: bool,
+        text: &crate::text::TextElement<T>,
+        annotation: &RubyText<T>,
+        shaper: &shaping::Shaper<'_>,
+        line_metric: &fontdue::LineMetrics,
     ) {
-        if fragments.is_empty() {
-            return;
+        let global = self.text_offset..self.text_offset + text.content.len();
+        let level = self
+            .bidi
+            .level_runs(global)
+            .first()
+            .map_or(self.paragraph_level, |(_, level)| *level);
+
+        // Glyphs of a shaped string, placed from its start.
+        let flatten =
+            |clusters: Vec<layout_utl::PlacedCluster>| {
+                let mut pen = 0.0;
+                let mut glyphs = Vec::new();
+                for cluster in clusters {
+                    glyphs.extend(cluster.glyphs.into_iter().map(|glyph| {
+                        layout_utl::PlacedGlyph {
+                            x: glyph.x + pen,
+                            ink_end: glyph.ink_end + pen,
+                            ..glyph
+                        }
+                    }));
+                    pen += cluster.advance;
+                }
+                (glyphs, pen)
+            };
+
+        let (mut base, base_width) = flatten(self.place_clusters(
+            shaper,
+            &text.content,
+            (None, None),
+            level,
+            text.font_id,
+            text.synthetic_style,
+        ));
+
+        let annotation_font = self
+            .font_storage
+            .font(annotation.font_id)
+            .zip(self.font_storage.face_data(annotation.font_id));
+        let (mut ruby_glyphs, ruby_width, ruby_metrics) = match annotation_font {
+            Some((font, face_data)) if !annotation.content.is_empty() => {
+                let ruby_shaper = shaping::Shaper::new(&face_data, &font, annotation.font_size);
+                let (glyphs, width) = flatten(self.place_clusters(
+                    &ruby_shaper,
+                    &annotation.content,
+                    (None, None),
+                    level,
+                    annotation.font_id,
+                    SyntheticStyle::default(),
+                ));
+                let metrics = font
+                    .horizontal_line_metrics(annotation.font_size)
+                    .map(|metrics| self.block_metrics(metrics));
+                (glyphs, width, metrics)
+            }
+            _ => (Vec::new(), 0.0, None),
+        };
+
+        let advance = base_width.max(ruby_width);
+        let base_offset = (advance - base_width) / 2.0;
+        for glyph in &mut base {
+            glyph.x += base_offset;
+            glyph.ink_end += base_offset;
         }
 
-        // Rule: Drop leading spaces if they start a new line.
-        // This prevents lines from looking indented due to a wrapped space.
-        if !allow_leading_space
-            && let Some(first) = fragments.first()
-            && first.ch.is_whitespace()
-            && self
-                .line_buf
-                .as_ref()
-                .map(|line| line.glyphs.is_empty())
-                .unwrap_or(true)
-        {
-            return;
+        let mut line_metrics = *line_metric;
+        if let Some(ruby_metrics) = ruby_metrics {
+            // The descent of the annotation rests on the ascent of the base.
+            let across = match self.config.writing_mode {
+                WritingMode::HorizontalTopToBottom => -line_metric.ascent + ruby_metrics.descent,
+                WritingMode::VerticalRightToLeft => line_metric.ascent + ruby_metrics.ascent,
+            };
+            let ruby_offset = (advance - ruby_width) / 2.0;
+            for glyph in &mut ruby_glyphs {
+                glyph.x += ruby_offset;
+                glyph.ink_end += ruby_offset;
+                glyph.y += across;
+            }
+            line_metrics.ascent += ruby_metrics.ascent - ruby_metrics.descent;
+        }
+        if self.config.baseline_shift_extends_line {
+            line_metrics.ascent += self.baseline_shift;
+            line_metrics.descent += self.baseline_shift;
         }
 
-        self.append_fragments_to_line(fragments);
+        let fragment = layout_utl::GlyphFragment {
+            ch: text.content.chars().next().unwrap_or_default(),
+            glyphs: base,
+            advance,
+            level,
+            element: self.element_index,
+            source: 0..text.content.len(),
+            line_metrics,
+            font_id: text.font_id,
+            font_size: text.font_size,
+            user_data: text.user_data.clone(),
+            object: None,
+            baseline_shift: match self.config.writing_mode {
+                WritingMode::HorizontalTopToBottom => -self.baseline_shift,
+                WritingMode::VerticalRightToLeft => self.baseline_shift,
+            },
+            ruby: Some(layout_utl::RubyGlyphs {
+                glyphs: ruby_glyphs,
+                user_data: annotation.user_data.clone(),
+            }),
+        };
+        self.append_regular_fragment(fragment, self.text_offset);
     }
 
-    fn append_fragments_to_line(&mut self, fragments: &[layout_utl::GlyphFragment<T>]) {
-        if fragments.is_empty() {
-            return;
-        }
+    /// Lays out an inline object as a single cluster without glyphs.
+    fn process_inline_object(&mut self, text: &crate::text::TextElement<T>, object: InlineObject) {
+        let global = self.text_offset..self.text_offset + text.content.len();
+        let level = self
+            .bidi
+            .level_runs(global)
+            .first()
+            .map_or(self.paragraph_level, |(_, level)| *level);
 
-        let limit = if self.config.wrap_style == WrapStyle::NoWrap {
-            None
-        } else {
-            self.config.max_width
+        // Vertical lines keep the object upright and center it on the column.
+        let (advance, ascent, descent) = match self.config.writing_mode {
+            WritingMode::HorizontalTopToBottom => (object.width, object.ascent, -object.descent),
+            WritingMode::VerticalRightToLeft => (
+                object.ascent + object.descent,
+                object.width / 2.0,
+                -object.width / 2.0,
+            ),
+        };
+        let line_metrics = fontdue::LineMetrics {
+            ascent,
+            descent,
+            line_gap: 0.0,
+            new_line_size: ascent - descent,
         };
+        if self.last_line_metrics.is_none() {
+            self.last_line_metrics = Some(line_metrics);
+        }
 
-        let Some(buffer) = layout_utl::LayoutBuffer::from_fragments(fragments, self.font_storage)
-        else {
-            return;
+        let fragment = layout_utl::GlyphFragment {
+            ch: layout_utl::OBJECT_REPLACEMENT,
+            glyphs: Vec::new(),
+            advance,
+            level,
+            element: self.element_index,
+            source: 0..text.content.len(),
+            line_metrics,
+            font_id: text.font_id,
+            font_size: text.font_size,
+            user_data: text.user_data.clone(),
+            object: Some(object),
+            baseline_shift: 0.0,
+            ruby: None,
         };
+        self.append_regular_fragment(fragment, self.text_offset);
+    }
 
-        if let Some(limit_width) = limit {
-            // Case 1: Try to append the entire fragment sequence to the current line.
-            if let Some(current) = self.line_buf.as_mut() {
-                let projected = current.projected_concat_length(&buffer, self.font_storage);
-                if projected <= limit_width {
-                    // It fits!
-                    current.concat(buffer, self.font_storage);
-                    return;
-                }
-            }
+    /// Shapes a segment that contains no hard line breaks or tabs and feeds the
+    /// resulting clusters into the word and line buffers.
+    ///
+    /// The segment is split further into runs of a single bidi level so each
+    /// run is shaped in its resolved direction.
+    fn process_shaped_segment(
+        &mut self,
+        shaper: &shaping::Shaper<'_>,
+        segment: std::ops::Range<usize>,
+        text: &crate::text::TextElement<T>,
+        line_metric: &fontdue::LineMetrics,
+    ) {
+        let global_segment = self.text_offset + segment.start..self.text_offset + segment.end;
+        for (run, level) in self.bidi.level_runs(global_segment) {
+            let run = run.start - self.text_offset..run.end - self.text_offset;
+            self.process_level_run(shaper, run, level, text, line_metric);
+        }
+    }
 
-            // Case 2: It doesn't fit on the current line, so push the current line to `lines`.
-            if self.line_buf.is_some() {
-                self.push_line_buffer();
+    /// Splits a run of a single bidi level by font and processes each part.
+    ///
+    /// Characters the element's font has no glyph for are shaped with a
+    /// fallback face resolved by the font storage.
+    fn process_level_run(
+        &mut self,
+        shaper: &shaping::Shaper<'_>,
+        run: std::ops::Range<usize>,
+        level: unicode_bidi::Level,
+        text: &crate::text::TextElement<T>,
+        line_metric: &fontdue::LineMetrics,
+    ) {
+        for (font_run, font_id) in self.itemize_fonts(shaper, run, text) {
+            if font_id == text.font_id {
+                self.process_font_run(shaper, font_run, level, text, font_id, line_metric);
+                continue;
             }
 
-            // Case 3: Try to put the entire fragment sequence on the new empty line.
-            if buffer.width() <= limit_width {
-                self.line_buf = Some(buffer);
-                return;
-            }
+            let fallback = self
+                .font_storage
+                .font(font_id)
+                .zip(self.font_storage.face_data(font_id));
+            let Some((font, face_data)) = fallback else {
+                self.process_font_run(shaper, font_run, level, text, text.font_id, line_metric);
+                continue;
+            };
+            let fallback_metric = font
+                .horizontal_line_metrics(text.font_size)
+                .map_or(*line_metric, |metrics| self.block_metrics(metrics));
 
-            // Case 4: It doesn't fit even on a new line (e.g., a very long word).
-            if !self.config.wrap_hard_break {
-                // If hard break is disabled, we just let it overflow.
-                self.line_buf = Some(buffer);
-                return;
-            }
+            let fallback_shaper = shaping::Shaper::new(&face_data, &font, text.font_size);
+            self.process_font_run(
+                &fallback_shaper,
+                font_run,
+                level,
+                text,
+                font_id,
+                &fallback_metric,
+            );
+        }
+    }
+
+    /// Splits `run` into consecutive sub-runs that can be rendered with a single face.
+    ///
+    /// Characters that attach to their predecessor (marks, joiners, ...) always
+    /// stay in the face of that predecessor so clusters are never split.
+    fn itemize_fonts(
+        &mut self,
+        shaper: &shaping::Shaper<'_>,
+        run: std::ops::Range<usize>,
+        text: &crate::text::TextElement<T>,
+    ) -> Vec<(std::ops::Range<usize>, fontdb::ID)> {
+        let mut runs: Vec<(std::ops::Range<usize>, fontdb::ID)> = Vec::new();
+        for (offset, ch) in text.content[run.clone()].char_indices() {
+            let offset = run.start + offset;
+            let previous = runs.last().map(|(_, font_id)| *font_id);
+
+            let font_id = match previous {
+                Some(previous) if shaping::extends_cluster(ch) => previous,
+                _ if shaper.has_glyph(ch) => text.font_id,
+                Some(previous)
+                    if previous != text.font_id && self.font_storage.has_glyph(previous, ch) =>
+                {
+                    previous
+                }
+                // Keep the element's font (and its notdef glyph) if nothing covers `ch`.
+                _ => self
+                    .font_storage
+                    .fallback_font(text.font_id, ch)
+                    .unwrap_or(text.font_id),
+            };
+
+            match runs.last_mut() {
+                Some((range, last_id)) if *last_id == font_id => range.end = offset + ch.len_utf8(),
+                _ => runs.push((offset..offset + ch.len_utf8(), font_id)),
+            }
+        }
+
+        runs
+    }
+
+    fn process_font_run(
+        &mut self,
+        shaper: &shaping::Shaper<'_>,
+        run: std::ops::Range<usize>,
+        level: unicode_bidi::Level,
+        text: &crate::text::TextElement<T>,
+        font_id: fontdb::ID,
+        line_metric: &fontdue::LineMetrics,
+    ) {
+        let run_offset = self.text_offset + run.start;
+        let run_text = &text.content[run.clone()];
+        let element = self.element_index;
+        let letter_spacing = text.letter_spacing.to_pixels(text.font_size);
+        let word_spacing = text.word_spacing.to_pixels(text.font_size);
+
+        // Raised glyphs move up in horizontal lines and right in vertical ones.
+        let baseline_shift = match self.config.writing_mode {
+            WritingMode::HorizontalTopToBottom => -self.baseline_shift,
+            WritingMode::VerticalRightToLeft => self.baseline_shift,
+        };
+        let mut line_metric = *line_metric;
+        if self.config.baseline_shift_extends_line {
+            line_metric.ascent += self.baseline_shift;
+            line_metric.descent += self.baseline_shift;
+        }
+
+        let context = (
+            text.content[..run.start].chars().next_back(),
+            text.content[run.end..].chars().next(),
+        );
+        let mut clusters = self.place_clusters(
+            shaper,
+            run_text,
+            context,
+            level,
+            font_id,
+            text.synthetic_style,
+        );
+        self.kern_run_start(shaper, &mut clusters, level, font_id);
+
+        for cluster in clusters {
+            if self.full {
+                return;
+            }
+            let Some(ch) = run_text[cluster.range.clone()].chars().next() else {
+                continue;
+            };
+
+            let behavior = layout_utl::classify_char(
+                ch,
+                &self.config.word_separators,
+                &self.config.linebreak_char,
+            );
+
+            // Spacing is part of the advance, so wrapping, alignment and the
+            // final positions all measure the same width.
+            let spacing = match behavior {
+                layout_utl::CharBehavior::WordBreak { .. } => letter_spacing + word_spacing,
+                _ => letter_spacing,
+            };
+
+            // Soft hyphens are invisible unless the word is hyphenated there.
+            let is_soft_hyphen = ch == layout_utl::SOFT_HYPHEN;
+            let create_fragment = || layout_utl::GlyphFragment {
+                ch,
+                glyphs: if is_soft_hyphen {
+                    Vec::new()
+                } else {
+                    cluster.glyphs.clone()
+                },
+                advance: if is_soft_hyphen {
+                    0.0
+                } else {
+                    cluster.advance + spacing
+                },
+                element,
+                source: run.start + cluster.range.start..run.start + cluster.range.end,
+                level,
+                line_metrics: line_metric,
+                font_id,
+                font_size: text.font_size,
+                user_data: text.user_data.clone(),
+                object: None,
+                baseline_shift,
+                ruby: None,
+            };
+
+            match behavior {
+                layout_utl::CharBehavior::WordBreak { render_glyph } => {
+                    // A separator (e.g., space) marks the end of a word.
+                    if let Some(word) = self.word_buf.take() {
+                        self.append_fragments_with_rules(&word, true);
+                    }
+
+                    if render_glyph {
+                        let fragment = create_fragment();
+                        // Append the separator itself (not part of the `word_buf`).
+                        self.append_fragments_with_rules(std::slice::from_ref(&fragment), false);
+                    }
+                }
+                layout_utl::CharBehavior::Regular => {
+                    self.append_regular_fragment(
+                        create_fragment(),
+                        run_offset + cluster.range.start,
+                    );
+                }
+                layout_utl::CharBehavior::LineBreak
+                | layout_utl::CharBehavior::Tab
+                | layout_utl::CharBehavior::Ignore => {
+                    // Line breaks and tabs never reach the shaper.
+                    // Skip control characters or invalid inputs.
+                }
+            }
+        }
+    }
+
+    /// Shapes `text` in the direction of `level` and places its clusters for the
+    /// writing mode. `context` holds the characters around `text`.
+    fn place_clusters(
+        &self,
+        shaper: &shaping::Shaper<'_>,
+        text: &str,
+        context: (Option<char>, Option<char>),
+        level: unicode_bidi::Level,
+        font_id: fontdb::ID,
+        synthetic_style: SyntheticStyle,
+    ) -> Vec<layout_utl::PlacedCluster> {
+        let mut clusters: Vec<layout_utl::PlacedCluster> = match self.config.writing_mode {
+            WritingMode::HorizontalTopToBottom => {
+                let direction = if level.is_rtl() {
+                    rustybuzz::Direction::RightToLeft
+                } else {
+                    rustybuzz::Direction::LeftToRight
+                };
+                shaper
+                    .shape(text, direction)
+                    .into_iter()
+                    .map(|cluster| {
+                        layout_utl::PlacedCluster::horizontal(cluster, font_id, shaper.font_size())
+                    })
+                    .collect()
+            }
+            WritingMode::VerticalRightToLeft => vertical::shape(
+                shaper,
+                text,
+                context,
+                level.is_rtl(),
+                self.config.tate_chu_yoko,
+                font_id,
+            ),
+        };
+
+        for cluster in &mut clusters {
+            cluster.synthesize(synthetic_style, shaper, self.config.writing_mode);
+        }
+        clusters
+    }
+
+    /// Kerns the first cluster of a font run against the last glyph of the
+    /// previous one, and records the last glyph of this run.
+    ///
+    /// The kerning moves the glyphs of the cluster and is added to its advance,
+    /// like the kerning the shaper applies inside a run. Only left-to-right
+    /// horizontal runs are kerned.
+    fn kern_run_start(
+        &mut self,
+        shaper: &shaping::Shaper<'_>,
+        clusters: &mut [layout_utl::PlacedCluster],
+        level: unicode_bidi::Level,
+        font_id: fontdb::ID,
+    ) {
+        let previous = self.kern_from.take();
+        if self.config.writing_mode != WritingMode::HorizontalTopToBottom || level.is_rtl() {
+            return;
+        }
+
+        let font_size = shaper.font_size();
+        if let Some(previous) = previous
+            && previous.font_id == font_id
+            && previous.font_size == font_size
+            && let Some(first) = clusters.first_mut()
+            && let Some(glyph) = first.glyphs.first()
+            && let Some(kern) = shaper.font().horizontal_kern_indexed(
+                previous.glyph_index,
+                glyph.glyph_id.glyph_index(),
+                font_size,
+            )
+        {
+            for glyph in &mut first.glyphs {
+                glyph.x += kern;
+                glyph.ink_end += kern;
+            }
+            first.advance += kern;
+        }
+
+        self.kern_from = clusters
+            .last()
+            .and_then(|cluster| cluster.glyphs.last())
+            .map(|glyph| KernGlyph {
+                font_id,
+                font_size,
+                glyph_index: glyph.glyph_id.glyph_index(),
+            });
+    }
+
+    /// Appends a cluster that is not a separator, `offset` being its position in
+    /// the concatenated text.
+    fn append_regular_fragment(&mut self, fragment: layout_utl::GlyphFragment<T>, offset: usize) {
+        // Kinsoku shori: keep a character that may not start a line with the
+        // previous one, and one that may not end a line with the next one.
+        let kinsoku = &self.config.kinsoku;
+        let keep_together = kinsoku.no_line_start.contains(&fragment.ch)
+            || self
+                .word_buf
+                .as_ref()
+                .and_then(|word| word.last())
+                .is_some_and(|last| kinsoku.no_line_end.contains(&last.ch));
+
+        if matches!(self.config.wrap_style, WrapStyle::CharWrap) {
+            // In CharWrap mode, we treat every cluster as an independent unit.
+            // The word buffer only holds clusters that kinsoku keeps together.
+            if !keep_together && let Some(word) = self.word_buf.take() {
+                self.append_fragments_with_rules(&word, true);
+            }
+            match &mut self.word_buf {
+                Some(buffer) => buffer.push(fragment),
+                None => self.word_buf = Some(vec![fragment]),
+            }
+            return;
+        }
+
+        // In UnicodeWrap mode, a break opportunity before this cluster
+        // ends the current word. Breaks after a soft hyphen are left to
+        // hyphenation, which also inserts the visible hyphen.
+        if self.config.wrap_style == WrapStyle::UnicodeWrap
+            && !keep_together
+            && self.breaks.is_break_before(offset)
+            && self
+                .word_buf
+                .as_ref()
+                .and_then(|word| word.last())
+                .is_none_or(|last| last.ch != layout_utl::SOFT_HYPHEN)
+            && let Some(word) = self.word_buf.take()
+        {
+            self.append_fragments_with_rules(&word, true);
+        }
+
+        // Accumulate clusters into the word buffer until a break occurs.
+        match &mut self.word_buf {
+            Some(buffer) => buffer.push(fragment),
+            None => self.word_buf = Some(vec![fragment]),
+        }
+    }
+
+    fn append_fragments_with_rules(
+        &mut self,
+        fragments: &[layout_utl::GlyphFragment<T>],
+        allow_leading_space: bool,
+    ) {
+        if fragments.is_empty() {
+            return;
+        }
+
+        // Rule: Drop leading spaces if they start a new line.
+        // This prevents lines from looking indented due to a wrapped space.
+        if !allow_leading_space
+            && let Some(first) = fragments.first()
+            && first.ch.is_whitespace()
+            && self
+                .line_buf
+                .as_ref()
+                .map(|line| line.glyphs.is_empty())
+                .unwrap_or(true)
+        {
+            return;
+        }
+
+        // Burasage: punctuation at the end may hang past the end of the line.
+        let hang = fragments
+            .last()
+            .filter(|last| self.config.kinsoku.hangs(last.ch))
+            .map_or(0.0, |last| last.advance);
+
+        self.append_fragments_to_line(fragments, hang);
+    }
+
+    /// Appends fragments to the line buffer, wrapping when they do not fit.
+    ///
+    /// `hang` is the length the fragments may extend past the end of the line.
+    fn append_fragments_to_line(&mut self, fragments: &[layout_utl::GlyphFragment<T>], hang: f32) {
+        if fragments.is_empty() {
+            return;
+        }
+
+        let Some(mut buffer) = layout_utl::LayoutBuffer::from_fragments(fragments) else {
+            return;
+        };
+
+        let wrap = self.config.wrap_style != WrapStyle::NoWrap;
+        if wrap && self.line_limit(&buffer).is_some() {
+            let mut fragments = fragments;
+            let mut hyphenation_points: Option<Vec<usize>> = None;
+
+            loop {
+                // Lines may have different lengths in a shape, so the limit is
+                // looked up again for each of them.
+                let Some(limit) = self.line_limit(&buffer) else {
+                    // The shape has ended and the layout has no width.
+                    match self.line_buf.as_mut() {
+                        Some(current) => current.concat(buffer),
+                        None => self.line_buf = Some(buffer),
+                    }
+                    return;
+                };
+                // Paragraph indents shorten the line.
+                let limit_width = limit - self.indent();
+
+                // Case 1: Try to append the entire fragment sequence to the current line.
+                match self.line_buf.as_mut() {
+                    Some(current)
+                        if current.projected_concat_length(&buffer) <= limit_width + hang =>
+                    {
+                        // It fits!
+                        current.concat(buffer);
+                        return;
+                    }
+                    // Case 3: Try to put the entire fragment sequence on the new empty line.
+                    None if buffer.width() <= limit_width + hang => {
+                        self.line_buf = Some(buffer);
+                        return;
+                    }
+                    _ => {}
+                }
+
+                // Hyphenation: fill the line with the head of the word and a hyphen,
+                // then continue with the rest of the word on the next line.
+                let points =
+                    hyphenation_points.get_or_insert_with(|| self.hyphenation_points(fragments));
+                if let Some((head, split)) = self.hyphenate_to_fit(fragments, points, limit_width) {
+                    match self.line_buf.as_mut() {
+                        Some(current) => current.concat(head),
+                        None => self.line_buf = Some(head),
+                    }
+                    self.push_line_buffer();
+
+                    fragments = &fragments[split..];
+                    points.retain(|&point| point > split);
+                    points.iter_mut().for_each(|point| *point -= split);
+                    buffer = layout_utl::LayoutBuffer::from_fragments(fragments)
+                        .expect("hyphenation never splits after the last fragment");
+                    continue;
+                }
+
+                // Case 2: It doesn't fit on the current line, so push the current line to `lines`.
+                if self.line_buf.is_some() {
+                    self.push_line_buffer();
+                    continue;
+                }
+
+                // In a shape, an interval too narrow for the word is left empty
+                // when the band has another one.
+                if self.flow.as_ref().is_some_and(shape::ShapeFlow::has_spans) {
+                    self.line_placement = None;
+                    continue;
+                }
+
+                break;
+            }
+
+            // Case 4: It doesn't fit even on a new line (e.g., a very long word).
+            if !self.config.wrap_hard_break {
+                // If hard break is disabled, we just let it overflow.
+                self.line_buf = Some(buffer);
+                return;
+            }
 
             // Case 5: Hard break is enabled. We must split the fragment sequence.
             let mut start = 0usize;
             while start < fragments.len() {
+                // Each chunk goes on a new line.
+                self.push_line_buffer();
                 let mut end = start + 1;
                 // Start with the smallest possible chunk (1 char).
-                let mut best = layout_utl::LayoutBuffer::from_fragments(
-                    &fragments[start..end],
-                    self.font_storage,
-                )
-                .expect("fragment slice must not be empty");
+                let mut best = layout_utl::LayoutBuffer::from_fragments(&fragments[start..end])
+                    .expect("fragment slice must not be empty");
+                let limit_width = self.line_limit(&best).unwrap_or(f32::INFINITY) - self.indent();
 
                 // Even a single character might be too wide (edge case).
                 if best.width() > limit_width {
-                    self.push_line_buffer();
                     self.line_buf = Some(best);
                     start = end;
                     continue;
@@ -434,174 +1783,814 @@ impl<'a, T: Clone> LayoutEngine<'a, T> {
 
                 // Greedily extend the chunk as long as it fits.
                 while end < fragments.len() {
-                    let next_buf = layout_utl::LayoutBuffer::from_fragments(
-                        &fragments[end..end + 1],
-                        self.font_storage,
-                    )
-                    .expect("fragment slice must not be empty");
+                    let next_buf =
+                        layout_utl::LayoutBuffer::from_fragments(&fragments[end..end + 1])
+                            .expect("fragment slice must not be empty");
 
-                    let projected = best.projected_concat_length(&next_buf, self.font_storage);
+                    let projected = best.projected_concat_length(&next_buf);
                     if projected > limit_width {
                         // Adding next char would exceed limit, so stop here.
                         break;
                     }
 
-                    best.concat(next_buf, self.font_storage);
+                    best.concat(next_buf);
                     end += 1;
                 }
 
-                // Commit the chunk to a new line.
-                self.push_line_buffer();
+                // Commit the chunk to the new line.
                 self.line_buf = Some(best);
                 start = end;
-
-                // If there are more fragments, force a break for the next iteration.
-                if start < fragments.len() {
-                    self.push_line_buffer();
-                }
             }
         } else {
             // No max width limit (NoWrap mode or unconfigured).
             if let Some(current) = self.line_buf.as_mut() {
-                current.concat(buffer, self.font_storage);
+                current.concat(buffer);
             } else {
                 self.line_buf = Some(buffer);
             }
         }
     }
 
-    fn finalize_line(&mut self, metrics: Option<fontdue::LineMetrics>) {
-        if self.line_buf.is_some() || metrics.is_some() {
-            self.lines.push(LineRecord {
-                buffer: self.line_buf.take(),
-                metrics,
-            });
+    /// Returns the fragment indices a word may be hyphenated before.
+    ///
+    /// Soft hyphens in the word take precedence over the configured patterns.
+    fn hyphenation_points(&self, fragments: &[layout_utl::GlyphFragment<T>]) -> Vec<usize> {
+        if !matches!(
+            self.config.wrap_style,
+            WrapStyle::WordWrap | WrapStyle::UnicodeWrap
+        ) {
+            return Vec::new();
         }
-    }
 
-    fn push_line_buffer(&mut self) {
-        if self.line_buf.is_some() {
-            self.lines.push(LineRecord {
-                buffer: self.line_buf.take(),
-                metrics: None,
-            });
+        let soft_hyphens: Vec<usize> = fragments
+            .iter()
+            .enumerate()
+            .filter(|(index, fragment)| {
+                fragment.ch == layout_utl::SOFT_HYPHEN && index + 1 < fragments.len()
+            })
+            .map(|(index, _)| index + 1)
+            .collect();
+        if !soft_hyphens.is_empty() {
+            return soft_hyphens;
         }
-    }
 
-    fn build_result(self) -> TextLayout<T> {
-        /// Final measurements for a single laid-out line before alignment.
-        struct LineData<T> {
-            width: f32,
-            height: f32,
-            y: f32,
-            glyphs: Vec<GlyphPosition<T>>,
+        // A word spanning several runs is hyphenated in the language of its start.
+        let language = fragments
+            .first()
+            .and_then(|fragment| self.texts[fragment.element].language.as_deref());
+        let Some(hyphenator) = self.config.hyphenator(language) else {
+            return Vec::new();
+        };
+
+        let mut word = String::new();
+        let mut starts = Vec::with_capacity(fragments.len());
+        for fragment in fragments {
+            starts.push(word.len());
+            word.push_str(&self.texts[fragment.element].content[fragment.source.clone()]);
         }
 
-        let mut layout_lines: Vec<LineData<T>> = Vec::new();
-        let mut cursor_y = 0.0;
-        let mut max_line_width: f32 = 0.0;
-        let line_height_scale = self.config.line_height_scale;
+        // Points inside a cluster (e.g. a ligature) can not be used.
+        hyphenator
+            .hyphenate(&word)
+            .into_iter()
+            .filter_map(|offset| starts.binary_search(&offset).ok())
+            .filter(|&index| index > 0)
+            .collect()
+    }
 
-        // Convert the abstract "lines" (buffers) into physical "LineData" (coordinates).
-        for record in self.lines {
-            let (width, ascent, descent, line_gap, glyphs) = if let Some(buffer) = record.buffer {
-                let (ascent, descent, line_gap) = buffer.line_metrics();
-                let width_value = buffer.width();
-                let glyphs = buffer.glyphs;
-                (width_value, ascent, descent, line_gap, glyphs)
-            } else if let Some(metrics) = record.metrics {
-                // Empty line but with valid metrics (e.g., from newline char).
-                (
-                    0.0,
-                    metrics.ascent,
-                    metrics.descent,
-                    metrics.line_gap,
-                    Vec::new(),
-                )
-            } else {
-                // Fallback for completely empty state (should happen rarely).
-                (0.0, 0.0, 0.0, 0.0, Vec::new())
+    /// Finds the longest head of `fragments`, split at one of `points`, that fits on
+    /// the current line together with a hyphen.
+    ///
+    /// Returns the head (hyphen included) and the index of the first fragment of the tail.
+    fn hyphenate_to_fit(
+        &mut self,
+        fragments: &[layout_utl::GlyphFragment<T>],
+        points: &[usize],
+        limit_width: f32,
+    ) -> Option<(layout_utl::LayoutBuffer<T>, usize)> {
+        for &split in points.iter().rev() {
+            let head = &fragments[..split];
+            let Some(mut buffer) = layout_utl::LayoutBuffer::from_fragments(head) else {
+                continue;
+            };
+            if let Some(hyphen) = self.hyphen_fragment(&head[split - 1]) {
+                buffer.push(&hyphen);
+            }
+
+            let width = match &self.line_buf {
+                Some(current) => current.projected_concat_length(&buffer),
+                None => buffer.width(),
             };
+            if width <= limit_width {
+                return Some((buffer, split));
+            }
+        }
 
-            max_line_width = max_line_width.max(width);
-            let raw_line_height = ascent - descent + line_gap;
-            let scaled_line_height = (raw_line_height * line_height_scale).max(0.0);
+        None
+    }
 
-            // Baseline is relative to the *top* of the line box.
-            let baseline = cursor_y + ascent;
+    /// Shapes the hyphen inserted at a hyphenation point, styled like `template`.
+    fn hyphen_fragment(
+        &mut self,
+        template: &layout_utl::GlyphFragment<T>,
+    ) -> Option<layout_utl::GlyphFragment<T>> {
+        let font = self.font_storage.font(template.font_id)?;
+        let face_data = self.font_storage.face_data(template.font_id)?;
+        let shaper = shaping::Shaper::new(&face_data, &font, template.font_size);
 
-            let mut glyph_positions = Vec::with_capacity(glyphs.len());
-            for mut glyph in glyphs {
-                glyph.y += baseline;
-                glyph_positions.push(glyph);
+        // Prefer the unambiguous HYPHEN (U+2010) over HYPHEN-MINUS when the font has it.
+        let hyphen = if shaper.has_glyph('\u{2010}') {
+            "\u{2010}"
+        } else {
+            "-"
+        };
+        let direction = if template.level.is_rtl() {
+            rustybuzz::Direction::RightToLeft
+        } else {
+            rustybuzz::Direction::LeftToRight
+        };
+        let cluster = shaper.shape(hyphen, direction).into_iter().next()?;
+        let mut cluster = match self.config.writing_mode {
+            WritingMode::HorizontalTopToBottom => {
+                layout_utl::PlacedCluster::horizontal(cluster, template.font_id, template.font_size)
             }
+            WritingMode::VerticalRightToLeft => {
+                vertical::place_sideways(cluster, 0, &shaper, template.font_id)
+            }
+        };
+        cluster.synthesize(
+            self.texts[template.element].synthetic_style,
+            &shaper,
+            self.config.writing_mode,
+        );
 
-            cursor_y += scaled_line_height;
+        Some(layout_utl::GlyphFragment {
+            ch: '-',
+            glyphs: cluster.glyphs,
+            advance: cluster.advance,
+            source: template.source.end..template.source.end,
+            object: None,
+            ruby: None,
+            ..template.clone()
+        })
+    }
 
-            layout_lines.push(LineData {
-                width,
-                height: scaled_line_height,
-                y: cursor_y - scaled_line_height,
-                glyphs: glyph_positions,
-            });
+    /// Returns the maximum length of the line being built, placing it in
+    /// [`TextLayoutConfig::shape`] first if needed.
+    ///
+    /// The line is placed with the height of its text so far, or of `buffer`
+    /// when it is empty.
+    fn line_limit(&mut self, buffer: &layout_utl::LayoutBuffer<T>) -> Option<f32> {
+        if self.flow.is_none() {
+            return self.inline_limit();
         }
+        if self.line_placement.is_none() {
+            let (ascent, descent, line_gap) =
+                self.line_buf.as_ref().unwrap_or(buffer).line_metrics();
+            let scale = self
+                .paragraph_style
+                .line_height_scale
+                .unwrap_or(self.config.line_height_scale);
+            let height = ((ascent - descent + line_gap) * scale).max(0.0);
+            self.line_placement = self.place_line(height);
+        }
+        self.line_placement.and_then(|placement| placement.width)
+    }
 
-        let total_height = cursor_y;
-        let total_width = max_line_width;
-
-        let target_width = self.config.max_width.unwrap_or(total_width);
-        let target_height = self.config.max_height.unwrap_or(total_height);
-
-        let vertical_offset = match self.config.vertical_align {
-            VerticalAlign::Top => 0.0,
-            VerticalAlign::Middle => (target_height - total_height) / 2.0,
-            VerticalAlign::Bottom => target_height - total_height,
+    /// Places the next line in [`TextLayoutConfig::shape`].
+    fn place_line(&mut self, height: f32) -> Option<shape::LinePlacement> {
+        let space_before = if self.first_line {
+            self.paragraph_style.space_before
+        } else {
+            0.0
         };
+        Some(self.flow.as_mut()?.place(space_before, height))
+    }
 
-        let mut lines_out = Vec::with_capacity(layout_lines.len());
+    /// Returns the maximum length of a line.
+    fn inline_limit(&self) -> Option<f32> {
+        match self.config.writing_mode {
+            WritingMode::HorizontalTopToBottom => self.config.max_width,
+            WritingMode::VerticalRightToLeft => self.config.max_height,
+        }
+    }
 
-        for mut line in layout_lines {
-            let horizontal_offset = match self.config.horizontal_align {
-                HorizontalAlign::Left => 0.0,
-                HorizontalAlign::Center => (target_width - line.width) / 2.0,
-                HorizontalAlign::Right => target_width - line.width,
-            };
+    /// Returns the maximum extent of the stacked lines.
+    fn block_limit(&self) -> Option<f32> {
+        match self.config.writing_mode {
+            WritingMode::HorizontalTopToBottom => self.config.max_height,
+            WritingMode::VerticalRightToLeft => self.config.max_width,
+        }
+    }
 
-            if horizontal_offset != 0.0 {
-                for glyph in &mut line.glyphs {
-                    glyph.x += horizontal_offset;
+    /// Adapts the line metrics of a font to the writing mode.
+    ///
+    /// Vertical lines are centered on the glyphs, so the ascent and descent are
+    /// split evenly around the column center.
+    fn block_metrics(&self, metrics: fontdue::LineMetrics) -> fontdue::LineMetrics {
+        match self.config.writing_mode {
+            WritingMode::HorizontalTopToBottom => metrics,
+            WritingMode::VerticalRightToLeft => {
+                let half = (metrics.ascent - metrics.descent) / 2.0;
+                fontdue::LineMetrics {
+                    ascent: half,
+                    descent: -half,
+                    ..metrics
                 }
             }
+        }
+    }
 
-            if vertical_offset != 0.0 {
-                for glyph in &mut line.glyphs {
-                    glyph.y += vertical_offset;
-                }
-            }
+    /// Drops the lines beyond [`TextLayoutConfig::max_lines`] or the layout box
+    /// and appends an ellipsis to the last visible line if requested.
+    ///
+    /// Returns whether the text was truncated.
+    fn truncate_lines(&mut self) -> bool {
+        let mut visible = self.lines.len();
+        if let Some(max_lines) = self.config.max_lines {
+            visible = visible.min(max_lines);
+        }
+        if self.config.overflow != TextOverflow::Visible
+            && let Some(limit) = self.block_limit()
+        {
+            let mut extent = 0.0;
+            let fitting = self
+                .lines
+                .iter()
+                .take_while(|record| {
+                    let (before, after) = record.spacing();
+                    let top = record
+                        .placement
+                        .map_or(extent + before, |placement| placement.top);
+                    extent = top + record.line_height(self.config);
+                    let fits = extent <= limit;
+                    extent += after;
+                    fits
+                })
+                .count();
+            visible = visible.min(fitting);
+        }
 
-            lines_out.push(TextLayoutLine {
-                line_height: line.height,
-                line_width: line.width,
-                top: line.y + vertical_offset,
-                bottom: line.y + vertical_offset + line.height,
-                glyphs: line.glyphs,
-            });
+        let dropped = visible < self.lines.len();
+        self.lines.truncate(visible);
+        if self.config.overflow != TextOverflow::Ellipsis {
+            return dropped;
         }
 
-        TextLayout {
-            config: self.config.clone(),
-            total_height,
-            total_width,
-            lines: lines_out,
+        let inline_limit = self.inline_limit();
+        let overflowing = self.lines.last().is_some_and(|record| {
+            record
+                .buffer
+                .as_ref()
+                .zip(record.inline_limit(inline_limit))
+                .is_some_and(|(buffer, limit)| buffer.width() - record.hang > limit - record.indent)
+        });
+        if !dropped && !overflowing {
+            return false;
         }
+
+        self.append_ellipsis();
+        true
     }
-}
 
-mod layout_utl {
-    use crate::font_storage::FontStorage;
+    /// Replaces the end of the last line with an ellipsis that fits within the line length.
+    fn append_ellipsis(&mut self) {
+        let Some(last) = self.lines.last() else {
+            return;
+        };
+        let paragraph_level = last.paragraph_level;
 
-    use super::*;
-    use std::sync::Arc;
+        // The ellipsis is styled like the text it replaces.
+        let element = self
+            .lines
+            .iter()
+            .rev()
+            .filter_map(|record| record.buffer.as_ref())
+            .flat_map(|buffer| buffer.clusters.iter().rev())
+            .find(|cluster| !cluster.glyphs.is_empty())
+            .map_or(0, |cluster| cluster.element);
+        let fragments = self.ellipsis_fragments(element, paragraph_level);
+        let Some(ellipsis) = layout_utl::LayoutBuffer::from_fragments(&fragments) else {
+            return;
+        };
+
+        let limit = self.inline_limit();
+        let Some(record) = self.lines.last_mut() else {
+            return;
+        };
+        let limit = record
+            .inline_limit(limit)
+            .map(|limit| limit - record.indent);
+        let Some(buffer) = record.buffer.as_mut() else {
+            record.buffer = Some(ellipsis);
+            return;
+        };
+
+        // Drop clusters from the end until the ellipsis fits, without leaving
+        // whitespace in front of it.
+        let mut keep = buffer.clusters.len();
+        loop {
+            while keep > 0 && buffer.clusters[keep - 1].whitespace {
+                keep -= 1;
+            }
+            let end = keep.checked_sub(1).map_or(0.0, |index| {
+                buffer.clusters[index].x + buffer.clusters[index].advance
+            });
+            if keep == 0 || limit.is_none_or(|limit| end + ellipsis.width() <= limit) {
+                break;
+            }
+            keep -= 1;
+        }
+
+        buffer.truncate(keep);
+        if let Some(last) = buffer.clusters.iter().rev().find(|c| !c.source.is_empty()) {
+            record.source.end = TextPosition {
+                element: last.element,
+                offset: last.source.end,
+            };
+        } else {
+            record.source.end = record.source.start;
+        }
+        buffer.concat(ellipsis);
+    }
+
+    /// Shapes an ellipsis in the font of the element at `element`.
+    ///
+    /// Falls back to another face for "…" and then to three full stops when
+    /// the element's font has no ellipsis glyph.
+    fn ellipsis_fragments(
+        &mut self,
+        element: usize,
+        level: unicode_bidi::Level,
+    ) -> Vec<layout_utl::GlyphFragment<T>> {
+        let Some(text) = self.texts.get(element) else {
+            return Vec::new();
+        };
+
+        let (ellipsis, font_id) = if self.font_storage.has_glyph(text.font_id, '…') {
+            ("…", text.font_id)
+        } else if let Some(fallback) = self.font_storage.fallback_font(text.font_id, '…') {
+            ("…", fallback)
+        } else {
+            ("...", text.font_id)
+        };
+
+        let Some(font) = self.font_storage.font(font_id) else {
+            return Vec::new();
+        };
+        let Some(face_data) = self.font_storage.face_data(font_id) else {
+            return Vec::new();
+        };
+        let Some(line_metric) = font.horizontal_line_metrics(text.font_size) else {
+            return Vec::new();
+        };
+        let line_metrics = self.block_metrics(line_metric);
+        let shaper = shaping::Shaper::new(&face_data, &font, text.font_size);
+
+        let mut clusters: Vec<layout_utl::PlacedCluster> = match self.config.writing_mode {
+            WritingMode::HorizontalTopToBottom => {
+                let direction = if level.is_rtl() {
+                    rustybuzz::Direction::RightToLeft
+                } else {
+                    rustybuzz::Direction::LeftToRight
+                };
+                shaper
+                    .shape(ellipsis, direction)
+                    .into_iter()
+                    .map(|cluster| {
+                        layout_utl::PlacedCluster::horizontal(cluster, font_id, text.font_size)
+                    })
+                    .collect()
+            }
+            WritingMode::VerticalRightToLeft => {
+                vertical::shape(&shaper, ellipsis, (None, None), level.is_rtl(), 0, font_id)
+            }
+        };
+
+        for cluster in &mut clusters {
+            cluster.synthesize(text.synthetic_style, &shaper, self.config.writing_mode);
+        }
+
+        let end = text.content.len();
+        clusters
+            .into_iter()
+            .map(|cluster| layout_utl::GlyphFragment {
+                ch: '…',
+                glyphs: cluster.glyphs,
+                advance: cluster.advance,
+                level,
+                element,
+                source: end..end,
+                line_metrics,
+                font_id,
+                font_size: text.font_size,
+                user_data: text.user_data.clone(),
+                object: None,
+                baseline_shift: 0.0,
+                ruby: None,
+            })
+            .collect()
+    }
+
+    fn finalize_line(&mut self, metrics: Option<fontdue::LineMetrics>) {
+        if self.line_buf.is_some() || metrics.is_some() {
+            let buffer = self.line_buf.take();
+            self.push_record(buffer, metrics, LineBreakKind::Hard);
+        }
+    }
+
+    fn push_line_buffer(&mut self) {
+        if self.line_buf.is_some() {
+            let buffer = self.line_buf.take();
+            self.push_record(buffer, None, LineBreakKind::Soft);
+        }
+    }
+
+    /// Appends a line record and ends the source range of the previous line where it starts.
+    fn push_record(
+        &mut self,
+        mut buffer: Option<layout_utl::LayoutBuffer<T>>,
+        metrics: Option<fontdue::LineMetrics>,
+        break_kind: LineBreakKind,
+    ) {
+        if let Some(buffer) = buffer.as_mut() {
+            let texts = self.texts;
+            buffer.align_tabs(|cluster, ch| {
+                texts[cluster.element].content[cluster.source.clone()].contains(ch)
+            });
+        }
+
+        // Lines after a hard break start right after it. Wrapped lines start at
+        // their first cluster, so whitespace dropped at the break belongs to the
+        // previous line.
+        let start = match self.lines.last() {
+            Some(previous) if previous.break_kind == LineBreakKind::Soft => buffer
+                .as_ref()
+                .and_then(|buffer| {
+                    buffer
+                        .clusters
+                        .iter()
+                        .find(|cluster| !cluster.source.is_empty())
+                })
+                .map_or(previous.source.start, |cluster| TextPosition {
+                    element: cluster.element,
+                    offset: cluster.source.start,
+                }),
+            _ => self.line_start,
+        };
+        if let Some(previous) = self.lines.last_mut() {
+            previous.source.end = start;
+        }
+
+        let kinsoku = &self.config.kinsoku;
+        let texts = self.texts;
+        let hang = buffer
+            .as_ref()
+            .and_then(|buffer| {
+                buffer
+                    .clusters
+                    .iter()
+                    .rev()
+                    .find(|cluster| !cluster.whitespace)
+            })
+            .filter(|cluster| {
+                texts[cluster.element].content[cluster.source.clone()]
+                    .chars()
+                    .next()
+                    .is_some_and(|ch| kinsoku.hangs(ch))
+            })
+            .map_or(0.0, |cluster| cluster.advance);
+
+        let mut record = LineRecord {
+            buffer,
+            metrics,
+            paragraph_level: self.paragraph_level,
+            break_kind,
+            source: start..start,
+            style: self.paragraph_style,
+            paragraph_start: self.first_line,
+            indent: self.indent(),
+            hang,
+            placement: None,
+        };
+
+        // Lines that never needed their length (empty or unwrapped lines) are
+        // placed in the shape now.
+        let height = record.line_height(self.config);
+        record.placement = match self.line_placement.take() {
+            Some(placement) => Some(placement),
+            None => self.place_line(height),
+        };
+        if let Some((flow, placement)) = self.flow.as_mut().zip(record.placement) {
+            let (_, space_after) = record.spacing();
+            flow.finish_line(
+                placement.top + height,
+                space_after,
+                break_kind != LineBreakKind::Soft,
+            );
+        }
+
+        // Lines are dropped from the first one `truncate_lines` finds past the
+        // end, so nothing after it needs to be laid out.
+        let (space_before, space_after) = record.spacing();
+        let top = record
+            .placement
+            .map_or(self.extent + space_before, |placement| placement.top);
+        self.extent = top + height + space_after;
+        self.full |= self.config.overflow != TextOverflow::Visible
+            && self.block_limit().is_some_and(|limit| top + height > limit);
+        self.full |= self
+            .config
+            .max_lines
+            .is_some_and(|max_lines| self.lines.len() >= max_lines);
+
+        self.lines.push(record);
+        self.first_line = break_kind != LineBreakKind::Soft;
+    }
+
+    fn build_result(self, truncated: bool) -> TextLayout<T> {
+        /// Final measurements for a single laid-out line before alignment.
+        struct LineData<T> {
+            width: f32,
+            height: f32,
+            y: f32,
+            baseline: f32,
+            rtl: bool,
+            indent: f32,
+            placement: Option<shape::LinePlacement>,
+            align: HorizontalAlign,
+            source: std::ops::Range<TextPosition>,
+            break_kind: LineBreakKind,
+            glyphs: Vec<GlyphPosition<T>>,
+            clusters: Vec<ClusterPosition>,
+            objects: Vec<InlineObjectPosition<T>>,
+            decorations: Vec<DecorationPosition<T>>,
+        }
+
+        let mut layout_lines: Vec<LineData<T>> = Vec::new();
+        let mut cursor_y = 0.0;
+        let mut max_line_width: f32 = 0.0;
+
+        let inline_limit = self.inline_limit();
+        let block_limit = self.block_limit();
+
+        // Justified lines are stretched to the layout width, or to the widest line.
+        let justify_width = inline_limit.unwrap_or_else(|| {
+            self.lines
+                .iter()
+                .filter_map(|record| Some(record.buffer.as_ref()?.width() + record.indent))
+                .fold(0.0, f32::max)
+        });
+
+        let texts = self.texts;
+        let decoration_strokes = &self.decoration_strokes;
+
+        // Convert the abstract "lines" (buffers) into physical "LineData" (coordinates).
+        for record in self.lines {
+            let (ascent, _, _) = record.metrics();
+            let (space_before, space_after) = record.spacing();
+            let scaled_line_height = record.line_height(self.config);
+            let align = record
+                .style
+                .horizontal_align
+                .unwrap_or(self.config.horizontal_align);
+            let line_limit = record
+                .inline_limit(Some(justify_width))
+                .unwrap_or(justify_width);
+            let (width, glyphs, clusters, objects, decorations) = if let Some(mut buffer) =
+                record.buffer
+            {
+                if align == HorizontalAlign::Justify && record.break_kind == LineBreakKind::Soft {
+                    // A line with hanging punctuation is stretched up to the
+                    // punctuation, which stays past the end.
+                    let mut target = line_limit - record.indent;
+                    if buffer.width() > target {
+                        target += record.hang;
+                    }
+                    buffer.justify(target, self.config.justify_inter_character);
+                }
+                buffer.reorder_visual(record.paragraph_level);
+
+                let clusters = buffer
+                    .clusters
+                    .iter()
+                    .map(|cluster| ClusterPosition {
+                        element: cluster.element,
+                        source: cluster.source.clone(),
+                        offset: cluster.x,
+                        advance: cluster.advance,
+                        rtl: cluster.level.is_rtl(),
+                    })
+                    .collect();
+                // Objects are placed relative to the line origin like glyphs:
+                // along the line and from the baseline (column center) across it.
+                let objects = buffer
+                    .clusters
+                    .iter()
+                    .filter_map(|cluster| {
+                        let object = cluster.object?;
+                        let across = match self.config.writing_mode {
+                            WritingMode::HorizontalTopToBottom => -object.ascent,
+                            WritingMode::VerticalRightToLeft => -object.width / 2.0,
+                        };
+                        Some(InlineObjectPosition {
+                            element: cluster.element,
+                            x: cluster.x,
+                            y: across,
+                            width: object.width,
+                            height: object.ascent + object.descent,
+                            user_data: texts[cluster.element].user_data.clone(),
+                        })
+                    })
+                    .collect();
+                let decorations = buffer
+                    .element_spans()
+                    .into_iter()
+                    .flat_map(|(element, span)| {
+                        decoration_strokes[element]
+                            .iter()
+                            .map(move |stroke| DecorationPosition {
+                                kind: stroke.kind,
+                                element,
+                                x: span.start,
+                                y: stroke.across,
+                                width: span.end - span.start,
+                                height: stroke.thickness,
+                                user_data: texts[element].user_data.clone(),
+                            })
+                    })
+                    .collect();
+                (
+                    buffer.width(),
+                    buffer.glyphs,
+                    clusters,
+                    objects,
+                    decorations,
+                )
+            } else {
+                (0.0, Vec::new(), Vec::new(), Vec::new(), Vec::new())
+            };
+
+            let left = record.placement.map_or(0.0, |placement| placement.left);
+            max_line_width = max_line_width.max(left + width + record.indent);
+            // Lines placed in a shape may share a band, so the extent is the
+            // lowest line bottom.
+            let top = record
+                .placement
+                .map_or(cursor_y + space_before, |placement| placement.top);
+            cursor_y = cursor_y.max(top + scaled_line_height);
+
+            layout_lines.push(LineData {
+                width,
+                height: scaled_line_height,
+                y: top,
+                // Baseline is relative to the *top* of the line box.
+                baseline: top + ascent,
+                rtl: record.paragraph_level.is_rtl(),
+                indent: record.indent,
+                placement: record.placement,
+                align,
+                source: record.source,
+                break_kind: record.break_kind,
+                glyphs,
+                clusters,
+                objects,
+                decorations,
+            });
+            cursor_y += space_after;
+        }
+
+        // Lines are measured along the inline axis and stacked along the block axis.
+        let total_block = cursor_y;
+        let total_inline = max_line_width;
+
+        let vertical_mode = self.config.writing_mode == WritingMode::VerticalRightToLeft;
+        let (total_width, total_height) = if vertical_mode {
+            (total_block, total_inline)
+        } else {
+            (total_inline, total_block)
+        };
+
+        let target_inline = inline_limit.unwrap_or(total_inline);
+        let target_block = block_limit.unwrap_or(total_block);
+
+        let block_offset = match self.config.vertical_align {
+            VerticalAlign::Top => 0.0,
+            VerticalAlign::Middle => (target_block - total_block) / 2.0,
+            VerticalAlign::Bottom => target_block - total_block,
+        };
+
+        let mut lines_out = Vec::with_capacity(layout_lines.len());
+
+        for mut line in layout_lines {
+            // Lines placed in a shape are aligned within their interval.
+            let (left, span) = line.placement.map_or((0.0, target_inline), |placement| {
+                (placement.left, placement.width.unwrap_or(target_inline))
+            });
+            // The indent is taken from the start edge of the paragraph.
+            let available = span - line.indent;
+            let start = left + if line.rtl { 0.0 } else { line.indent };
+            let inline_offset = start
+                + match (line.align, line.rtl) {
+                    (HorizontalAlign::Left, _)
+                    | (HorizontalAlign::Start | HorizontalAlign::Justify, false)
+                    | (HorizontalAlign::End, true) => 0.0,
+                    (HorizontalAlign::Center, _) => (available - line.width) / 2.0,
+                    (HorizontalAlign::Right, _)
+                    | (HorizontalAlign::Start | HorizontalAlign::Justify, true)
+                    | (HorizontalAlign::End, false) => available - line.width,
+                };
+
+            let layout_line = if vertical_mode {
+                // Columns are stacked from the right edge and glyphs are placed
+                // around the column center.
+                for cluster in &mut line.clusters {
+                    cluster.offset += inline_offset;
+                }
+
+                let right = target_block - (line.y + block_offset);
+                let center = target_block - (line.baseline + block_offset);
+                for glyph in &mut line.glyphs {
+                    let (along, across) = (glyph.x, glyph.y);
+                    glyph.x = center + across;
+                    glyph.y = inline_offset + along;
+                }
+                for object in &mut line.objects {
+                    let (along, across) = (object.x, object.y);
+                    object.x = center + across;
+                    object.y = inline_offset + along;
+                }
+                for decoration in &mut line.decorations {
+                    let (along, across) = (decoration.x, decoration.y);
+                    decoration.x = center + across;
+                    decoration.y = inline_offset + along;
+                    std::mem::swap(&mut decoration.width, &mut decoration.height);
+                }
+
+                TextLayoutLine {
+                    line_height: line.height,
+                    line_width: line.width,
+                    top: inline_offset,
+                    bottom: inline_offset + line.width,
+                    left: right - line.height,
+                    right,
+                    source: line.source,
+                    break_kind: line.break_kind,
+                    glyphs: line.glyphs,
+                    clusters: line.clusters,
+                    objects: line.objects,
+                    decorations: line.decorations,
+                }
+            } else {
+                for glyph in &mut line.glyphs {
+                    glyph.x += inline_offset;
+                    glyph.y += line.baseline + block_offset;
+                }
+                for cluster in &mut line.clusters {
+                    cluster.offset += inline_offset;
+                }
+                for object in &mut line.objects {
+                    object.x += inline_offset;
+                    object.y += line.baseline + block_offset;
+                }
+                for decoration in &mut line.decorations {
+                    decoration.x += inline_offset;
+                    decoration.y += line.baseline + block_offset;
+                }
+
+                TextLayoutLine {
+                    line_height: line.height,
+                    line_width: line.width,
+                    top: line.y + block_offset,
+                    bottom: line.y + block_offset + line.height,
+                    left: inline_offset,
+                    right: inline_offset + line.width,
+                    source: line.source,
+                    break_kind: line.break_kind,
+                    glyphs: line.glyphs,
+                    clusters: line.clusters,
+                    objects: line.objects,
+                    decorations: line.decorations,
+                }
+            };
+
+            lines_out.push(layout_line);
+        }
+
+        TextLayout {
+            config: self.config.clone(),
+            total_height,
+            total_width,
+            lines: lines_out,
+            truncated,
+        }
+    }
+}
+
+mod layout_utl {
+    use super::shaping::ShapedCluster;
+    use super::*;
+    use std::ops::Range;
 
     /// Defines how a character should be handled during layout.
     pub enum CharBehavior {
@@ -617,6 +2606,12 @@ mod layout_utl {
         Ignore,
     }
 
+    /// Invisible hyphenation point (U+00AD).
+    pub const SOFT_HYPHEN: char = '\u{AD}';
+
+    /// Character standing for an inline object (U+FFFC).
+    pub const OBJECT_REPLACEMENT: char = '\u{FFFC}';
+
     /// Classifies a character to determine its layout behavior.
     pub fn classify_char(
         ch: char,
@@ -646,26 +2641,156 @@ mod layout_utl {
     }
 
     #[derive(Clone)]
-    /// Precomputed glyph data used to build layout buffers.
+    /// Shaped cluster data used to build layout buffers.
     ///
-    /// Storing the font handle allows kerning to be applied without repeatedly
-    /// fetching the same font from storage.
+    /// A fragment is the smallest unit the wrapping logic moves around. Its
+    /// glyph offsets and advance already include the shaper's kerning and mark
+    /// positioning as well as letter and word spacing, so buffers can be joined
+    /// without consulting the font again.
     pub struct GlyphFragment<T> {
+        /// First character of the cluster, used for classification.
         pub ch: char,
-        pub glyph_idx: u16,
-        pub metrics: fontdue::Metrics,
+        pub glyphs: Vec<PlacedGlyph>,
+        pub advance: f32,
+        /// Resolved bidi embedding level.
+        pub level: unicode_bidi::Level,
+        /// Index of the source [`TextElement`](crate::text::TextElement).
+        pub element: usize,
+        /// Byte range of the cluster inside the element's content.
+        pub source: Range<usize>,
         pub line_metrics: fontdue::LineMetrics,
         pub font_id: fontdb::ID,
         pub font_size: f32,
-        pub font: Arc<fontdue::Font>,
         pub user_data: T,
+        /// Inline object laid out in place of glyphs.
+        pub object: Option<InlineObject>,
+        /// Offset added to [`PlacedGlyph::y`] of the glyphs.
+        pub baseline_shift: f32,
+        /// Ruby annotation laid out with the glyphs.
+        pub ruby: Option<RubyGlyphs<T>>,
+    }
+
+    /// Glyphs of a ruby annotation, placed like the glyphs of its base.
+    #[derive(Clone)]
+    pub struct RubyGlyphs<T> {
+        pub glyphs: Vec<PlacedGlyph>,
+        pub user_data: T,
+    }
+
+    /// Decoration line of a text run.
+    #[derive(Clone, Copy, Debug)]
+    pub struct DecorationStroke {
+        pub kind: DecorationKind,
+        /// Offset of the line from the baseline (column center), in the
+        /// direction of [`PlacedGlyph::y`].
+        pub across: f32,
+        pub thickness: f32,
+    }
+
+    /// Glyph of a cluster, positioned for the writing mode of the layout.
+    #[derive(Clone, Copy, Debug)]
+    pub struct PlacedGlyph {
+        pub glyph_id: GlyphId,
+        /// Offset of the top-left corner of the glyph bitmap along the line,
+        /// from the cluster origin.
+        pub x: f32,
+        /// Offset of the top-left corner of the glyph bitmap across the line:
+        /// downwards from the baseline for horizontal lines, rightwards from the
+        /// center for vertical ones.
+        pub y: f32,
+        /// End of the glyph's ink along the line, from the cluster origin.
+        pub ink_end: f32,
+        /// Whether the glyph is drawn from a color image.
+        pub color: bool,
+    }
+
+    /// Shaped cluster whose glyphs have been placed for the writing mode.
+    pub struct PlacedCluster {
+        /// Byte range of the source characters, relative to the shaped string.
+        pub range: Range<usize>,
+        pub glyphs: Vec<PlacedGlyph>,
+        /// Advance along the line.
+        pub advance: f32,
+    }
+
+    impl PlacedCluster {
+        /// Places a cluster on a horizontal baseline.
+        pub fn horizontal(cluster: ShapedCluster, font_id: fontdb::ID, font_size: f32) -> Self {
+            let glyphs = cluster
+                .glyphs
+                .iter()
+                .map(|glyph| {
+                    let metrics = &glyph.metrics;
+                    let left = glyph.x + metrics.xmin as f32;
+                    PlacedGlyph {
+                        glyph_id: GlyphId::new(font_id, glyph.glyph_idx, font_size),
+                        x: left,
+                        y: -(glyph.y + metrics.ymin as f32 + metrics.height as f32),
+                        ink_end: left + metrics.width as f32,
+                        color: glyph.color,
+                    }
+                })
+                .collect();
+
+            Self {
+                range: cluster.range,
+                glyphs,
+                advance: cluster.advance,
+            }
+        }
+
+        /// Marks the glyphs for synthetic bold and oblique, moves them to where
+        /// the transformed bitmaps start and widens the advance for bold.
+        pub fn synthesize(
+            &mut self,
+            style: SyntheticStyle,
+            shaper: &shaping::Shaper<'_>,
+            writing_mode: WritingMode,
+        ) {
+            if style == SyntheticStyle::default() {
+                return;
+            }
+
+            let font = shaper.font();
+            let mut emboldened = false;
+            for glyph in &mut self.glyphs {
+                // Color images are drawn as they are.
+                if glyph.color {
+                    continue;
+                }
+                emboldened |= style.bold;
+                glyph.glyph_id = glyph
+                    .glyph_id
+                    .with_synthetic_bold(style.bold)
+                    .with_synthetic_oblique(style.oblique);
+                let [dx, dy] = glyph.glyph_id.synthetic_offset(font);
+                let metrics = glyph.glyph_id.metrics(font);
+                // Vertical lines run along the y axis of the bitmaps.
+                let length = match writing_mode {
+                    WritingMode::HorizontalTopToBottom => {
+                        glyph.x += dx;
+                        glyph.y += dy;
+                        metrics.width
+                    }
+                    WritingMode::VerticalRightToLeft => {
+                        glyph.x += dy;
+                        glyph.y += dx;
+                        metrics.height
+                    }
+                };
+                glyph.ink_end = glyph.x + length as f32;
+            }
+
+            if emboldened {
+                self.advance += shaper.font_size() * crate::glyph_id::SYNTHETIC_BOLD_STRENGTH;
+            }
+        }
     }
 
     /// Buffer of glyph positions with origin located on the baseline.
     ///
     /// Layout buffers are concatenated as new fragments are processed, letting
-    /// us calculate kerning-aware widths before the final glyph positions are
-    /// produced.
+    /// us calculate widths before the final glyph positions are produced.
     pub struct LayoutBuffer<T> {
         pub instance_length: f32,
 
@@ -673,16 +2798,31 @@ mod layout_utl {
         pub max_descent: f32,
         pub max_line_gap: f32,
 
-        pub first_glyph: Option<u16>,
-        pub first_font_id: Option<fontdb::ID>,
-        pub first_font_size: Option<f32>,
-        pub last_glyph: Option<u16>,
-        pub last_font_id: Option<fontdb::ID>,
-        pub last_font_size: Option<f32>,
-        pub last_metrics: Option<fontdue::Metrics>,
         pub next_origin_x: f32,
 
         pub glyphs: Vec<GlyphPosition<T>>,
+        /// Clusters in logical order, used for visual reordering.
+        pub clusters: Vec<ClusterRecord>,
+    }
+
+    /// Placement of a single cluster (or tab gap) inside a [`LayoutBuffer`].
+    pub struct ClusterRecord {
+        /// Indices of the cluster's glyphs in [`LayoutBuffer::glyphs`].
+        pub glyphs: Range<usize>,
+        /// Origin of the cluster relative to the buffer origin.
+        pub x: f32,
+        pub advance: f32,
+        pub level: unicode_bidi::Level,
+        /// Index of the source [`TextElement`](crate::text::TextElement).
+        pub element: usize,
+        /// Byte range of the cluster inside the element's content.
+        pub source: Range<usize>,
+        /// Whitespace is reset to the paragraph level at the end of a line (UAX #9, L1).
+        pub whitespace: bool,
+        /// Stop reached by a tab gap.
+        pub tab_stop: Option<TabStop>,
+        /// Inline object occupying the cluster.
+        pub object: Option<InlineObject>,
     }
 
     impl<T: Clone> LayoutBuffer<T> {
@@ -693,177 +2833,190 @@ mod layout_utl {
                 max_accent: line_metrics.ascent,
                 max_descent: line_metrics.descent,
                 max_line_gap: line_metrics.line_gap,
-                first_glyph: None,
-                first_font_id: None,
-                first_font_size: None,
-                last_glyph: None,
-                last_font_id: None,
-                last_font_size: None,
-                last_metrics: None,
                 next_origin_x: 0.0,
                 glyphs: vec![],
+                clusters: vec![],
             }
         }
 
-        /// Creates a buffer containing a single glyph fragment.
+        /// Appends a shaped cluster to the buffer, updating metrics.
         ///
-        /// The glyph is stored relative to the baseline so it can be shifted
+        /// The glyphs are stored relative to the baseline so they can be shifted
         /// after all fragments for the line are known.
-        pub fn new(
-            glyph_idx: u16,
-            metrics: &fontdue::Metrics,
-            line_metrics: &fontdue::LineMetrics,
-            font_id: fontdb::ID,
-            font_size: f32,
-            user_data: T,
-        ) -> Self {
-            let mut buffer = Self {
-                instance_length: metrics.width as f32 + metrics.xmin as f32,
-                max_accent: line_metrics.ascent,
-                max_descent: line_metrics.descent,
-                max_line_gap: line_metrics.line_gap,
-                first_glyph: Some(glyph_idx),
-                first_font_id: Some(font_id),
-                first_font_size: Some(font_size),
-                last_glyph: Some(glyph_idx),
-                last_font_id: Some(font_id),
-                last_font_size: Some(font_size),
-                last_metrics: Some(*metrics),
-                next_origin_x: metrics.advance_width,
-                glyphs: vec![],
+        pub fn push(&mut self, fragment: &GlyphFragment<T>) {
+            let origin_x = self.next_origin_x;
+            let first_glyph = self.glyphs.len();
+
+            // An inline object is opaque over its whole advance.
+            let mut instance_length = match fragment.object {
+                Some(_) => origin_x + fragment.advance,
+                None => origin_x,
             };
+            let ruby = fragment
+                .ruby
+                .iter()
+                .flat_map(|ruby| ruby.glyphs.iter().map(|glyph| (glyph, &ruby.user_data)));
+            for (glyph, user_data) in fragment
+                .glyphs
+                .iter()
+                .map(|glyph| (glyph, &fragment.user_data))
+                .chain(ruby)
+            {
+                instance_length = instance_length.max(origin_x + glyph.ink_end);
 
-            buffer.glyphs.push(GlyphPosition {
-                glyph_id: GlyphId::new(font_id, glyph_idx, font_size),
-                x: metrics.xmin as f32,
-                y: -(metrics.ymin as f32 + metrics.height as f32),
-                user_data,
+                self.glyphs.push(GlyphPosition {
+                    glyph_id: glyph.glyph_id,
+                    x: origin_x + glyph.x,
+                    y: glyph.y + fragment.baseline_shift,
+                    element: fragment.element,
+                    source: fragment.source.clone(),
+                    user_data: user_data.clone(),
+                });
+            }
+
+            self.clusters.push(ClusterRecord {
+                glyphs: first_glyph..self.glyphs.len(),
+                x: origin_x,
+                advance: fragment.advance,
+                level: fragment.level,
+                element: fragment.element,
+                source: fragment.source.clone(),
+                whitespace: fragment.ch.is_whitespace(),
+                tab_stop: None,
+                object: fragment.object,
             });
 
-            buffer
+            self.instance_length = instance_length;
+            self.max_accent = self.max_accent.max(fragment.line_metrics.ascent);
+            // Descents are negative, the deepest one is the smallest.
+            self.max_descent = self.max_descent.min(fragment.line_metrics.descent);
+            self.max_line_gap = self.max_line_gap.max(fragment.line_metrics.line_gap);
+            self.next_origin_x = origin_x + fragment.advance;
         }
 
-        /// Appends another glyph to the buffer, updating metrics and kerning.
-        ///
-        /// The kerning calculation uses the provided font handle when the
-        /// previous and new glyph share the same font and size. This keeps the
-        /// layout accurate while avoiding redundant lookups.
-        pub fn push(
+        /// Appends empty space (e.g. up to a tab stop) without any glyphs.
+        pub fn push_gap(
             &mut self,
-            glyph_idx: u16,
-            metrics: &fontdue::Metrics,
-            line_metrics: &fontdue::LineMetrics,
-            font: &fontdue::Font,
-            font_id: fontdb::ID,
-            font_size: f32,
-            user_data: T,
-            _font_storage: &mut FontStorage,
+            advance: f32,
+            level: unicode_bidi::Level,
+            element: usize,
+            source: Range<usize>,
         ) {
-            let kerning = if let (Some(last_id), Some(last_size), Some(last_glyph)) =
-                (self.last_font_id, self.last_font_size, self.last_glyph)
-                && last_id == font_id
-                && (last_size - font_size).abs() < f32::EPSILON
-            {
-                font.horizontal_kern_indexed(last_glyph, glyph_idx, font_size)
-                    .unwrap_or(0.0)
-            } else {
-                0.0
-            };
-
-            let current_origin_x = self.next_origin_x + kerning;
-            let new_next_origin_x = current_origin_x + metrics.advance_width;
-
-            self.instance_length = current_origin_x + metrics.width as f32 + metrics.xmin as f32;
-            self.max_accent = self.max_accent.max(line_metrics.ascent);
-            self.max_descent = self.max_descent.max(line_metrics.descent);
-            self.max_line_gap = self.max_line_gap.max(line_metrics.line_gap);
-
-            if self.first_glyph.is_none() {
-                self.first_glyph = Some(glyph_idx);
-                self.first_font_id = Some(font_id);
-                self.first_font_size = Some(font_size);
-            }
-
-            self.last_glyph = Some(glyph_idx);
-            self.last_font_id = Some(font_id);
-            self.last_font_size = Some(font_size);
-            self.last_metrics = Some(*metrics);
-            self.next_origin_x = new_next_origin_x;
-            self.glyphs.push(GlyphPosition {
-                glyph_id: GlyphId::new(font_id, glyph_idx, font_size),
-                x: current_origin_x + metrics.xmin as f32,
-                y: -(metrics.ymin as f32 + metrics.height as f32),
-                user_data,
+            let glyph_count = self.glyphs.len();
+            self.clusters.push(ClusterRecord {
+                glyphs: glyph_count..glyph_count,
+                x: self.next_origin_x,
+                advance,
+                level,
+                element,
+                source,
+                whitespace: true,
+                tab_stop: None,
+                object: None,
             });
+            self.next_origin_x += advance;
         }
 
         /// Concatenates another layout buffer, adjusting positions in-place.
         ///
-        /// When the buffers originate from the same font and size we apply
-        /// kerning between the boundary glyphs; otherwise the buffers are joined
-        /// using the recorded advance of the current buffer.
-        pub fn concat(&mut self, other: LayoutBuffer<T>, font_storage: &mut FontStorage) {
-            let kerning = if let (
-                Some(last_id),
-                Some(last_size),
-                Some(last_glyph),
-                Some(other_first_id),
-                Some(other_first_size),
-                Some(other_first_glyph),
-            ) = (
-                self.last_font_id,
-                self.last_font_size,
-                self.last_glyph,
-                other.first_font_id,
-                other.first_font_size,
-                other.first_glyph,
-            ) && last_id == other_first_id
-                && (last_size - other_first_size).abs() < f32::EPSILON
-            {
-                let font = font_storage
-                    .font(last_id)
-                    .expect("font must exist in font storage");
-                font.horizontal_kern_indexed(last_glyph, other_first_glyph, last_size)
-                    .unwrap_or(0.0)
-            } else {
-                0.0
-            };
-
-            let x_offset = self.next_origin_x + kerning;
+        /// The buffers are joined using the recorded advance of the current
+        /// buffer. Kerning inside a shaped run is already part of that advance.
+        pub fn concat(&mut self, other: LayoutBuffer<T>) {
+            let x_offset = self.next_origin_x;
 
-            let new_instance_length = x_offset + other.instance_length;
-            let new_next_origin_x = x_offset + other.next_origin_x;
-
-            self.instance_length = new_instance_length;
+            self.instance_length = x_offset + other.instance_length;
             self.max_accent = self.max_accent.max(other.max_accent);
-            self.max_descent = self.max_descent.max(other.max_descent);
+            self.max_descent = self.max_descent.min(other.max_descent);
             self.max_line_gap = self.max_line_gap.max(other.max_line_gap);
+            self.next_origin_x = x_offset + other.next_origin_x;
 
-            if self.first_glyph.is_none() {
-                self.first_glyph = other.first_glyph;
-                self.first_font_id = other.first_font_id;
-                self.first_font_size = other.first_font_size;
-            }
-
-            // Only update "last" fields if "other" actually has content.
-            // If other is empty, we keep our own last fields.
-            // However, "other" could be empty but have an offset (e.g. trailing tabs).
-            // But LayoutBuffer with offset usually comes from tabs, which don't have glyphs.
-            // If other has glyphs, it must have last_* fields.
-            if other.last_glyph.is_some() {
-                self.last_glyph = other.last_glyph;
-                self.last_font_id = other.last_font_id;
-                self.last_font_size = other.last_font_size;
-                self.last_metrics = other.last_metrics;
+            let glyph_offset = self.glyphs.len();
+            for mut cluster in other.clusters {
+                cluster.glyphs =
+                    cluster.glyphs.start + glyph_offset..cluster.glyphs.end + glyph_offset;
+                cluster.x += x_offset;
+                self.clusters.push(cluster);
             }
 
-            self.next_origin_x = new_next_origin_x;
             for mut glyph_pos in other.glyphs {
                 glyph_pos.x += x_offset;
                 self.glyphs.push(glyph_pos);
             }
         }
 
+        /// Resizes the gaps of tabs whose stop is not left-aligned so the text up to
+        /// the next tab is centered, right-aligned or decimal-aligned on the stop.
+        ///
+        /// `contains` tells whether a cluster contains a character, for decimal stops.
+        /// Text after the next tab keeps its position. Must be called on a complete
+        /// line, before [`LayoutBuffer::justify`].
+        pub fn align_tabs(&mut self, contains: impl Fn(&ClusterRecord, char) -> bool) {
+            for index in 0..self.clusters.len() {
+                let Some(stop) = self.clusters[index].tab_stop else {
+                    continue;
+                };
+                if stop.align == TabAlign::Left {
+                    continue;
+                }
+
+                let end = self.clusters[index + 1..]
+                    .iter()
+                    .position(|cluster| cluster.tab_stop.is_some())
+                    .map_or(self.clusters.len(), |offset| index + 1 + offset);
+                let segment = &self.clusters[index + 1..end];
+                let width: f32 = segment.iter().map(|cluster| cluster.advance).sum();
+                let lead = match stop.align {
+                    TabAlign::Left => 0.0,
+                    TabAlign::Center => width / 2.0,
+                    TabAlign::Right => width,
+                    TabAlign::Decimal(ch) => segment
+                        .iter()
+                        .take_while(|cluster| !contains(cluster, ch))
+                        .map(|cluster| cluster.advance)
+                        .sum(),
+                };
+
+                let gap = &mut self.clusters[index];
+                let advance = (stop.position - lead - gap.x).max(0.0);
+                let shift = advance - gap.advance;
+                gap.advance = advance;
+
+                for cluster in &mut self.clusters[index + 1..end] {
+                    cluster.x += shift;
+                    for glyph in &mut self.glyphs[cluster.glyphs.clone()] {
+                        glyph.x += shift;
+                    }
+                }
+                match self.clusters.get_mut(end) {
+                    // The next tab still reaches its stop.
+                    Some(next_tab) => {
+                        next_tab.x += shift;
+                        next_tab.advance -= shift;
+                    }
+                    None => {
+                        self.next_origin_x += shift;
+                        self.instance_length += shift;
+                    }
+                }
+            }
+        }
+
+        /// Removes the clusters from index `len` onwards.
+        ///
+        /// Must be called before [`LayoutBuffer::reorder_visual`], while the
+        /// clusters and glyphs are still in logical order.
+        pub fn truncate(&mut self, len: usize) {
+            let Some(cluster) = self.clusters.get(len) else {
+                return;
+            };
+
+            self.glyphs.truncate(cluster.glyphs.start);
+            self.next_origin_x = cluster.x;
+            // The ink of the kept glyphs is assumed to end before the removed cluster.
+            self.instance_length = cluster.x;
+            self.clusters.truncate(len);
+        }
+
         /// Returns the current width of the buffer.
         pub fn width(&self) -> f32 {
             self.instance_length.max(0.0)
@@ -873,40 +3026,128 @@ mod layout_utl {
         ///
         /// This prediction is used during wrapping decisions to avoid expensive
         /// cloning or re-layout work.
-        pub fn projected_concat_length(
-            &self,
-            other: &LayoutBuffer<T>,
-            font_storage: &mut FontStorage,
-        ) -> f32 {
-            let kerning = if let (
-                Some(last_id),
-                Some(last_size),
-                Some(last_glyph),
-                Some(other_first_id),
-                Some(other_first_size),
-                Some(other_first_glyph),
-            ) = (
-                self.last_font_id,
-                self.last_font_size,
-                self.last_glyph,
-                other.first_font_id,
-                other.first_font_size,
-                other.first_glyph,
-            ) && last_id == other_first_id
-                && (last_size - other_first_size).abs() < f32::EPSILON
-            {
-                font_storage
-                    .font(last_id)
-                    .and_then(|font| {
-                        font.horizontal_kern_indexed(last_glyph, other_first_glyph, last_size)
-                    })
-                    .unwrap_or(0.0)
+        pub fn projected_concat_length(&self, other: &LayoutBuffer<T>) -> f32 {
+            self.next_origin_x + other.instance_length
+        }
+
+        /// Moves the clusters of a finished line into visual order.
+        ///
+        /// Clusters keep their logical order in [`Self::clusters`]; only the glyph
+        /// positions change. Lines without right-to-left content are left untouched.
+        pub fn reorder_visual(&mut self, paragraph_level: unicode_bidi::Level) {
+            if paragraph_level.is_ltr() && self.clusters.iter().all(|c| c.level.is_ltr()) {
+                return;
+            }
+
+            let levels: Vec<unicode_bidi::Level> = self.clusters.iter().map(|c| c.level).collect();
+            let trailing_whitespace = self
+                .clusters
+                .iter()
+                .rev()
+                .take_while(|c| c.whitespace)
+                .count();
+            let order = bidi::reorder_line(&levels, trailing_whitespace, paragraph_level);
+
+            let mut x = self.clusters.first().map(|c| c.x).unwrap_or(0.0);
+            for logical in order {
+                let cluster = &mut self.clusters[logical];
+                let shift = x - cluster.x;
+                for glyph in &mut self.glyphs[cluster.glyphs.clone()] {
+                    glyph.x += shift;
+                }
+                cluster.x = x;
+                x += cluster.advance;
+            }
+        }
+
+        /// Stretches the buffer to `target_width` by widening its clusters.
+        ///
+        /// The extra space goes to inter-word spaces. Trailing whitespace and tab
+        /// gaps (which carry no glyphs) keep their width. Without any space the
+        /// extra space is put between clusters if `inter_character` is set.
+        /// Must be called before [`LayoutBuffer::reorder_visual`].
+        pub fn justify(&mut self, target_width: f32, inter_character: bool) {
+            let extra = target_width - self.width();
+            if extra <= 0.0 {
+                return;
+            }
+
+            let trailing_whitespace = self
+                .clusters
+                .iter()
+                .rev()
+                .take_while(|c| c.whitespace)
+                .count();
+            let body_len = self.clusters.len() - trailing_whitespace;
+
+            let is_space = |c: &ClusterRecord| c.whitespace && !c.glyphs.is_empty();
+            let spaces = self.clusters[..body_len]
+                .iter()
+                .filter(|c| is_space(c))
+                .count();
+
+            let (gap_count, use_spaces) = if spaces > 0 {
+                (spaces, true)
+            } else if inter_character && body_len > 1 {
+                (body_len - 1, false)
             } else {
-                0.0
+                return;
             };
+            let gap = extra / gap_count as f32;
+
+            let mut shift = 0.0;
+            for (index, cluster) in self.clusters.iter_mut().enumerate() {
+                cluster.x += shift;
+                for glyph in &mut self.glyphs[cluster.glyphs.clone()] {
+                    glyph.x += shift;
+                }
+
+                let widen = if use_spaces {
+                    index < body_len && is_space(cluster)
+                } else {
+                    index + 1 < body_len
+                };
+                if widen {
+                    cluster.advance += gap;
+                    shift += gap;
+                }
+            }
+
+            self.instance_length += extra;
+            self.next_origin_x += shift;
+        }
+
+        /// Returns the extents along the line of consecutive clusters of the same
+        /// element, in visual order. Trailing whitespace is left out.
+        ///
+        /// Must be called after [`LayoutBuffer::reorder_visual`].
+        pub fn element_spans(&self) -> Vec<(usize, Range<f32>)> {
+            let trailing_whitespace = self
+                .clusters
+                .iter()
+                .rev()
+                .take_while(|c| c.whitespace)
+                .count();
+            let mut body: Vec<&ClusterRecord> = self.clusters
+                [..self.clusters.len() - trailing_whitespace]
+                .iter()
+                .collect();
+            body.sort_by(|a, b| a.x.total_cmp(&b.x));
 
-            let x_offset = self.next_origin_x + kerning;
-            x_offset + other.instance_length
+            let mut spans: Vec<(usize, Range<f32>)> = Vec::new();
+            for cluster in body {
+                let end = cluster.x + cluster.advance;
+                match spans.last_mut() {
+                    Some((element, span))
+                        if *element == cluster.element && (span.end - cluster.x).abs() < 0.01 =>
+                    {
+                        span.end = end;
+                    }
+                    _ => spans.push((cluster.element, cluster.x..end)),
+                }
+            }
+
+            spans
         }
 
         /// Returns line metrics derived from the buffered glyph fragments.
@@ -918,34 +3159,1290 @@ mod layout_utl {
         ///
         /// `None` is returned when the slice is empty because there are no
         /// glyphs to measure or position.
-        pub fn from_fragments(
-            fragments: &[GlyphFragment<T>],
-            font_storage: &mut FontStorage,
-        ) -> Option<LayoutBuffer<T>> {
+        pub fn from_fragments(fragments: &[GlyphFragment<T>]) -> Option<LayoutBuffer<T>> {
             let first = fragments.first()?;
-            let mut buffer = LayoutBuffer::new(
-                first.glyph_idx,
-                &first.metrics,
-                &first.line_metrics,
-                first.font_id,
-                first.font_size,
-                first.user_data.clone(),
-            );
+            let mut buffer = LayoutBuffer::new_empty(&first.line_metrics);
 
-            for fragment in fragments.iter().skip(1) {
-                buffer.push(
-                    fragment.glyph_idx,
-                    &fragment.metrics,
-                    &fragment.line_metrics,
-                    fragment.font.as_ref(),
-                    fragment.font_id,
-                    fragment.font_size,
-                    fragment.user_data.clone(),
-                    font_storage,
-                );
+            for fragment in fragments {
+                buffer.push(fragment);
             }
 
             Some(buffer)
         }
     }
 }
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::layout_utl::{GlyphFragment, LayoutBuffer, PlacedGlyph};
+    use super::*;
+    use crate::{
+        font_storage::FontStorage,
+        text::{BaselineShift, Spacing, TextElement},
+    };
+
+    fn font_id() -> fontdb::ID {
+        unsafe { std::mem::transmute(1u64) }
+    }
+
+    /// Cluster of one glyph inked over its whole advance; spaces have no ink.
+    fn fragment(ch: char, advance: f32, element: usize) -> GlyphFragment<()> {
+        GlyphFragment {
+            ch,
+            glyphs: vec![PlacedGlyph {
+                glyph_id: GlyphId::new(font_id(), 1, 12.0),
+                x: 0.0,
+                y: -10.0,
+                ink_end: if ch.is_whitespace() { 0.0 } else { advance },
+                color: false,
+            }],
+            advance,
+            level: unicode_bidi::Level::ltr(),
+            element,
+            source: 0..ch.len_utf8(),
+            line_metrics: fontdue::LineMetrics {
+                ascent: 10.0,
+                descent: -3.0,
+                line_gap: 0.0,
+                new_line_size: 13.0,
+            },
+            font_id: font_id(),
+            font_size: 12.0,
+            user_data: (),
+            object: None,
+            baseline_shift: 0.0,
+            ruby: None,
+        }
+    }
+
+    fn buffer(text: &str) -> LayoutBuffer<()> {
+        let fragments: Vec<_> = text.chars().map(|ch| fragment(ch, 10.0, 0)).collect();
+        LayoutBuffer::from_fragments(&fragments).unwrap()
+    }
+
+    fn offsets(buffer: &LayoutBuffer<()>) -> Vec<f32> {
+        buffer.clusters.iter().map(|cluster| cluster.x).collect()
+    }
+
+    fn object(width: f32, ascent: f32, descent: f32) -> TextElement<()> {
+        TextElement::new(font_id(), 12.0, "\u{FFFC}", ()).with_inline_object(InlineObject {
+            width,
+            ascent,
+            descent,
+        })
+    }
+
+    /// Text of inline objects, which lays out without fonts.
+    fn objects(widths: &[f32]) -> TextData<()> {
+        let mut text = TextData::new();
+        for &width in widths {
+            text.append(object(width, 8.0, 2.0));
+        }
+        text
+    }
+
+    #[test]
+    fn test_buffer_descent() {
+        // Descents are negative: the line takes the deepest, the smallest one.
+        let mut deep = fragment('g', 10.0, 0);
+        deep.line_metrics.descent = -6.0;
+        let mut line = buffer("a");
+        line.push(&deep);
+        line.push(&fragment('b', 10.0, 0));
+        assert_eq!(line.line_metrics(), (10.0, -6.0, 0.0));
+
+        let mut line = buffer("a");
+        line.concat(LayoutBuffer::from_fragments(&[deep]).unwrap());
+        line.concat(buffer("b"));
+        assert_eq!(line.line_metrics(), (10.0, -6.0, 0.0));
+    }
+
+    #[test]
+    fn test_inline_objects() {
+        let mut text = objects(&[40.0]);
+        text.append(object(40.0, 20.0, 5.0));
+        text.append(object(40.0, 8.0, 2.0));
+        let config = TextLayoutConfig {
+            max_width: Some(100.0),
+            wrap_style: WrapStyle::CharWrap,
+            ..Default::default()
+        };
+        let layout = text.layout(&config, &mut FontStorage::new());
+
+        // The objects wrap like characters and size the lines they are on.
+        assert_eq!(layout.lines.len(), 2);
+        let (first, second) = (&layout.lines[0], &layout.lines[1]);
+        assert_eq!(
+            (first.top, first.line_height, first.line_width),
+            (0.0, 25.0, 80.0)
+        );
+        assert_eq!((second.top, second.line_height), (25.0, 10.0));
+        assert_eq!(layout.total_height, 35.0);
+
+        // Objects sit on the baseline, which is below the tallest one.
+        let boxes: Vec<_> = layout
+            .lines
+            .iter()
+            .flat_map(|line| &line.objects)
+            .map(|object| {
+                (
+                    object.element,
+                    object.x,
+                    object.y,
+                    object.width,
+                    object.height,
+                )
+            })
+            .collect();
+        assert_eq!(
+            boxes,
+            vec![
+                (0, 0.0, 12.0, 40.0, 10.0),
+                (1, 40.0, 0.0, 40.0, 25.0),
+                (2, 0.0, 25.0, 40.0, 10.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_element_spans() {
+        let rtl = unicode_bidi::Level::rtl();
+        let fragments: Vec<_> = [('a', 0), ('b', 0), ('c', 1), ('d', 1), ('e', 2), ('f', 2)]
+            .into_iter()
+            .map(|(ch, element)| GlyphFragment {
+                level: if element > 0 {
+                    rtl
+                } else {
+                    unicode_bidi::Level::ltr()
+                },
+                ..fragment(ch, 10.0, element)
+            })
+            .chain([fragment(' ', 10.0, 0)])
+            .collect();
+        let mut line = LayoutBuffer::from_fragments(&fragments).unwrap();
+        line.reorder_visual(unicode_bidi::Level::ltr());
+
+        // The right-to-left run is reversed; the trailing space is left out.
+        assert_eq!(
+            line.element_spans(),
+            vec![(0, 0.0..20.0), (2, 20.0..40.0), (1, 40.0..60.0)]
+        );
+    }
+
+    #[test]
+    fn test_decoration_geometry() {
+        let text = objects(&[30.0, 30.0]);
+        let layout = |config: &TextLayoutConfig| {
+            let mut font_storage = FontStorage::new();
+            let analysis = TextAnalysis::new(&text.texts, &[], config);
+            let mut engine =
+                LayoutEngine::new(config, &mut font_storage, &text.texts, &[], &[], &analysis);
+            // An underline 2px below the baseline and a strikethrough 4px above it.
+            engine.decoration_strokes = vec![
+                vec![layout_utl::DecorationStroke {
+                    kind: DecorationKind::Underline,
+                    across: 2.0,
+                    thickness: 1.0,
+                }],
+                vec![layout_utl::DecorationStroke {
+                    kind: DecorationKind::Strikethrough,
+                    across: -4.0,
+                    thickness: 1.0,
+                }],
+            ];
+            engine.layout().lines[0]
+                .decorations
+                .iter()
+                .map(|d| (d.kind, d.element, d.x, d.y, d.width, d.height))
+                .collect::<Vec<_>>()
+        };
+
+        // The baseline is 8px down and the line is centered in 100px.
+        let horizontal = layout(&TextLayoutConfig {
+            max_width: Some(100.0),
+            horizontal_align: HorizontalAlign::Center,
+            ..Default::default()
+        });
+        assert_eq!(
+            horizontal,
+            vec![
+                (DecorationKind::Underline, 0, 20.0, 10.0, 30.0, 1.0),
+                (DecorationKind::Strikethrough, 1, 50.0, 4.0, 30.0, 1.0),
+            ]
+        );
+
+        // Vertical lines run down a 30px wide column centered at x = 15.
+        let vertical = layout(&TextLayoutConfig {
+            writing_mode: WritingMode::VerticalRightToLeft,
+            ..Default::default()
+        });
+        assert_eq!(
+            vertical,
+            vec![
+                (DecorationKind::Underline, 0, 17.0, 0.0, 1.0, 10.0),
+                (DecorationKind::Strikethrough, 1, 11.0, 10.0, 1.0, 10.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vertical_layout() {
+        use crate::font_storage::tests::{font_data_with_tables, outline_tables, push_face_data};
+
+        let chars = ['漢', '字', 'a', 'b', '1', '2'];
+        let data = font_data_with_tables(&chars, outline_tables(chars.len() as u16 + 1));
+        let mut font_storage = FontStorage::new();
+        let font_id = push_face_data(&mut font_storage, "Test", data);
+        let mut text = TextData::new();
+        text.append(TextElement::new(font_id, 10.0, "漢字ab字12漢", ()));
+        let config = TextLayoutConfig {
+            max_height: Some(30.0),
+            wrap_style: WrapStyle::CharWrap,
+            writing_mode: WritingMode::VerticalRightToLeft,
+            tate_chu_yoko: 2,
+            ..Default::default()
+        };
+        let layout = text.layout(&config, &mut font_storage);
+        let glyphs = |line: &TextLayoutLine<()>| {
+            line.glyphs
+                .iter()
+                .map(|glyph| {
+                    let source = &text.texts[0].content[glyph.source.clone()];
+                    (source, glyph.x, glyph.y, glyph.glyph_id.is_rotated())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Two 30px columns of 10px, the first one on the right.
+        assert_eq!((layout.total_width, layout.total_height), (20.0, 30.0));
+        let columns: Vec<_> = layout
+            .lines
+            .iter()
+            .map(|line| (line.left, line.right, line.top, line.line_height))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![(10.0, 20.0, 0.0, 10.0), (0.0, 10.0, 0.0, 10.0)]
+        );
+
+        // Ideographs stay upright, centered on the column and advancing by one
+        // em downwards. Latin letters are rotated and advance by their width.
+        assert_eq!(
+            glyphs(&layout.lines[0]),
+            vec![
+                ("漢", 12.5, 1.5, false),
+                ("字", 12.5, 11.5, false),
+                ("a", 12.0, 20.0, true),
+                ("b", 12.0, 25.0, true),
+            ]
+        );
+        // The two digits are one cluster, set side by side in the space of one em.
+        assert_eq!(
+            glyphs(&layout.lines[1]),
+            vec![
+                ("字", 2.5, 1.5, false),
+                ("12", 0.0, 11.0, false),
+                ("12", 5.0, 11.0, false),
+                ("漢", 2.5, 21.5, false),
+            ]
+        );
+        let cluster = &layout.lines[1].clusters[1];
+        assert_eq!((cluster.source.clone(), cluster.advance), (11..13, 10.0));
+
+        // Without tate-chu-yoko the digits are rotated like other Latin text.
+        let config = TextLayoutConfig {
+            tate_chu_yoko: 0,
+            ..config
+        };
+        let layout = text.layout(&config, &mut font_storage);
+        assert_eq!(
+            glyphs(&layout.lines[1])[1..3],
+            [("1", 2.0, 10.0, true), ("2", 2.0, 15.0, true)]
+        );
+    }
+
+    #[test]
+    fn test_paragraph_geometry() {
+        let mut text = TextData::new();
+        text.start_paragraph(ParagraphStyle {
+            first_line_indent: 20.0,
+            hanging_indent: 5.0,
+            space_after: 6.0,
+            ..Default::default()
+        });
+        for _ in 0..3 {
+            text.append(object(30.0, 8.0, 2.0));
+        }
+        text.start_paragraph(ParagraphStyle {
+            space_before: 4.0,
+            horizontal_align: Some(HorizontalAlign::Right),
+            ..Default::default()
+        });
+        text.append(object(30.0, 8.0, 2.0));
+        // A paragraph starting with right-to-left text starts on the right.
+        text.start_paragraph(ParagraphStyle::default());
+        text.append(TextElement {
+            content: "א".to_string(),
+            ..object(30.0, 8.0, 2.0)
+        });
+        let config = TextLayoutConfig {
+            max_width: Some(80.0),
+            wrap_style: WrapStyle::CharWrap,
+            ..Default::default()
+        };
+        let layout = text.layout(&config, &mut FontStorage::new());
+
+        // The first line leaves room for its indent only for two objects.
+        let lines: Vec<_> = layout
+            .lines
+            .iter()
+            .map(|line| (line.top, line.left, line.line_width, line.break_kind))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (0.0, 20.0, 60.0, LineBreakKind::Soft),
+                (10.0, 5.0, 30.0, LineBreakKind::Paragraph),
+                (30.0, 50.0, 30.0, LineBreakKind::Paragraph),
+                (40.0, 50.0, 30.0, LineBreakKind::EndOfText),
+            ]
+        );
+        assert_eq!(layout.total_height, 50.0);
+    }
+
+    #[test]
+    fn test_ruby() {
+        let mut font_storage = FontStorage::new();
+        let font_id =
+            crate::font_storage::tests::push_face(&mut font_storage, "Test", &['a', 'b', 'x']);
+        // Glyphs are half an em wide: 5px for the base, 2.5px for the annotation.
+        let ruby = |content: &str| RubyText {
+            font_id,
+            font_size: 5.0,
+            content: content.to_string(),
+            user_data: 1u8,
+        };
+        let mut text = TextData::new();
+        text.append(TextElement::new(font_id, 10.0, "x", 0u8));
+        text.append_ruby(TextElement::new(font_id, 10.0, "ab", 0), ruby("abbaab"));
+        text.append_ruby(TextElement::new(font_id, 10.0, "abba", 0), ruby("ab"));
+        let config = TextLayoutConfig {
+            max_width: Some(22.0),
+            wrap_style: WrapStyle::CharWrap,
+            ..Default::default()
+        };
+        let layout = text.layout(&config, &mut font_storage);
+
+        // Each pair is one cluster as wide as its wider part, and moves to the
+        // next line as a whole.
+        let clusters = |line: &TextLayoutLine<u8>| -> Vec<_> {
+            line.clusters
+                .iter()
+                .map(|cluster| (cluster.element, cluster.offset, cluster.advance))
+                .collect()
+        };
+        assert_eq!(layout.lines.len(), 2);
+        assert_eq!(
+            clusters(&layout.lines[0]),
+            vec![(0, 0.0, 5.0), (1, 5.0, 15.0)]
+        );
+        assert_eq!(clusters(&layout.lines[1]), vec![(2, 0.0, 20.0)]);
+
+        // The narrower part is centered on the wider one.
+        let glyphs = |line: &TextLayoutLine<u8>, user_data: u8| -> Vec<_> {
+            line.glyphs
+                .iter()
+                .filter(|glyph| glyph.user_data == user_data && glyph.element > 0)
+                .map(|glyph| (glyph.x, glyph.y))
+                .collect()
+        };
+        let first = &layout.lines[0];
+        assert_eq!(glyphs(first, 0), vec![(7.5, 13.0), (12.5, 13.0)]);
+        assert_eq!(glyphs(first, 1)[0], (5.0, 4.0));
+        let second = &layout.lines[1];
+        let ruby_top = second.top + 4.0;
+        assert_eq!(glyphs(second, 1), vec![(7.5, ruby_top), (10.0, ruby_top)]);
+
+        // The annotation (4px ascent, 1px descent) raises the line.
+        assert_eq!((first.line_height, second.line_height), (15.0, 15.0));
+    }
+
+    /// Lays out `content` in one element, with room for four glyphs of the test
+    /// font per line. The glyphs have no ink, so the fifth one starts past the end.
+    fn kinsoku_lines(content: &str, config: TextLayoutConfig) -> (Vec<String>, bool) {
+        let mut font_storage = FontStorage::new();
+        let font_id = crate::font_storage::tests::push_face(
+            &mut font_storage,
+            "Test",
+            &['a', 'あ', 'ぁ', '～', '。', '…'],
+        );
+        let mut text = TextData::new();
+        text.append(TextElement::new(font_id, 10.0, content, ()));
+        let config = TextLayoutConfig {
+            max_width: Some(17.5),
+            ..config
+        };
+        let layout = text.layout(&config, &mut font_storage);
+        let lines = layout
+            .lines
+            .iter()
+            .map(|line| content[line.source.start.offset..line.source.end.offset].to_string())
+            .collect();
+        (lines, layout.truncated)
+    }
+
+    #[test]
+    fn test_kinsoku_grouping() {
+        let char_wrap = |kinsoku| TextLayoutConfig {
+            wrap_style: WrapStyle::CharWrap,
+            kinsoku,
+            ..Default::default()
+        };
+        assert_eq!(
+            kinsoku_lines("aaaaぁa", char_wrap(Kinsoku::default())).0,
+            ["aaaa", "ぁa"]
+        );
+        // Small kana may only start a line under the loose rules.
+        assert_eq!(
+            kinsoku_lines("aaaaぁa", char_wrap(Kinsoku::strict())).0,
+            ["aaa", "aぁa"]
+        );
+        assert_eq!(
+            kinsoku_lines("aaaaぁa", char_wrap(Kinsoku::loose())).0,
+            ["aaaa", "ぁa"]
+        );
+        assert_eq!(
+            kinsoku_lines("aaaa。a", char_wrap(Kinsoku::loose())).0,
+            ["aaa", "a。a"]
+        );
+
+        // The rules also remove break opportunities of the Unicode algorithm,
+        // which allows one before a fullwidth tilde.
+        let unicode_wrap = |kinsoku| TextLayoutConfig {
+            wrap_style: WrapStyle::UnicodeWrap,
+            kinsoku,
+            ..Default::default()
+        };
+        assert_eq!(
+            kinsoku_lines("ああああ～", unicode_wrap(Kinsoku::default())).0,
+            ["ああああ", "～"]
+        );
+        assert_eq!(
+            kinsoku_lines("ああああ～", unicode_wrap(Kinsoku::strict())).0,
+            ["あああ", "あ～"]
+        );
+    }
+
+    #[test]
+    fn test_kinsoku_hanging() {
+        let config = TextLayoutConfig {
+            wrap_style: WrapStyle::CharWrap,
+            kinsoku: Kinsoku {
+                hanging_punctuation: true,
+                ..Kinsoku::loose()
+            },
+            overflow: TextOverflow::Ellipsis,
+            ..Default::default()
+        };
+        // The full stop hangs past the end instead of taking the glyph before it
+        // to the next line, and does not count as overflow.
+        assert_eq!(
+            kinsoku_lines("aaaa。a", config.clone()),
+            (vec!["aaaa。".to_string(), "a".to_string()], false)
+        );
+        assert_eq!(
+            kinsoku_lines("aaaa。", config),
+            (vec!["aaaa。".to_string()], false)
+        );
+    }
+
+    #[test]
+    fn test_shape_without_room() {
+        let mut font_storage = FontStorage::new();
+        let mut layout = |mut shape: LayoutShape, min_width: f32| {
+            shape.min_width = min_width;
+            let config = TextLayoutConfig {
+                max_width: Some(100.0),
+                shape: Some(shape),
+                ..Default::default()
+            };
+            objects(&[30.0, 30.0]).layout(&config, &mut font_storage)
+        };
+
+        // Intervals too narrow for any line, all the way down.
+        let narrow = layout(LayoutShape::new(|_, _| vec![0.0..10.0]), 20.0);
+        assert_eq!(narrow.lines.len(), 1);
+        assert_eq!(narrow.lines[0].objects.len(), 2);
+
+        // An exclusion covering the whole width without a bottom.
+        let blocked = layout(
+            LayoutShape::with_exclusions(
+                100.0,
+                vec![LayoutRect {
+                    left: 0.0,
+                    top: 0.0,
+                    right: 100.0,
+                    bottom: f32::INFINITY,
+                }],
+            ),
+            0.0,
+        );
+        assert_eq!(blocked.lines.len(), 1);
+
+        // Below the last exclusion the shape ends if it is too narrow.
+        let below = layout(
+            LayoutShape::with_exclusions(
+                40.0,
+                vec![LayoutRect {
+                    left: 0.0,
+                    top: 0.0,
+                    right: 40.0,
+                    bottom: 15.0,
+                }],
+            ),
+            50.0,
+        );
+        assert_eq!(below.lines.len(), 1);
+        assert_eq!(below.lines[0].top, 20.0);
+    }
+
+    #[test]
+    fn test_shape_hit_test() {
+        let mut font_storage = FontStorage::new();
+        // The first band is split into 0..40 and 60..100, one object in each.
+        let config = TextLayoutConfig {
+            max_width: Some(100.0),
+            wrap_style: WrapStyle::CharWrap,
+            shape: Some(LayoutShape::with_exclusions(
+                100.0,
+                vec![LayoutRect {
+                    left: 40.0,
+                    top: 0.0,
+                    right: 60.0,
+                    bottom: 10.0,
+                }],
+            )),
+            ..Default::default()
+        };
+        let layout = objects(&[30.0, 30.0, 30.0, 30.0]).layout(&config, &mut font_storage);
+        let lines: Vec<_> = layout
+            .lines
+            .iter()
+            .map(|line| (line.top, line.left, line.right))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![(0.0, 0.0, 30.0), (0.0, 60.0, 90.0), (10.0, 0.0, 60.0)]
+        );
+
+        let hit = |x, y| {
+            let hit = layout.hit_test(x, y).unwrap();
+            (hit.element, hit.trailing)
+        };
+        assert_eq!(hit(20.0, 5.0), (0, true));
+        assert_eq!(hit(70.0, 5.0), (1, false));
+        assert_eq!(hit(85.0, 5.0), (1, true));
+        // Between the intervals the closest line is hit.
+        assert_eq!(hit(35.0, 5.0), (0, true));
+        assert_eq!(hit(55.0, 5.0), (1, false));
+        assert_eq!(hit(50.0, 15.0), (3, true));
+
+        let position = |element| TextPosition { element, offset: 0 };
+        let caret = layout.caret_rect(position(1)).unwrap();
+        assert_eq!((caret.left, caret.top, caret.bottom), (60.0, 0.0, 10.0));
+        let rects: Vec<_> = layout
+            .selection_rects(position(0)..position(3))
+            .iter()
+            .map(|rect| (rect.left, rect.right, rect.top))
+            .collect();
+        assert_eq!(
+            rects,
+            vec![(0.0, 30.0, 0.0), (60.0, 90.0, 0.0), (0.0, 30.0, 10.0)]
+        );
+    }
+
+    #[test]
+    fn test_justify_spaces() {
+        // The trailing space keeps its width.
+        let mut line = buffer("ab cd ef ");
+        assert_eq!(line.width(), 80.0);
+        line.justify(100.0, true);
+        assert_eq!(line.width(), 100.0);
+        assert_eq!(
+            offsets(&line),
+            vec![0.0, 10.0, 20.0, 40.0, 50.0, 60.0, 80.0, 90.0, 100.0]
+        );
+        assert_eq!(line.glyphs[6].x, 80.0);
+
+        // A line already wide enough is left as it is.
+        let mut line = buffer("ab cd");
+        line.justify(40.0, false);
+        assert_eq!(offsets(&line), vec![0.0, 10.0, 20.0, 30.0, 40.0]);
+    }
+
+    #[test]
+    fn test_justify_inter_character() {
+        let mut line = buffer("漢字仮名");
+        line.justify(70.0, false);
+        assert_eq!(line.width(), 40.0);
+
+        line.justify(70.0, true);
+        assert_eq!(line.width(), 70.0);
+        assert_eq!(offsets(&line), vec![0.0, 20.0, 40.0, 60.0]);
+        assert_eq!(line.clusters[3].advance, 10.0);
+    }
+
+    #[test]
+    fn test_justify_last_line() {
+        let mut font_storage = FontStorage::new();
+        let font_id = crate::font_storage::tests::push_face(&mut font_storage, "Test", &['a']);
+        // Glyphs are 30px wide without ink, so four fit in 105px.
+        let mut text = TextData::new();
+        text.append(TextElement::new(font_id, 60.0, "aaaaaa", ()));
+        let config = TextLayoutConfig {
+            max_width: Some(105.0),
+            horizontal_align: HorizontalAlign::Justify,
+            wrap_style: WrapStyle::CharWrap,
+            justify_inter_character: true,
+            ..Default::default()
+        };
+        let layout = text.layout(&config, &mut font_storage);
+        let x = |line: &TextLayoutLine<()>| -> Vec<f32> {
+            line.glyphs.iter().map(|glyph| glyph.x).collect()
+        };
+
+        assert_eq!(layout.lines.len(), 2);
+        assert_eq!(x(&layout.lines[0]), vec![0.0, 35.0, 70.0, 105.0]);
+        assert_eq!(x(&layout.lines[1]), vec![0.0, 30.0]);
+    }
+
+    /// Lays out `content` in one element of the test font at 10px, where the
+    /// glyphs advance by 5px and have no ink, and returns the glyph x positions
+    /// of each line.
+    fn spaced_lines(
+        content: &str,
+        letter_spacing: Spacing,
+        word_spacing: Spacing,
+        config: &TextLayoutConfig,
+    ) -> Vec<Vec<f32>> {
+        let mut font_storage = FontStorage::new();
+        let font_id = crate::font_storage::tests::push_face(&mut font_storage, "Test", &['a', ' ']);
+        let mut text = TextData::new();
+        text.append(
+            TextElement::new(font_id, 10.0, content, ())
+                .with_letter_spacing(letter_spacing)
+                .with_word_spacing(word_spacing),
+        );
+        text.layout(config, &mut font_storage)
+            .lines
+            .iter()
+            .map(|line| line.glyphs.iter().map(|glyph| glyph.x).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_spacing() {
+        // Letters advance by 5 + 2px and the space by 5 + 2 + 3px, whether the
+        // spacing is given in pixels or relative to the font size.
+        let unbounded = TextLayoutConfig::default();
+        let one_line = vec![vec![0.0, 7.0, 14.0, 21.0, 31.0, 38.0]];
+        let pixels = (Spacing::Pixels(2.0), Spacing::Pixels(3.0));
+        let em = (Spacing::Em(0.2), Spacing::Em(0.3));
+        assert_eq!(
+            spaced_lines("aaa aa", pixels.0, pixels.1, &unbounded),
+            one_line
+        );
+        assert_eq!(spaced_lines("aaa aa", em.0, em.1, &unbounded), one_line);
+
+        // Wrapping measures the same width: the line fits exactly, and the last
+        // word moves down when the box is a little narrower.
+        let word_wrap = |max_width| TextLayoutConfig {
+            max_width: Some(max_width),
+            wrap_style: WrapStyle::WordWrap,
+            ..Default::default()
+        };
+        assert_eq!(
+            spaced_lines("aaa aa", pixels.0, pixels.1, &word_wrap(38.0)),
+            one_line
+        );
+        assert_eq!(
+            spaced_lines("aaa aa", em.0, em.1, &word_wrap(37.5)),
+            vec![vec![0.0, 7.0, 14.0, 21.0], vec![0.0, 7.0]]
+        );
+
+        // So does alignment: the last glyph ends at the right edge.
+        let right = TextLayoutConfig {
+            max_width: Some(50.0),
+            horizontal_align: HorizontalAlign::Right,
+            ..Default::default()
+        };
+        assert_eq!(
+            spaced_lines("aaa aa", pixels.0, pixels.1, &right),
+            vec![vec![12.0, 19.0, 26.0, 33.0, 43.0, 50.0]]
+        );
+    }
+
+    /// Lays out an unshifted glyph followed by a shifted one in `font_id` at
+    /// 100px, whose ascent is 80px and descent 20px. Returns the y position of
+    /// both glyphs (their baseline, as they have no ink) and the line height.
+    fn shifted_line(
+        font_storage: &mut FontStorage,
+        font_id: fontdb::ID,
+        shift: BaselineShift,
+        extends_line: bool,
+    ) -> (Vec<f32>, f32) {
+        let mut text = TextData::new();
+        text.append(TextElement::new(font_id, 100.0, "a", ()));
+        text.append(TextElement::new(font_id, 100.0, "a", ()).with_baseline_shift(shift));
+        let config = TextLayoutConfig {
+            baseline_shift_extends_line: extends_line,
+            ..Default::default()
+        };
+        let layout = text.layout(&config, font_storage);
+        let line = &layout.lines[0];
+        let y = line.glyphs.iter().map(|glyph| glyph.y).collect();
+        (y, line.line_height)
+    }
+
+    #[test]
+    fn test_baseline_shift() {
+        let mut font_storage = FontStorage::new();
+        let plain = crate::font_storage::tests::push_face(&mut font_storage, "Plain", &['a']);
+        let os2 = crate::font_storage::tests::push_face_data(
+            &mut font_storage,
+            "OS2",
+            crate::font_storage::tests::font_data_with_tables(
+                &['a'],
+                vec![(*b"OS/2", crate::font_storage::tests::os2_table(400, 150))],
+            ),
+        );
+        let mut line = |font_id, shift| shifted_line(&mut font_storage, font_id, shift, false);
+
+        // Without OS/2 metrics the presets use a third and a fifth of the size.
+        assert_eq!(
+            line(plain, BaselineShift::Superscript),
+            (vec![80.0, 47.0], 100.0)
+        );
+        assert_eq!(
+            line(plain, BaselineShift::Subscript),
+            (vec![80.0, 100.0], 100.0)
+        );
+        assert_eq!(
+            line(os2, BaselineShift::Superscript),
+            (vec![80.0, 40.0], 100.0)
+        );
+        assert_eq!(
+            line(os2, BaselineShift::Subscript),
+            (vec![80.0, 95.0], 100.0)
+        );
+        assert_eq!(
+            line(plain, BaselineShift::Pixels(-5.0)),
+            (vec![80.0, 85.0], 100.0)
+        );
+
+        // The line grows to hold the shifted glyphs only when configured to.
+        assert_eq!(
+            shifted_line(&mut font_storage, plain, BaselineShift::Superscript, true),
+            (vec![113.0, 80.0], 133.0)
+        );
+        assert_eq!(
+            shifted_line(&mut font_storage, plain, BaselineShift::Subscript, true),
+            (vec![80.0, 100.0], 120.0)
+        );
+    }
+
+    #[test]
+    fn test_kerning_across_elements() {
+        let mut font_storage = FontStorage::new();
+        let font_id = crate::font_storage::tests::push_face_data(
+            &mut font_storage,
+            "Features",
+            crate::text::layout::shaping::tests::feature_font_data(),
+        );
+        let mut x = |runs: &[(&str, f32)]| {
+            let mut text = TextData::new();
+            for &(content, font_size) in runs {
+                text.append(TextElement::new(font_id, font_size, content, ()));
+            }
+            let layout = text.layout(&TextLayoutConfig::default(), &mut font_storage);
+            layout.lines[0]
+                .glyphs
+                .iter()
+                .map(|glyph| glyph.x)
+                .collect::<Vec<_>>()
+        };
+
+        // `V` moves 10px closer to `A` whether or not they are in one element.
+        assert_eq!(x(&[("AVA", 100.0)]), vec![0.0, 40.0, 90.0]);
+        assert_eq!(x(&[("A", 100.0), ("VA", 100.0)]), vec![0.0, 40.0, 90.0]);
+        // Runs of another size are not kerned together.
+        assert_eq!(x(&[("A", 100.0), ("VA", 50.0)]), vec![0.0, 50.0, 75.0]);
+    }
+
+    /// Lays out `runs` of (content, font, user data) at 10px, where glyphs of
+    /// the test fonts advance by 5px and lines are 10px high. Returns the
+    /// characters of each line, with the ellipsis as '…', and whether the text
+    /// was truncated.
+    fn truncated_lines(
+        font_storage: &mut FontStorage,
+        runs: &[(&str, fontdb::ID, u32)],
+        config: &TextLayoutConfig,
+    ) -> (Vec<String>, bool) {
+        let mut text = TextData::new();
+        for &(content, font_id, user_data) in runs {
+            text.append(TextElement::new(font_id, 10.0, content, user_data));
+        }
+        let layout = text.layout(config, font_storage);
+        let lines = layout
+            .lines
+            .iter()
+            .map(|line| {
+                line.glyphs
+                    .iter()
+                    .map(|glyph| match glyph.source.is_empty() {
+                        true => '…',
+                        false => runs[glyph.element].0[glyph.source.clone()]
+                            .chars()
+                            .next()
+                            .unwrap(),
+                    })
+                    .collect()
+            })
+            .collect();
+        (lines, layout.truncated)
+    }
+
+    #[test]
+    fn test_truncation_lines() {
+        let mut font_storage = FontStorage::new();
+        let font_id = crate::font_storage::tests::push_face(&mut font_storage, "Test", &['a', '…']);
+        let runs = [("aa\naa\naa", font_id, 0)];
+        let mut lines = |config| truncated_lines(&mut font_storage, &runs, &config);
+        let three = vec!["aa".to_string(), "aa".to_string(), "aa".to_string()];
+
+        // Lines past the layout box are only dropped when clipping.
+        let max_height = |max_height, overflow| TextLayoutConfig {
+            max_height: Some(max_height),
+            overflow,
+            ..Default::default()
+        };
+        assert_eq!(
+            lines(max_height(25.0, TextOverflow::Visible)),
+            (three.clone(), false)
+        );
+        assert_eq!(
+            lines(max_height(25.0, TextOverflow::Clip)),
+            (three[..2].to_vec(), true)
+        );
+        assert_eq!(
+            lines(max_height(30.0, TextOverflow::Clip)),
+            (three.clone(), false)
+        );
+
+        // The line cap applies whatever the overflow.
+        let max_lines = |max_lines, overflow| TextLayoutConfig {
+            max_lines: Some(max_lines),
+            overflow,
+            ..Default::default()
+        };
+        assert_eq!(
+            lines(max_lines(1, TextOverflow::Visible)),
+            (three[..1].to_vec(), true)
+        );
+        assert_eq!(lines(max_lines(3, TextOverflow::Visible)), (three, false));
+
+        // The last kept line ends with an ellipsis when lines were dropped.
+        assert_eq!(
+            lines(max_lines(2, TextOverflow::Ellipsis)),
+            (vec!["aa".to_string(), "aa…".to_string()], true)
+        );
+    }
+
+    #[test]
+    fn test_truncation_ellipsis() {
+        use crate::font_storage::tests::{font_data_with_tables, outline_tables, push_face_data};
+
+        // Glyphs are inked over their whole advance, ellipsis included.
+        let mut font_storage = FontStorage::new();
+        let mut push_face = |family, chars: &[char]| {
+            let data = font_data_with_tables(chars, outline_tables(chars.len() as u16 + 1));
+            push_face_data(&mut font_storage, family, data)
+        };
+        let first = push_face("First", &['a', '…']);
+        let last = push_face("Last", &['b', '…']);
+        let runs = [("aaa", first, 1), ("bbbbbbb", last, 2)];
+        let ellipsis = |max_width| TextLayoutConfig {
+            max_width: Some(max_width),
+            wrap_style: WrapStyle::NoWrap,
+            overflow: TextOverflow::Ellipsis,
+            ..Default::default()
+        };
+
+        // Clusters are dropped until the ellipsis fits in 32px: five of them
+        // and the ellipsis take 30px.
+        let config = ellipsis(32.0);
+        assert_eq!(
+            truncated_lines(&mut font_storage, &runs, &config),
+            (vec!["aaabb…".to_string()], true)
+        );
+
+        // The ellipsis is set in the font of the last run and carries its user data.
+        let mut text = TextData::new();
+        for &(content, font_id, user_data) in &runs {
+            text.append(TextElement::new(font_id, 10.0, content, user_data));
+        }
+        let layout = text.layout(&config, &mut font_storage);
+        let glyph = layout.lines[0].glyphs.last().unwrap();
+        assert_eq!(
+            (glyph.glyph_id.font_id(), glyph.user_data, glyph.x),
+            (last, 2, 25.0)
+        );
+        assert_eq!(layout.total_width, 30.0);
+
+        // Text that fits is left alone.
+        assert_eq!(
+            truncated_lines(&mut font_storage, &runs, &ellipsis(50.0)),
+            (vec!["aaabbbbbbb".to_string()], false)
+        );
+
+        // An ellipsis wider than the line replaces all of it.
+        assert_eq!(
+            truncated_lines(&mut font_storage, &runs, &ellipsis(3.0)),
+            (vec!["…".to_string()], true)
+        );
+    }
+
+    /// Lays out `content` at 10px in a face whose glyphs are inked over their
+    /// 5px advance. Returns the characters of each line, with inserted hyphens
+    /// as '-', and its width.
+    fn inked_lines(content: &str, config: &TextLayoutConfig) -> Vec<(String, f32)> {
+        use crate::font_storage::tests::{font_data_with_tables, outline_tables, push_face_data};
+
+        let chars = [
+            'a', 'b', 'c', 'd', ' ', '-', '\u{AD}', '漢', '字', '日', '本',
+        ];
+        let data = font_data_with_tables(&chars, outline_tables(chars.len() as u16 + 1));
+        let mut font_storage = FontStorage::new();
+        let font_id = push_face_data(&mut font_storage, "Test", data);
+        let mut text = TextData::new();
+        text.append(TextElement::new(font_id, 10.0, content, ()));
+
+        text.layout(config, &mut font_storage)
+            .lines
+            .iter()
+            .map(|line| {
+                let chars = line
+                    .glyphs
+                    .iter()
+                    .map(|glyph| match glyph.source.is_empty() {
+                        true => '-',
+                        false => content[glyph.source.clone()].chars().next().unwrap(),
+                    })
+                    .collect();
+                (chars, line.line_width)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hyphenation() {
+        let config = |max_width, patterns: Option<&str>| TextLayoutConfig {
+            max_width: Some(max_width),
+            wrap_style: WrapStyle::WordWrap,
+            hyphenation: patterns.map(Hyphenator::new),
+            ..Default::default()
+        };
+        let line = |chars: &str, width| (chars.to_string(), width);
+
+        // A word overflowing the line is split at the pattern point, and the
+        // hyphen counts in the width of the line.
+        assert_eq!(
+            inked_lines("abcdabcd", &config(30.0, Some("d1a"))),
+            vec![line("abcd-", 25.0), line("abcd", 20.0)]
+        );
+        // The head and the hyphen fill the rest of a line holding other words.
+        assert_eq!(
+            inked_lines("c abcdabcd", &config(35.0, Some("d1a"))),
+            vec![line("c abcd-", 35.0), line("abcd", 20.0)]
+        );
+        // Without patterns the word is broken where the line is full.
+        assert_eq!(
+            inked_lines("abcdabcd", &config(30.0, None)),
+            vec![line("abcdab", 30.0), line("cd", 10.0)]
+        );
+    }
+
+    #[test]
+    fn test_soft_hyphens() {
+        let config = |max_width| TextLayoutConfig {
+            max_width: Some(max_width),
+            wrap_style: WrapStyle::WordWrap,
+            ..Default::default()
+        };
+        let line = |chars: &str, width| (chars.to_string(), width);
+
+        // A soft hyphen has no glyph and no width unless the line breaks there.
+        assert_eq!(
+            inked_lines("abc\u{AD}abc", &config(100.0)),
+            vec![line("abcabc", 30.0)]
+        );
+        assert_eq!(
+            inked_lines("abc\u{AD}abc", &config(25.0)),
+            vec![line("abc-", 20.0), line("abc", 15.0)]
+        );
+
+        // Soft hyphens take precedence over the patterns.
+        let patterns = TextLayoutConfig {
+            hyphenation: Some(Hyphenator::new("b1c")),
+            ..config(25.0)
+        };
+        assert_eq!(
+            inked_lines("abc\u{AD}abc", &patterns),
+            vec![line("abc-", 20.0), line("abc", 15.0)]
+        );
+    }
+
+    #[test]
+    fn test_hyphenation_without_point() {
+        let config = |patterns| TextLayoutConfig {
+            max_width: Some(30.0),
+            wrap_style: WrapStyle::WordWrap,
+            hyphenation: Some(Hyphenator::new(patterns)),
+            ..Default::default()
+        };
+        let line = |chars: &str, width| (chars.to_string(), width);
+
+        // "a1b" only matches too close to the ends of the word, so it moves
+        // whole to the next line.
+        assert_eq!(
+            inked_lines("c abcab", &config("a1b")),
+            vec![line("c ", 10.0), line("abcab", 25.0)]
+        );
+        // A usable point splits it beside the other words instead.
+        assert_eq!(
+            inked_lines("c abcab", &config("b1c")),
+            vec![line("c ab-", 25.0), line("cab", 15.0)]
+        );
+    }
+
+    #[test]
+    fn test_unicode_wrap() {
+        let config = |wrap_style| TextLayoutConfig {
+            max_width: Some(25.0),
+            wrap_style,
+            wrap_hard_break: false,
+            ..Default::default()
+        };
+        let lines = |content, wrap_style| -> Vec<String> {
+            inked_lines(content, &config(wrap_style))
+                .into_iter()
+                .map(|(chars, _)| chars)
+                .collect()
+        };
+
+        // Ideographs may be broken between any two of them, where word wrapping
+        // only breaks at spaces.
+        assert_eq!(
+            lines("漢字日本漢字", WrapStyle::UnicodeWrap),
+            vec!["漢字日本漢", "字"]
+        );
+        assert_eq!(
+            lines("漢字日本漢字", WrapStyle::WordWrap),
+            vec!["漢字日本漢字"]
+        );
+
+        // Mixed text breaks between Latin words and ideographs and after
+        // hyphens, but never inside a Latin word.
+        assert_eq!(
+            lines("abcd漢字ab-cd", WrapStyle::UnicodeWrap),
+            vec!["abcd漢", "字ab-", "cd"]
+        );
+        assert_eq!(
+            lines("abcd漢字ab-cd", WrapStyle::WordWrap),
+            vec!["abcd漢字ab-cd"]
+        );
+    }
+
+    /// A line as its source range, break kind and `(element, source, x)` of
+    /// each glyph.
+    type SourceLine = (
+        std::ops::Range<TextPosition>,
+        LineBreakKind,
+        Vec<(usize, std::ops::Range<usize>, f32)>,
+    );
+
+    fn source_map(
+        text: &TextData<()>,
+        config: &TextLayoutConfig,
+        font_storage: &mut FontStorage,
+    ) -> Vec<SourceLine> {
+        text.layout(config, font_storage)
+            .lines
+            .into_iter()
+            .map(|line| {
+                let glyphs = line
+                    .glyphs
+                    .iter()
+                    .map(|glyph| (glyph.element, glyph.source.clone(), glyph.x))
+                    .collect();
+                (line.source, line.break_kind, glyphs)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_source_mapping() {
+        let mut font_storage = FontStorage::new();
+        let font_id =
+            crate::font_storage::tests::push_face(&mut font_storage, "Test", &['a', ' ', 'א', 'ב']);
+        let mut text = TextData::new();
+        text.append(TextElement::new(font_id, 10.0, "aa aa\naa אב", ()));
+        let config = TextLayoutConfig {
+            max_width: Some(15.0),
+            wrap_style: WrapStyle::WordWrap,
+            ..Default::default()
+        };
+        let position = |offset| TextPosition { element: 0, offset };
+
+        // Lines cover the text without gaps: a soft break keeps the space it
+        // wrapped at, a hard break the line break character, which has no glyph.
+        // On the right-to-left line the first character is placed rightmost.
+        assert_eq!(
+            source_map(&text, &config, &mut font_storage),
+            vec![
+                (
+                    position(0)..position(3),
+                    LineBreakKind::Soft,
+                    vec![(0, 0..1, 0.0), (0, 1..2, 5.0), (0, 2..3, 10.0)],
+                ),
+                (
+                    position(3)..position(6),
+                    LineBreakKind::Hard,
+                    vec![(0, 3..4, 0.0), (0, 4..5, 5.0)],
+                ),
+                (
+                    position(6)..position(9),
+                    LineBreakKind::Soft,
+                    vec![(0, 6..7, 0.0), (0, 7..8, 5.0), (0, 8..9, 10.0)],
+                ),
+                (
+                    position(9)..position(13),
+                    LineBreakKind::EndOfText,
+                    vec![(0, 9..11, 5.0), (0, 11..13, 0.0)],
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_source_mapping_ligature() {
+        let mut font_storage = FontStorage::new();
+        let font_id = crate::font_storage::tests::push_face_data(
+            &mut font_storage,
+            "Test",
+            shaping::tests::feature_font_data(),
+        );
+        let mut text = TextData::new();
+        text.append(TextElement::new(font_id, 10.0, "a", ()));
+        text.append(TextElement::new(font_id, 10.0, "fia", ()));
+
+        // The ligature glyph maps back to both characters of its cluster.
+        let lines = source_map(&text, &TextLayoutConfig::default(), &mut font_storage);
+        let end = TextPosition {
+            element: 1,
+            offset: 3,
+        };
+        assert_eq!(
+            lines,
+            vec![(
+                TextPosition::default()..end,
+                LineBreakKind::EndOfText,
+                vec![(0, 0..1, 0.0), (1, 0..2, 5.0), (1, 2..3, 10.0)],
+            )]
+        );
+    }
+
+    #[test]
+    fn test_tab_stops() {
+        let mut font_storage = FontStorage::new();
+        let font_id =
+            crate::font_storage::tests::push_face(&mut font_storage, "Test", &['a', '1', '2', '.']);
+        let mut text = TextData::new();
+        text.append(TextElement::new(font_id, 10.0, "\taa\t1.22\taa\ta\ta", ()));
+        let stop = |position, align| TabStop { position, align };
+        let config = TextLayoutConfig {
+            tab_width: TabWidth::Pixels(25.0),
+            tab_stops: vec![
+                stop(20.0, TabAlign::Center),
+                stop(50.0, TabAlign::Decimal('.')),
+                stop(80.0, TabAlign::Right),
+            ],
+            ..Default::default()
+        };
+        let layout = text.layout(&config, &mut font_storage);
+        let glyphs: Vec<_> = layout.lines[0]
+            .glyphs
+            .iter()
+            .map(|glyph| (glyph.source.start, glyph.x))
+            .collect();
+
+        // "aa" is centered on 20, "1.22" has its '.' on 50 and "aa" ends at 80.
+        // Past the last explicit stop the tabs go to multiples of 25.
+        assert_eq!(
+            glyphs,
+            vec![
+                (1, 15.0),
+                (2, 20.0),
+                (4, 45.0),
+                (5, 50.0),
+                (6, 55.0),
+                (7, 60.0),
+                (9, 70.0),
+                (10, 75.0),
+                (12, 100.0),
+                (14, 125.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_align_tabs() {
+        let stop = |align| TabStop {
+            position: 50.0,
+            align,
+        };
+        // "a", a tab to 50, "bc" (element 2 for 'c'), a left tab to 70 and "d".
+        let line = |align| {
+            let ltr = unicode_bidi::Level::ltr();
+            let mut line = buffer("a");
+            line.push_gap(30.0, ltr, 0, 1..2);
+            line.clusters[1].tab_stop = Some(stop(align));
+            line.push(&fragment('b', 10.0, 0));
+            line.push(&fragment('c', 10.0, 2));
+            line.push_gap(10.0, ltr, 0, 4..5);
+            line.clusters[4].tab_stop = Some(TabStop {
+                position: 70.0,
+                align: TabAlign::Left,
+            });
+            line.push(&fragment('d', 10.0, 0));
+            line.align_tabs(|cluster, ch| cluster.element == 2 && ch == '.');
+            let glyphs: Vec<_> = line.glyphs.iter().map(|glyph| glyph.x).collect();
+            (offsets(&line), glyphs)
+        };
+        let glyphs = |b, c| vec![0.0, b, c, 70.0];
+
+        // The text after the next tab keeps its position.
+        let (clusters, placed) = line(TabAlign::Right);
+        assert_eq!(clusters, vec![0.0, 10.0, 30.0, 40.0, 50.0, 70.0]);
+        assert_eq!(placed, glyphs(30.0, 40.0));
+        let (clusters, placed) = line(TabAlign::Center);
+        assert_eq!(clusters, vec![0.0, 10.0, 40.0, 50.0, 60.0, 70.0]);
+        assert_eq!(placed, glyphs(40.0, 50.0));
+
+        // 'c' holds the decimal character, so it starts at the stop.
+        assert_eq!(line(TabAlign::Decimal('.')).1, glyphs(40.0, 50.0));
+        // Without it the text is right-aligned.
+        assert_eq!(line(TabAlign::Decimal(',')).1, glyphs(30.0, 40.0));
+    }
+}
@@ -0,0 +1,218 @@
+use std::{collections::HashSet, ops::Range};
+
+use unicode_bidi::{BidiClass, Level, ParagraphBidiInfo};
+
+use super::BaseDirection;
+use crate::text::{ParagraphStyle, TextElement};
+
+/// Embedding levels resolved over the concatenated content of a `TextData`.
+///
+/// Offsets are byte offsets into the concatenation of every
+/// [`TextElement::content`] in order.
+pub struct BidiLevels {
+    /// Level of every byte. Empty when the whole text resolves to level 0.
+    levels: Vec<Level>,
+    /// Paragraph ranges (split at the configured line break characters and at
+    /// explicit paragraphs) and their base level.
+    paragraphs: Vec<(Range<usize>, Level)>,
+}
+
+impl BidiLevels {
+    /// Runs the paragraph-level part of UAX #9 on every paragraph of `texts`.
+    ///
+    /// Paragraphs end at line break characters and before the elements
+    /// starting the explicit `paragraphs` of the text.
+    pub fn new<T>(
+        texts: &[TextElement<T>],
+        paragraphs: &[(usize, ParagraphStyle)],
+        base_direction: BaseDirection,
+        linebreak_char: &HashSet<char, fxhash::FxBuildHasher>,
+    ) -> Self {
+        let default_level = match base_direction {
+            BaseDirection::Auto => None,
+            BaseDirection::LeftToRight => Some(Level::ltr()),
+            BaseDirection::RightToLeft => Some(Level::rtl()),
+        };
+
+        let needs_resolution = base_direction == BaseDirection::RightToLeft
+            || texts
+                .iter()
+                .any(|text| text.content.chars().any(is_rtl_trigger));
+
+        if !needs_resolution {
+            return Self {
+                levels: Vec::new(),
+                paragraphs: Vec::new(),
+            };
+        }
+
+        let full_text: String = texts.iter().map(|text| text.content.as_str()).collect();
+        let element_starts: Vec<usize> = texts
+            .iter()
+            .scan(0, |offset, text| {
+                let start = *offset;
+                *offset += text.content.len();
+                Some(start)
+            })
+            .collect();
+        let mut explicit_starts = paragraphs
+            .iter()
+            .filter_map(|(element, _)| element_starts.get(*element).copied())
+            .peekable();
+
+        let mut levels = Vec::with_capacity(full_text.len());
+        let mut paragraphs = Vec::new();
+
+        let mut paragraph_start = 0usize;
+        let mut resolve_paragraph = |range: Range<usize>, break_len: usize| {
+            let info = ParagraphBidiInfo::new(&full_text[range.clone()], default_level);
+            levels.extend_from_slice(&info.levels);
+            // The line break character itself belongs to the paragraph it terminates.
+            levels.extend(std::iter::repeat_n(info.paragraph_level, break_len));
+            paragraphs.push((range.start..range.end + break_len, info.paragraph_level));
+        };
+
+        for (offset, ch) in full_text.char_indices() {
+            while let Some(start) = explicit_starts.next_if(|&start| start <= offset) {
+                if start > paragraph_start {
+                    resolve_paragraph(paragraph_start..start, 0);
+                    paragraph_start = start;
+                }
+            }
+            if linebreak_char.contains(&ch) {
+                resolve_paragraph(paragraph_start..offset, ch.len_utf8());
+                paragraph_start = offset + ch.len_utf8();
+            }
+        }
+        resolve_paragraph(paragraph_start..full_text.len(), 0);
+
+        Self { levels, paragraphs }
+    }
+
+    /// Returns the base level of the paragraph containing `offset`.
+    pub fn paragraph_level_at(&self, offset: usize) -> Level {
+        let index = self
+            .paragraphs
+            .partition_point(|(range, _)| range.end <= offset);
+
+        self.paragraphs
+            .get(index)
+            .or(self.paragraphs.last())
+            .map(|(_, level)| *level)
+            .unwrap_or_else(Level::ltr)
+    }
+
+    /// Splits `range` into consecutive sub-ranges that share a single level.
+    pub fn level_runs(&self, range: Range<usize>) -> Vec<(Range<usize>, Level)> {
+        if self.levels.is_empty() || range.is_empty() {
+            return vec![(range, Level::ltr())];
+        }
+
+        let mut runs: Vec<(Range<usize>, Level)> = Vec::new();
+        for offset in range.clone() {
+            let level = self.levels[offset];
+            match runs.last_mut() {
+                Some((run, run_level)) if *run_level == level => run.end = offset + 1,
+                _ => runs.push((offset..offset + 1, level)),
+            }
+        }
+
+        runs
+    }
+}
+
+/// Returns `true` for characters that can introduce right-to-left levels.
+///
+/// Text without any of these characters always resolves to level 0 in a
+/// left-to-right paragraph, which lets us skip the resolution entirely.
+fn is_rtl_trigger(ch: char) -> bool {
+    matches!(
+        unicode_bidi::bidi_class(ch),
+        BidiClass::R
+            | BidiClass::AL
+            | BidiClass::AN
+            | BidiClass::RLE
+            | BidiClass::RLO
+            | BidiClass::RLI
+            | BidiClass::FSI
+    )
+}
+
+/// Computes the visual order of the items of a line (rules L1 and L2 of UAX #9).
+///
+/// `levels` holds the resolved level of each item in logical order and
+/// `trailing_whitespace` the number of whitespace items at the end of the line,
+/// which are reset to the paragraph level. The returned vector maps visual
+/// position to logical index.
+pub fn reorder_line(
+    levels: &[Level],
+    trailing_whitespace: usize,
+    paragraph_level: Level,
+) -> Vec<usize> {
+    let mut levels = levels.to_vec();
+    let len = levels.len();
+    for level in &mut levels[len.saturating_sub(trailing_whitespace)..] {
+        *level = paragraph_level;
+    }
+
+    ParagraphBidiInfo::reorder_visual(&levels)
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(v: &[u8]) -> Vec<Level> {
+        v.iter().map(|&l| Level::new(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_reorder_line_mixed() {
+        // "abc ABC def" where ABC is right-to-left.
+        let order = reorder_line(&levels(&[0, 0, 1, 1, 0, 0]), 0, Level::ltr());
+        assert_eq!(order, vec![0, 1, 3, 2, 4, 5]);
+    }
+
+    #[test]
+    fn test_reorder_line_trailing_whitespace() {
+        // Trailing whitespace stays at the visual end of a right-to-left paragraph.
+        let order = reorder_line(&levels(&[1, 1, 0]), 1, Level::rtl());
+        assert_eq!(order, vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn test_paragraph_levels() {
+        let font_id: fontdb::ID = unsafe { std::mem::transmute(1u64) };
+        let texts = vec![TextElement::new(font_id, 12.0, "abc\nשלום", ())];
+        let linebreak_char = ['\n'].into_iter().collect();
+        let bidi = BidiLevels::new(&texts, &[], BaseDirection::Auto, &linebreak_char);
+
+        assert!(bidi.paragraph_level_at(0).is_ltr());
+        assert!(bidi.paragraph_level_at(4).is_rtl());
+        assert_eq!(bidi.level_runs(0..4).len(), 1);
+        assert_eq!(bidi.level_runs(2..6).len(), 2);
+    }
+
+    #[test]
+    fn test_explicit_paragraph_levels() {
+        let font_id: fontdb::ID = unsafe { std::mem::transmute(1u64) };
+        let texts = vec![
+            TextElement::new(font_id, 12.0, "שלום", ()),
+            TextElement::new(font_id, 12.0, " abc", ()),
+            TextElement::new(font_id, 12.0, "def", ()),
+        ];
+        let linebreak_char = ['\n'].into_iter().collect();
+        let paragraphs = [
+            (1, ParagraphStyle::default()),
+            (2, ParagraphStyle::default()),
+        ];
+        let bidi = BidiLevels::new(&texts, &paragraphs, BaseDirection::Auto, &linebreak_char);
+
+        // Each element is a paragraph of its own direction.
+        assert!(bidi.paragraph_level_at(0).is_rtl());
+        assert!(bidi.paragraph_level_at(8).is_ltr());
+        assert!(bidi.paragraph_level_at(12).is_ltr());
+        assert_eq!(bidi.level_runs(8..12), vec![(8..12, Level::ltr())]);
+    }
+}
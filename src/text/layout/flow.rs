@@ -0,0 +1,377 @@
+use crate::text::TextData;
+
+use super::{LayoutEngine, TextAnalysis, TextLayout, TextLayoutConfig, TextOverflow, TextPosition};
+
+/// Text laid out across a sequence of boxes, returned by [`TextData::layout_boxes`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextFlow<T> {
+    /// One layout per box, in order, so `layouts[i]` fills `boxes[i]`. Boxes
+    /// after the end of the text are left out.
+    pub layouts: Vec<TextLayout<T>>,
+    /// Indices of the boxes too short for the next line. Their layouts have no
+    /// lines, and the text goes on in the box after them.
+    pub skipped: Vec<usize>,
+    /// Where the text stopped when it did not fit in the boxes, to resume
+    /// from with more boxes. `None` when all of it was laid out.
+    pub end: Option<TextPosition>,
+}
+
+impl<T: Clone> TextData<T> {
+    /// Lays out the text from `start` into each of `boxes` (`[width, height]`)
+    /// in turn, like columns or pages.
+    ///
+    /// Each box is laid out with `config`, its size replacing `max_width` and
+    /// `max_height`. Lines that do not fit in a box are dropped and continue in
+    /// the next one; with [`TextOverflow::Ellipsis`] the last box ends with an
+    /// ellipsis. Positions in the layouts refer to this text, so
+    /// [`TextFlow::end`] can be passed back as `start` for the next page.
+    ///
+    /// A box starting inside a paragraph continues it: the paragraph keeps the
+    /// direction resolved for all of it, and a line wrapped at the end of the
+    /// previous box is not indented as a first line.
+    ///
+    /// A box that cannot hold the next line is listed in [`TextFlow::skipped`]
+    /// and gets an empty layout; the line is tried again in the next box.
+    ///
+    /// A `start` inside a character is moved back to the start of it.
+    pub fn layout_boxes(
+        &self,
+        start: TextPosition,
+        boxes: &[[f32; 2]],
+        config: &TextLayoutConfig,
+        font_storage: &mut crate::font_storage::FontStorage,
+    ) -> TextFlow<T> {
+        // Boxes only change the size of the layout, not how the text is analyzed.
+        let analysis = TextAnalysis::new(&self.texts, &self.paragraphs, config);
+        let mut layouts = Vec::with_capacity(boxes.len());
+        let mut skipped = Vec::new();
+        let mut position = Some(self.char_boundary(start));
+
+        for (index, &[width, height]) in boxes.iter().enumerate() {
+            let Some(start) = position.filter(|&start| !self.ends_at(start)) else {
+                position = None;
+                break;
+            };
+
+            let last = index + 1 == boxes.len();
+            let config = TextLayoutConfig {
+                max_width: Some(width),
+                max_height: Some(height),
+                overflow: if last && config.overflow == TextOverflow::Ellipsis {
+                    TextOverflow::Ellipsis
+                } else {
+                    TextOverflow::Clip
+                },
+                ..config.clone()
+            };
+
+            let layout = LayoutEngine::new(
+                &config,
+                font_storage,
+                &self.texts,
+                &self.paragraphs,
+                &self.ruby,
+                &analysis,
+            )
+            .layout_from(start);
+            if layout.lines.is_empty() {
+                skipped.push(index);
+            }
+            position = layout
+                .truncated
+                .then(|| layout.lines.last().map_or(start, |line| line.source.end));
+            layouts.push(layout);
+        }
+
+        TextFlow {
+            layouts,
+            skipped,
+            end: position.filter(|&end| !self.ends_at(end)),
+        }
+    }
+
+    /// Moves `position` back to the closest character boundary of its element.
+    fn char_boundary(&self, position: TextPosition) -> TextPosition {
+        let Some(text) = self.texts.get(position.element) else {
+            return position;
+        };
+        let offset = (0..=position.offset.min(text.content.len()))
+            .rev()
+            .find(|&offset| text.content.is_char_boundary(offset))
+            .unwrap_or(0);
+        TextPosition { offset, ..position }
+    }
+
+    /// Checks whether no text is left from `position`.
+    fn ends_at(&self, position: TextPosition) -> bool {
+        self.texts
+            .iter()
+            .enumerate()
+            .skip(position.element)
+            .all(|(index, text)| {
+                let offset = if index == position.element {
+                    position.offset
+                } else {
+                    0
+                };
+                text.content.len() <= offset
+            })
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::{ParagraphStyle, TextElement};
+
+    fn element(content: &str) -> TextElement<()> {
+        let font_id: fontdb::ID = unsafe { std::mem::transmute(1u64) };
+        TextElement::new(font_id, 12.0, content, ())
+    }
+
+    #[test]
+    fn test_layout_boxes() {
+        let mut text = TextData::new();
+        for _ in 0..5 {
+            text.append(
+                element("\u{FFFC}").with_inline_object(crate::text::InlineObject {
+                    width: 30.0,
+                    ascent: 8.0,
+                    descent: 2.0,
+                }),
+            );
+        }
+        let config = TextLayoutConfig {
+            wrap_style: crate::text::WrapStyle::CharWrap,
+            ..Default::default()
+        };
+        let mut font_storage = crate::font_storage::FontStorage::new();
+
+        // Two lines of two objects fit in the boxes, the fifth object is left.
+        // A start inside the first object is moved back to its start.
+        let flow = text.layout_boxes(
+            TextPosition {
+                element: 0,
+                offset: 1,
+            },
+            &[[70.0, 10.0], [70.0, 15.0]],
+            &config,
+            &mut font_storage,
+        );
+        let lines: Vec<_> = flow
+            .layouts
+            .iter()
+            .map(|layout| {
+                assert_eq!(layout.lines.len(), 1);
+                let line = &layout.lines[0];
+                (line.source.start.element, line.objects.len())
+            })
+            .collect();
+        assert_eq!(lines, vec![(0, 2), (2, 2)]);
+        let end = TextPosition {
+            element: 4,
+            offset: 0,
+        };
+        assert_eq!(flow.end, Some(end));
+
+        let rest = text.layout_boxes(end, &[[70.0, 10.0]], &config, &mut font_storage);
+        assert_eq!(rest.layouts.len(), 1);
+        assert_eq!(rest.end, None);
+    }
+
+    /// Adds a face whose glyphs are inked over their 5px advance at 10px.
+    fn push_inked_face(font_storage: &mut crate::font_storage::FontStorage) -> fontdb::ID {
+        use crate::font_storage::tests::{font_data_with_tables, outline_tables, push_face_data};
+
+        let chars = ['a', 'א', 'ב'];
+        let data = font_data_with_tables(&chars, outline_tables(chars.len() as u16 + 1));
+        push_face_data(font_storage, "Test", data)
+    }
+
+    /// Returns the x of the glyphs of each line of the layouts.
+    fn glyph_x(layouts: &[TextLayout<()>]) -> Vec<Vec<f32>> {
+        layouts
+            .iter()
+            .flat_map(|layout| &layout.lines)
+            .map(|line| line.glyphs.iter().map(|glyph| glyph.x).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_layout_boxes_resumes_paragraph() {
+        let mut font_storage = crate::font_storage::FontStorage::new();
+        let font_id = push_inked_face(&mut font_storage);
+        let mut text = TextData::new();
+        text.start_paragraph(ParagraphStyle {
+            first_line_indent: 20.0,
+            hanging_indent: 5.0,
+            space_before: 10.0,
+            ..Default::default()
+        });
+        text.append(TextElement::new(font_id, 10.0, "aaaaaa", ()));
+        let config = TextLayoutConfig {
+            wrap_style: crate::text::WrapStyle::CharWrap,
+            ..Default::default()
+        };
+
+        // After a wrap, the paragraph goes on with the indent of its other
+        // lines and without space before, like in a single layout.
+        let flow = text.layout_boxes(
+            TextPosition::default(),
+            &[[30.0, 20.0], [30.0, 20.0]],
+            &config,
+            &mut font_storage,
+        );
+        assert_eq!(
+            glyph_x(&flow.layouts),
+            vec![vec![20.0, 25.0], vec![5.0, 10.0, 15.0, 20.0]]
+        );
+        assert_eq!(flow.layouts[0].lines[0].top, 10.0);
+        assert_eq!(flow.layouts[1].lines[0].top, 0.0);
+        let single = text.layout(
+            &TextLayoutConfig {
+                max_width: Some(30.0),
+                ..config.clone()
+            },
+            &mut font_storage,
+        );
+        assert_eq!(glyph_x(&flow.layouts), glyph_x(&[single]));
+    }
+
+    #[test]
+    fn test_layout_boxes_keeps_direction() {
+        let mut font_storage = crate::font_storage::FontStorage::new();
+        let font_id = push_inked_face(&mut font_storage);
+        let mut text = TextData::new();
+        text.append(TextElement::new(font_id, 10.0, "אבaaaaa", ()));
+        let config = TextLayoutConfig {
+            wrap_style: crate::text::WrapStyle::CharWrap,
+            ..Default::default()
+        };
+
+        // The paragraph is right-to-left from its first letter, so the Latin
+        // text continuing it in the second box is still aligned to the right.
+        let flow = text.layout_boxes(
+            TextPosition::default(),
+            &[[20.0, 10.0], [20.0, 10.0]],
+            &config,
+            &mut font_storage,
+        );
+        assert_eq!(
+            flow.layouts[1].lines[0].source.start,
+            TextPosition {
+                element: 0,
+                offset: 6,
+            }
+        );
+        assert_eq!(glyph_x(&flow.layouts)[1], vec![5.0, 10.0, 15.0]);
+        let single = text.layout(
+            &TextLayoutConfig {
+                max_width: Some(20.0),
+                ..config.clone()
+            },
+            &mut font_storage,
+        );
+        assert_eq!(glyph_x(&flow.layouts), glyph_x(&[single]));
+    }
+
+    #[test]
+    fn test_layout_boxes_skips_short_box() {
+        let mut text = TextData::new();
+        for _ in 0..4 {
+            text.append(
+                element("\u{FFFC}").with_inline_object(crate::text::InlineObject {
+                    width: 30.0,
+                    ascent: 8.0,
+                    descent: 2.0,
+                }),
+            );
+        }
+        let config = TextLayoutConfig {
+            wrap_style: crate::text::WrapStyle::CharWrap,
+            ..Default::default()
+        };
+        let mut font_storage = crate::font_storage::FontStorage::new();
+        let start = TextPosition {
+            element: 0,
+            offset: 0,
+        };
+
+        // The first box is shorter than a line: it stays empty and the line
+        // goes into the second box instead of being dropped or repeated.
+        let flow = text.layout_boxes(
+            start,
+            &[[70.0, 5.0], [70.0, 10.0], [70.0, 10.0]],
+            &config,
+            &mut font_storage,
+        );
+        assert_eq!(flow.skipped, vec![0]);
+        let lines: Vec<_> = flow
+            .layouts
+            .iter()
+            .map(|layout| {
+                layout
+                    .lines
+                    .iter()
+                    .map(|line| (line.source.start.element, line.objects.len()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(lines, vec![vec![], vec![(0, 2)], vec![(2, 2)]]);
+        assert_eq!(flow.end, None);
+
+        // With no box tall enough, the text does not move on.
+        let flow = text.layout_boxes(start, &[[70.0, 5.0]], &config, &mut font_storage);
+        assert_eq!(flow.skipped, vec![0]);
+        assert_eq!(flow.end, Some(start));
+    }
+
+    #[test]
+    fn test_layout_boxes_at_line_break() {
+        let mut font_storage = crate::font_storage::FontStorage::new();
+        let font_id = crate::font_storage::tests::push_face(&mut font_storage, "Test", &['a', 'b']);
+        let style = ParagraphStyle {
+            first_line_indent: 20.0,
+            hanging_indent: 5.0,
+            ..Default::default()
+        };
+        let mut text = TextData::new();
+        text.start_paragraph(style);
+        text.append(TextElement::new(font_id, 10.0, "aa\nbb", ()));
+        let config = TextLayoutConfig::default();
+        let position = |offset| TextPosition { element: 0, offset };
+
+        // The page ends after the line break, which stays on the first page.
+        let flow = text.layout_boxes(position(0), &[[100.0, 10.0]], &config, &mut font_storage);
+        let line = &flow.layouts[0].lines[0];
+        assert_eq!(line.source, position(0)..position(3));
+        assert_eq!(line.break_kind, crate::text::LineBreakKind::Hard);
+        assert_eq!(flow.end, Some(position(3)));
+
+        // The next page starts on the line after it, as a new first line.
+        let rest = text.layout_boxes(position(3), &[[100.0, 10.0]], &config, &mut font_storage);
+        let line = &rest.layouts[0].lines[0];
+        assert_eq!(line.source, position(3)..position(5));
+        let glyphs: Vec<_> = line
+            .glyphs
+            .iter()
+            .map(|glyph| (glyph.source.clone(), glyph.x))
+            .collect();
+        assert_eq!(glyphs, vec![(3..4, 20.0), (4..5, 25.0)]);
+        assert_eq!(rest.end, None);
+
+        // Both in one flow give the same lines.
+        let both = text.layout_boxes(
+            position(0),
+            &[[100.0, 10.0], [100.0, 10.0]],
+            &config,
+            &mut font_storage,
+        );
+        assert_eq!(
+            both.layouts,
+            vec![flow.layouts[0].clone(), rest.layouts[0].clone()]
+        );
+        assert!(both.skipped.is_empty());
+    }
+}
@@ -0,0 +1,462 @@
+use std::ops::Range;
+
+use super::{ClusterPosition, TextLayout, TextLayoutLine, TextPosition, WritingMode};
+
+/// Source position under a point, returned by [`TextLayout::hit_test`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HitTestResult {
+    /// Index of the [`TextElement`](crate::text::TextElement) that was hit.
+    pub element: usize,
+    /// Byte offset of the cluster that was hit inside the element's content.
+    pub offset: usize,
+    /// Byte length of that cluster. `0` for empty lines and inserted text
+    /// (hyphens, ellipses).
+    pub len: usize,
+    /// Whether the point is on the trailing half of the cluster in reading
+    /// order: the right half of left-to-right text, the left half of
+    /// right-to-left text.
+    pub trailing: bool,
+}
+
+/// Axis-aligned rectangle in layout coordinates. **Y-axis goes down**
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct LayoutRect {
+    /// The X coordinate of the left edge.
+    pub left: f32,
+    /// The Y coordinate of the top edge.
+    pub top: f32,
+    /// The X coordinate of the right edge.
+    pub right: f32,
+    /// The Y coordinate of the bottom edge.
+    pub bottom: f32,
+}
+
+impl HitTestResult {
+    /// Returns the caret position closest to the point: before the cluster for
+    /// a leading hit, after it for a trailing one.
+    pub fn caret_position(&self) -> TextPosition {
+        TextPosition {
+            element: self.element,
+            offset: if self.trailing {
+                self.offset + self.len
+            } else {
+                self.offset
+            },
+        }
+    }
+}
+
+impl<T> TextLayout<T> {
+    /// Maps a point in layout coordinates to a position in the source text.
+    ///
+    /// Points above the first line or below the last one hit that line, and
+    /// points before or after a line hit its closest cluster. When a band of a
+    /// [`LayoutShape`](super::LayoutShape) holds several lines, the one closest
+    /// along the band is hit. In [`WritingMode::VerticalRightToLeft`] the same
+    /// applies to columns. Returns `None` if the layout has no lines.
+    pub fn hit_test(&self, x: f32, y: f32) -> Option<HitTestResult> {
+        let vertical = self.config.writing_mode == WritingMode::VerticalRightToLeft;
+        let (along, across) = if vertical { (y, x) } else { (x, y) };
+        // Extents of a line across and along it.
+        let band = |line: &TextLayoutLine<T>| {
+            if vertical {
+                (line.left, line.right)
+            } else {
+                (line.top, line.bottom)
+            }
+        };
+        let span = |line: &TextLayoutLine<T>| {
+            if vertical {
+                (line.top, line.bottom)
+            } else {
+                (line.left, line.right)
+            }
+        };
+
+        let first = self
+            .lines
+            .iter()
+            .find(|line| {
+                if vertical {
+                    across >= line.left
+                } else {
+                    across < line.bottom
+                }
+            })
+            .or(self.lines.last())?;
+
+        let distance = |line: &&TextLayoutLine<T>| {
+            let (start, end) = span(line);
+            (start - along).max(along - end).max(0.0)
+        };
+        let line = self
+            .lines
+            .iter()
+            .filter(|line| band(line) == band(first))
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .unwrap_or(first);
+
+        Some(line.hit_test(along))
+    }
+
+    /// Returns the caret rectangle for a position in the source text.
+    ///
+    /// The rectangle covers the line box across the line and has no extent
+    /// along it, so callers choose the caret thickness. A position at a soft
+    /// line break is placed at the start of the next line. Positions that are
+    /// not laid out (whitespace dropped at a line break, truncated text) use the
+    /// closest preceding cluster. Returns `None` if the layout has no lines.
+    pub fn caret_rect(&self, position: TextPosition) -> Option<LayoutRect> {
+        // Trailing edge of the last cluster before `position`.
+        let mut preceding: Option<(&TextLayoutLine<T>, f32, TextPosition)> = None;
+
+        for line in &self.lines {
+            if line.clusters.is_empty() {
+                if line.source.start == position {
+                    let edge = self.start_edge(line);
+                    return Some(self.span_rect(line, edge, edge));
+                }
+                if line.source.start < position
+                    && preceding.is_none_or(|(_, _, end)| end <= line.source.start)
+                {
+                    preceding = Some((line, self.start_edge(line), line.source.start));
+                }
+                continue;
+            }
+
+            // Inserted text (hyphens, ellipses) has no source position.
+            for cluster in line.clusters.iter().filter(|c| !c.source.is_empty()) {
+                let (start, end) = cluster.bounds();
+                if start <= position && position < end {
+                    let edge = cluster.edge(position.offset);
+                    return Some(self.span_rect(line, edge, edge));
+                }
+                if end <= position && preceding.is_none_or(|(_, _, last)| last <= end) {
+                    preceding = Some((line, cluster.edge(cluster.source.end), end));
+                }
+            }
+        }
+
+        let (line, edge) = match preceding {
+            Some((line, edge, _)) => (line, edge),
+            None => {
+                let line = self.lines.first()?;
+                (line, self.start_edge(line))
+            }
+        };
+        Some(self.span_rect(line, edge, edge))
+    }
+
+    /// Returns the rectangles covering the source text in `range`.
+    ///
+    /// There is one rectangle per visually contiguous span of each line, so a
+    /// range crossing a line break or a bidi boundary yields several of them.
+    /// Each rectangle covers the line box across the line. Empty lines whose
+    /// line break is inside the range get a rectangle with no extent along the
+    /// line, which callers may widen to show the selected line break.
+    pub fn selection_rects(&self, range: Range<TextPosition>) -> Vec<LayoutRect> {
+        let mut rects = Vec::new();
+        if range.is_empty() {
+            return rects;
+        }
+
+        for line in &self.lines {
+            if line.clusters.is_empty() {
+                if range.contains(&line.source.start) {
+                    let edge = self.start_edge(line);
+                    rects.push(self.span_rect(line, edge, edge));
+                }
+                continue;
+            }
+
+            let mut spans: Vec<(f32, f32)> = line
+                .clusters
+                .iter()
+                .filter_map(|cluster| {
+                    let (start, end) = cluster.overlap(&range)?;
+                    let (a, b) = (cluster.edge(start), cluster.edge(end));
+                    Some((a.min(b), a.max(b)))
+                })
+                .collect();
+            spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut merged: Vec<(f32, f32)> = Vec::with_capacity(spans.len());
+            for (start, end) in spans {
+                match merged.last_mut() {
+                    // Adjacent clusters touch, up to rounding errors.
+                    Some(last) if start <= last.1 + 0.01 => last.1 = last.1.max(end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            rects.extend(
+                merged
+                    .into_iter()
+                    .map(|(start, end)| self.span_rect(line, start, end)),
+            );
+        }
+
+        rects
+    }
+
+    /// Returns the caret offset along `line` before its first character.
+    fn start_edge(&self, line: &TextLayoutLine<T>) -> f32 {
+        match (line.clusters.first(), self.config.writing_mode) {
+            (Some(cluster), _) => cluster.edge(cluster.source.start),
+            (None, WritingMode::HorizontalTopToBottom) => line.left,
+            (None, WritingMode::VerticalRightToLeft) => line.top,
+        }
+    }
+
+    /// Builds the rectangle covering `start..end` along `line`.
+    fn span_rect(&self, line: &TextLayoutLine<T>, start: f32, end: f32) -> LayoutRect {
+        match self.config.writing_mode {
+            WritingMode::HorizontalTopToBottom => LayoutRect {
+                left: start,
+                top: line.top,
+                right: end,
+                bottom: line.bottom,
+            },
+            WritingMode::VerticalRightToLeft => LayoutRect {
+                left: line.left,
+                top: start,
+                right: line.right,
+                bottom: end,
+            },
+        }
+    }
+}
+
+impl<T> TextLayoutLine<T> {
+    /// Finds the cluster closest to `along`, a coordinate along the line.
+    fn hit_test(&self, along: f32) -> HitTestResult {
+        let distance = |start: f32, end: f32| (start - along).max(along - end).max(0.0);
+        let closest = self.clusters.iter().min_by(|a, b| {
+            distance(a.offset, a.offset + a.advance)
+                .total_cmp(&distance(b.offset, b.offset + b.advance))
+        });
+
+        match closest {
+            Some(cluster) => {
+                let second_half = along >= cluster.offset + cluster.advance / 2.0;
+                HitTestResult {
+                    element: cluster.element,
+                    offset: cluster.source.start,
+                    len: cluster.source.len(),
+                    trailing: second_half != cluster.rtl,
+                }
+            }
+            None => HitTestResult {
+                element: self.source.start.element,
+                offset: self.source.start.offset,
+                len: 0,
+                trailing: false,
+            },
+        }
+    }
+}
+
+impl ClusterPosition {
+    /// Returns the source positions of the start and the end of the cluster.
+    fn bounds(&self) -> (TextPosition, TextPosition) {
+        (
+            TextPosition {
+                element: self.element,
+                offset: self.source.start,
+            },
+            TextPosition {
+                element: self.element,
+                offset: self.source.end,
+            },
+        )
+    }
+
+    /// Returns the byte range of the cluster covered by `range`, if any.
+    fn overlap(&self, range: &Range<TextPosition>) -> Option<(usize, usize)> {
+        let (start, end) = self.bounds();
+        if self.source.is_empty() || end <= range.start || range.end <= start {
+            return None;
+        }
+
+        Some((start.max(range.start).offset, end.min(range.end).offset))
+    }
+
+    /// Returns the caret offset along the line before byte `offset` of the cluster.
+    ///
+    /// Offsets inside a cluster (e.g. a ligature) are interpolated.
+    fn edge(&self, offset: usize) -> f32 {
+        let len = self.source.len().max(1) as f32;
+        let fraction = (offset.saturating_sub(self.source.start) as f32 / len).min(1.0);
+        if self.rtl {
+            self.offset + self.advance * (1.0 - fraction)
+        } else {
+            self.offset + self.advance * fraction
+        }
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::layout::{ClusterPosition, LineBreakKind, TextLayoutConfig};
+
+    fn line(top: f32, start: TextPosition, clusters: &[(usize, f32, bool)]) -> TextLayoutLine<()> {
+        let mut offset = 10.0;
+        let clusters = clusters
+            .iter()
+            .map(|&(source, advance, rtl)| {
+                let cluster = ClusterPosition {
+                    element: start.element,
+                    source: source..source + 1,
+                    offset,
+                    advance,
+                    rtl,
+                };
+                offset += advance;
+                cluster
+            })
+            .collect();
+
+        TextLayoutLine {
+            line_height: 20.0,
+            line_width: offset - 10.0,
+            top,
+            bottom: top + 20.0,
+            left: 10.0,
+            right: offset,
+            source: start..start,
+            break_kind: LineBreakKind::Soft,
+            glyphs: Vec::new(),
+            clusters,
+            objects: Vec::new(),
+            decorations: Vec::new(),
+        }
+    }
+
+    fn layout() -> TextLayout<()> {
+        let lines = vec![
+            // "ab"
+            line(
+                0.0,
+                TextPosition::default(),
+                &[(0, 10.0, false), (1, 10.0, false)],
+            ),
+            // Empty line of "ab\n\n".
+            line(
+                20.0,
+                TextPosition {
+                    element: 0,
+                    offset: 3,
+                },
+                &[],
+            ),
+            // Right-to-left "cd", clusters listed in visual order.
+            line(
+                40.0,
+                TextPosition {
+                    element: 1,
+                    offset: 0,
+                },
+                &[(1, 10.0, true), (0, 10.0, true)],
+            ),
+        ];
+
+        TextLayout {
+            config: TextLayoutConfig::default(),
+            total_height: 60.0,
+            total_width: 20.0,
+            lines,
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn test_hit_test_inside_lines() {
+        let layout = layout();
+
+        let hit = layout.hit_test(12.0, 5.0).unwrap();
+        assert_eq!((hit.element, hit.offset, hit.trailing), (0, 0, false));
+        let hit = layout.hit_test(18.0, 5.0).unwrap();
+        assert_eq!((hit.element, hit.offset, hit.trailing), (0, 0, true));
+        assert_eq!(hit.caret_position().offset, 1);
+
+        let hit = layout.hit_test(15.0, 25.0).unwrap();
+        assert_eq!((hit.element, hit.offset, hit.len), (0, 3, 0));
+
+        // The left half of a right-to-left cluster is its trailing half.
+        let hit = layout.hit_test(12.0, 45.0).unwrap();
+        assert_eq!((hit.element, hit.offset, hit.trailing), (1, 1, true));
+        assert_eq!(hit.caret_position().offset, 2);
+    }
+
+    #[test]
+    fn test_hit_test_outside_lines() {
+        let layout = layout();
+
+        let hit = layout.hit_test(-5.0, -5.0).unwrap();
+        assert_eq!((hit.element, hit.offset, hit.trailing), (0, 0, false));
+        let hit = layout.hit_test(100.0, 5.0).unwrap();
+        assert_eq!((hit.element, hit.offset, hit.trailing), (0, 1, true));
+
+        // Right of a right-to-left line is its logical start.
+        let hit = layout.hit_test(100.0, 100.0).unwrap();
+        assert_eq!((hit.element, hit.offset, hit.trailing), (1, 0, false));
+        assert_eq!(hit.caret_position().offset, 0);
+        let hit = layout.hit_test(0.0, 100.0).unwrap();
+        assert_eq!((hit.element, hit.offset, hit.trailing), (1, 1, true));
+
+        assert!(
+            TextLayout::<()> {
+                lines: Vec::new(),
+                ..layout
+            }
+            .hit_test(0.0, 0.0)
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_caret_rect() {
+        let layout = layout();
+        let caret = |element, offset| {
+            let rect = layout.caret_rect(TextPosition { element, offset }).unwrap();
+            assert_eq!(rect.left, rect.right);
+            (rect.left, rect.top)
+        };
+
+        assert_eq!(caret(0, 0), (10.0, 0.0));
+        assert_eq!(caret(0, 2), (30.0, 0.0));
+        assert_eq!(caret(0, 3), (10.0, 20.0));
+        assert_eq!(caret(1, 0), (30.0, 40.0));
+        assert_eq!(caret(1, 2), (10.0, 40.0));
+        // Past the end of the text.
+        assert_eq!(caret(5, 0), (10.0, 40.0));
+    }
+
+    #[test]
+    fn test_selection_rects() {
+        let layout = layout();
+        let rects = |start: (usize, usize), end: (usize, usize)| {
+            layout
+                .selection_rects(
+                    TextPosition {
+                        element: start.0,
+                        offset: start.1,
+                    }..TextPosition {
+                        element: end.0,
+                        offset: end.1,
+                    },
+                )
+                .iter()
+                .map(|rect| (rect.left, rect.right, rect.top))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            rects((0, 1), (1, 1)),
+            vec![(20.0, 30.0, 0.0), (10.0, 10.0, 20.0), (20.0, 30.0, 40.0)]
+        );
+        assert_eq!(rects((1, 0), (1, 2)), vec![(10.0, 30.0, 40.0)]);
+        assert!(rects((0, 1), (0, 1)).is_empty());
+    }
+}
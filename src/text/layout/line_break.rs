@@ -0,0 +1,60 @@
+use crate::text::TextElement;
+
+/// Line break opportunities found by the Unicode line breaking algorithm (UAX #14).
+///
+/// Offsets are byte offsets into the concatenation of every
+/// [`TextElement::content`] in order, so opportunities between two text runs
+/// are found as well.
+pub struct BreakOpportunities {
+    /// Sorted offsets of the characters a line may start with.
+    offsets: Vec<usize>,
+}
+
+impl BreakOpportunities {
+    /// Finds every break opportunity in `texts`.
+    pub fn new<T>(texts: &[TextElement<T>]) -> Self {
+        let full_text: String = texts.iter().map(|text| text.content.as_str()).collect();
+
+        let offsets = unicode_linebreak::linebreaks(&full_text)
+            .map(|(offset, _)| offset)
+            .filter(|&offset| offset < full_text.len())
+            .collect();
+
+        Self { offsets }
+    }
+
+    /// Creates an empty set, used when the wrap style does not need UAX #14.
+    pub fn empty() -> Self {
+        Self {
+            offsets: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if a line may be broken right before `offset`.
+    pub fn is_break_before(&self, offset: usize) -> bool {
+        self.offsets.binary_search(&offset).is_ok()
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(content: &str) -> TextElement<()> {
+        let font_id: fontdb::ID = unsafe { std::mem::transmute(1u64) };
+        TextElement::new(font_id, 12.0, content, ())
+    }
+
+    #[test]
+    fn test_breaks_across_runs() {
+        // "well-known " + "日本語"
+        let breaks = BreakOpportunities::new(&[element("well-known "), element("日本語")]);
+
+        assert!(!breaks.is_break_before(4)); // before '-'
+        assert!(breaks.is_break_before(5)); // after '-'
+        assert!(breaks.is_break_before(11)); // after the space, between runs
+        assert!(breaks.is_break_before(14)); // between ideographs
+        assert!(!breaks.is_break_before(20)); // end of text
+    }
+}
@@ -0,0 +1,280 @@
+use std::{ops::Range, sync::Arc};
+
+use super::LayoutRect;
+
+/// Bands searched for an interval wide enough before the shape is taken to end.
+const MAX_SKIPPED_BANDS: usize = 1000;
+
+/// Region text flows into, described by the horizontal spans available to
+/// each line. Set with [`TextLayoutConfig::shape`](super::TextLayoutConfig::shape).
+///
+/// The callback receives the top and the height of a line and returns the
+/// X intervals the line may use, from left to right. A line band with several
+/// intervals holds one line in each of them. Intervals narrower than
+/// [`Self::min_width`] are skipped, and when all of them are, the line moves
+/// down by its height. An empty list ends the shape: the remaining lines use
+/// [`TextLayoutConfig::max_width`](super::TextLayoutConfig::max_width). A
+/// thousand bands in a row without a wide enough interval end it too.
+///
+/// ```
+/// use suzuri::text::LayoutShape;
+///
+/// // Text inside a circle of radius 100 centered at (100, 100).
+/// let circle = LayoutShape::new(|top, height| {
+///     let bottom = top + height;
+///     if bottom > 200.0 {
+///         return Vec::new();
+///     }
+///     // Use the narrower edge of the line so it stays inside the circle.
+///     let dy = (top - 100.0).abs().max((bottom - 100.0).abs());
+///     let dx = (100.0f32 * 100.0 - dy * dy).max(0.0).sqrt();
+///     vec![100.0 - dx..100.0 + dx]
+/// });
+/// assert!(circle.spans(190.0, 20.0).is_empty());
+/// ```
+#[derive(Clone)]
+pub struct LayoutShape {
+    spans: Arc<dyn Fn(f32, f32) -> Vec<Range<f32>> + Send + Sync>,
+    /// Minimum width of an interval holding a line.
+    pub min_width: f32,
+    /// Top from which every band has the same intervals, if known.
+    settled: Option<f32>,
+}
+
+impl LayoutShape {
+    /// Creates a shape from a callback returning the intervals available to a
+    /// line, given its top and height.
+    pub fn new(spans: impl Fn(f32, f32) -> Vec<Range<f32>> + Send + Sync + 'static) -> Self {
+        Self {
+            spans: Arc::new(spans),
+            min_width: 0.0,
+            settled: None,
+        }
+    }
+
+    /// Creates a shape of the given width with text flowing around the
+    /// `exclusions` (floating images, for example).
+    ///
+    /// The shape has no bottom, so `max_height` still limits the layout. It
+    /// ends below the last exclusion if lines do not fit in `width` there.
+    pub fn with_exclusions(width: f32, exclusions: Vec<LayoutRect>) -> Self {
+        let settled = exclusions
+            .iter()
+            .map(|rect| rect.bottom)
+            .fold(0.0, f32::max);
+        let shape = Self::new(move |top, height| {
+            let mut spans = vec![0.0..width];
+            for rect in &exclusions {
+                if rect.bottom <= top || rect.top >= top + height {
+                    continue;
+                }
+                spans = spans
+                    .into_iter()
+                    .flat_map(|span| {
+                        [
+                            span.start..span.end.min(rect.left),
+                            span.start.max(rect.right)..span.end,
+                        ]
+                    })
+                    .filter(|span| span.start < span.end)
+                    .collect();
+            }
+            // Keep an empty interval so the shape does not end beside an exclusion.
+            if spans.is_empty() {
+                spans.push(0.0..0.0);
+            }
+            spans
+        });
+        Self {
+            settled: Some(settled),
+            ..shape
+        }
+    }
+
+    /// Returns the intervals available to a line, from left to right.
+    pub fn spans(&self, top: f32, height: f32) -> Vec<Range<f32>> {
+        (self.spans)(top, height)
+    }
+}
+
+impl std::fmt::Debug for LayoutShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LayoutShape")
+            .field("min_width", &self.min_width)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Shapes are equal when they share the same callback.
+impl PartialEq for LayoutShape {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.spans, &other.spans) && self.min_width == other.min_width
+    }
+}
+
+/// Position of a line placed in a [`LayoutShape`].
+#[derive(Clone, Copy, Debug)]
+pub struct LinePlacement {
+    /// The Y coordinate of the top of the line.
+    pub top: f32,
+    /// The X coordinate the line starts at.
+    pub left: f32,
+    /// Width of the interval, `None` past the end of an unlimited shape.
+    pub width: Option<f32>,
+}
+
+/// Places lines band by band in a [`LayoutShape`].
+pub struct ShapeFlow {
+    shape: LayoutShape,
+    max_width: Option<f32>,
+    max_height: Option<f32>,
+    /// Top of the current band.
+    top: f32,
+    /// Bottom of the lowest line placed so far.
+    bottom: f32,
+    /// Space required below the last line.
+    space_after: f32,
+    /// Intervals of the current band not holding a line yet.
+    spans: Vec<Range<f32>>,
+    /// Whether the shape returned no interval, so lines fall back to `max_width`.
+    ended: bool,
+}
+
+impl ShapeFlow {
+    pub fn new(shape: LayoutShape, max_width: Option<f32>, max_height: Option<f32>) -> Self {
+        Self {
+            shape,
+            max_width,
+            max_height,
+            top: 0.0,
+            bottom: 0.0,
+            space_after: 0.0,
+            spans: Vec::new(),
+            ended: false,
+        }
+    }
+
+    /// Places the next line, `space_before` and `height` being used if it
+    /// starts a new band.
+    pub fn place(&mut self, space_before: f32, height: f32) -> LinePlacement {
+        if self.spans.is_empty() {
+            self.next_band(space_before, height);
+        }
+        if self.spans.is_empty() {
+            return LinePlacement {
+                top: self.top,
+                left: 0.0,
+                width: self.max_width,
+            };
+        }
+
+        let span = self.spans.remove(0);
+        LinePlacement {
+            top: self.top,
+            left: span.start,
+            width: Some(span.end - span.start),
+        }
+    }
+
+    /// Checks whether the current band has intervals not holding a line yet.
+    pub fn has_spans(&self) -> bool {
+        !self.spans.is_empty()
+    }
+
+    /// Records the extent of a placed line. Lines ending with a hard break
+    /// also end their band.
+    pub fn finish_line(&mut self, bottom: f32, space_after: f32, hard_break: bool) {
+        self.bottom = self.bottom.max(bottom);
+        self.space_after = space_after;
+        if hard_break {
+            self.spans.clear();
+        }
+    }
+
+    /// Moves below the lines placed so far to the first band with an
+    /// interval wide enough.
+    ///
+    /// The shape ends when there is none past [`LayoutShape::settled`] or
+    /// within [`MAX_SKIPPED_BANDS`] bands.
+    fn next_band(&mut self, space_before: f32, height: f32) {
+        self.top = self.bottom + self.space_after + space_before;
+        let mut skipped = 0;
+        // Bands past the layout box are not searched any further.
+        while !self.ended
+            && self
+                .max_height
+                .is_none_or(|max_height| self.top < max_height)
+        {
+            let spans = self.shape.spans(self.top, height);
+            if spans.is_empty() {
+                self.ended = true;
+                break;
+            }
+
+            self.spans = spans
+                .into_iter()
+                .filter(|span| span.end - span.start >= self.shape.min_width.max(f32::EPSILON))
+                .collect();
+            if !self.spans.is_empty() {
+                break;
+            }
+
+            skipped += 1;
+            if skipped >= MAX_SKIPPED_BANDS
+                || self
+                    .shape
+                    .settled
+                    .is_some_and(|settled| self.top >= settled)
+            {
+                self.ended = true;
+                break;
+            }
+            self.top += height.max(1.0);
+        }
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusion_spans() {
+        let shape = LayoutShape::with_exclusions(
+            100.0,
+            vec![LayoutRect {
+                left: 30.0,
+                top: 10.0,
+                right: 50.0,
+                bottom: 40.0,
+            }],
+        );
+        assert_eq!(shape.spans(0.0, 10.0), vec![0.0..100.0]);
+        assert_eq!(shape.spans(5.0, 10.0), vec![0.0..30.0, 50.0..100.0]);
+
+        // The left interval is too narrow for the lines beside the exclusion.
+        let mut flow = ShapeFlow::new(
+            LayoutShape {
+                min_width: 40.0,
+                ..shape
+            },
+            Some(100.0),
+            None,
+        );
+        let first = flow.place(0.0, 20.0);
+        assert_eq!(
+            (first.top, first.left, first.width),
+            (0.0, 50.0, Some(50.0))
+        );
+        flow.finish_line(20.0, 0.0, false);
+        let second = flow.place(0.0, 20.0);
+        assert_eq!((second.top, second.left), (20.0, 50.0));
+        flow.finish_line(40.0, 0.0, false);
+        let third = flow.place(0.0, 20.0);
+        assert_eq!(
+            (third.top, third.left, third.width),
+            (40.0, 0.0, Some(100.0))
+        );
+    }
+}
@@ -0,0 +1,617 @@
+use std::ops::Range;
+
+use unicode_properties::{GeneralCategoryGroup, UnicodeGeneralCategory};
+
+use rustybuzz::Direction;
+
+use crate::{font_storage::FaceData, glyph_id::ColorFace};
+
+/// A single glyph produced by the shaper, positioned relative to its cluster origin.
+#[derive(Clone, Copy, Debug)]
+pub struct ShapedGlyph {
+    /// Glyph index inside the font.
+    pub glyph_idx: u16,
+    /// Horizontal offset of the glyph origin from the cluster origin (pixels).
+    pub x: f32,
+    /// Vertical offset of the glyph origin from the cluster origin (pixels, **Y-axis goes up**).
+    pub y: f32,
+    /// Rasterization metrics of the glyph.
+    pub metrics: fontdue::Metrics,
+    /// Whether the glyph is drawn from the color tables of its face, which
+    /// `metrics` then describe.
+    pub color: bool,
+}
+
+/// Position and thickness of a decoration line, in pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct Stroke {
+    /// Distance of the top edge of the line above the baseline.
+    pub top: f32,
+    pub thickness: f32,
+}
+
+/// Decoration lines recommended by a font.
+#[derive(Clone, Copy, Debug)]
+pub struct DecorationMetrics {
+    pub underline: Stroke,
+    pub strikethrough: Stroke,
+    pub overline: Stroke,
+}
+
+/// Smallest unit of text that can not be split by the line breaker.
+///
+/// A cluster maps one or more characters to one or more glyphs
+/// (ligatures, decomposed characters, base + marks, ...).
+#[derive(Clone, Debug)]
+pub struct ShapedCluster {
+    /// Byte range of the source characters, relative to the shaped string.
+    pub range: Range<usize>,
+    /// Glyphs of this cluster in visual order.
+    pub glyphs: Vec<ShapedGlyph>,
+    /// Total advance of the cluster (pixels), including GPOS/kern adjustments.
+    ///
+    /// Measured downwards for vertical text.
+    pub advance: f32,
+}
+
+/// Reusable shaper for a single font face at a single size.
+///
+/// Parsing the face is done once so multiple segments of the same text run can
+/// be shaped without re-reading the font tables.
+#[derive(Clone)]
+pub struct Shaper<'a> {
+    face: Option<rustybuzz::Face<'a>>,
+    /// Color tables of the face, looked up before the outline metrics.
+    color: Option<&'a ColorFace>,
+    font: &'a fontdue::Font,
+    font_size: f32,
+}
+
+impl<'a> Shaper<'a> {
+    /// Creates a shaper for the face stored in `face_data`.
+    ///
+    /// If the face can not be parsed by the shaper, a plain character to glyph
+    /// mapping through `font` is used instead.
+    pub fn new(face_data: &'a FaceData, font: &'a fontdue::Font, font_size: f32) -> Self {
+        let face = rustybuzz::Face::from_slice(&face_data.data, face_data.index);
+        if face.is_none() {
+            log::warn!("Failed to parse face for shaping. Falling back to cmap lookup.");
+        }
+
+        Self {
+            face,
+            color: face_data.color.as_deref(),
+            font,
+            font_size,
+        }
+    }
+
+    /// Checks whether the face has a glyph for `ch`.
+    pub fn has_glyph(&self, ch: char) -> bool {
+        match &self.face {
+            Some(face) => face.glyph_index(ch).is_some(),
+            None => self.font.lookup_glyph_index(ch) != 0,
+        }
+    }
+
+    /// Returns the metrics of a glyph, taken from the color tables (`COLR`, `CBDT`
+    /// or `sbix`) if they draw it, and whether they do.
+    fn glyph_metrics(&self, glyph_idx: u16) -> (fontdue::Metrics, bool) {
+        self.color
+            .and_then(|face| face.metrics(self.font, glyph_idx, self.font_size))
+            .map_or_else(
+                || (self.font.metrics_indexed(glyph_idx, self.font_size), false),
+                |(metrics, _)| (metrics, true),
+            )
+    }
+
+    /// Returns a shaper for the same face at another size.
+    pub fn with_size(&self, font_size: f32) -> Self {
+        Self {
+            font_size,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the font the shaper was created for.
+    pub fn font(&self) -> &'a fontdue::Font {
+        self.font
+    }
+
+    /// Returns the size the shaper was created for.
+    pub fn font_size(&self) -> f32 {
+        self.font_size
+    }
+
+    /// Returns the horizontal line metrics of the font at the shaper's size.
+    pub fn line_metrics(&self) -> Option<fontdue::LineMetrics> {
+        self.font.horizontal_line_metrics(self.font_size)
+    }
+
+    /// Returns the distances from the baseline the font recommends for superscript
+    /// and subscript glyphs (raised and lowered respectively), in pixels.
+    ///
+    /// Fonts without these values in their `OS/2` table get a fraction of the
+    /// font size instead.
+    pub fn script_offsets(&self) -> (f32, f32) {
+        let mut superscript = self.font_size * 0.33;
+        let mut subscript = self.font_size * 0.2;
+        if let Some(face) = &self.face {
+            let scale = self.font_size / face.units_per_em() as f32;
+            if let Some(metrics) = face.superscript_metrics()
+                && metrics.y_offset > 0
+            {
+                superscript = metrics.y_offset as f32 * scale;
+            }
+            if let Some(metrics) = face.subscript_metrics()
+                && metrics.y_offset > 0
+            {
+                subscript = metrics.y_offset as f32 * scale;
+            }
+        }
+
+        (superscript, subscript)
+    }
+
+    /// Returns the decoration lines of the font, from its `post` and `OS/2` tables.
+    ///
+    /// The overline uses the underline thickness and sits at the ascent. Missing
+    /// values are replaced by proportions of the font size.
+    pub fn decoration_metrics(&self) -> DecorationMetrics {
+        let size = self.font_size;
+        let ascent = self.line_metrics().map_or(size * 0.8, |m| m.ascent);
+        let mut underline = Stroke {
+            top: -size * 0.1,
+            thickness: size / 14.0,
+        };
+        let mut strikethrough = Stroke {
+            top: size * 0.3,
+            thickness: size / 14.0,
+        };
+
+        if let Some(face) = &self.face {
+            let scale = size / face.units_per_em() as f32;
+            if let Some(metrics) = face.underline_metrics()
+                && metrics.thickness > 0
+            {
+                underline = Stroke {
+                    top: metrics.position as f32 * scale,
+                    thickness: metrics.thickness as f32 * scale,
+                };
+            }
+            if let Some(metrics) = face.strikeout_metrics()
+                && metrics.thickness > 0
+            {
+                strikethrough = Stroke {
+                    top: metrics.position as f32 * scale,
+                    thickness: metrics.thickness as f32 * scale,
+                };
+            }
+        }
+
+        DecorationMetrics {
+            underline,
+            strikethrough,
+            overline: Stroke {
+                top: ascent,
+                thickness: underline.thickness,
+            },
+        }
+    }
+
+    /// Checks whether vertical shaping substitutes `ch` with a vertical form
+    /// (the OpenType `vert` feature), e.g. for brackets or the long vowel mark.
+    pub fn has_vertical_alternate(&self, ch: char) -> bool {
+        let Some(face) = &self.face else {
+            return false;
+        };
+        let Some(glyph) = face.glyph_index(ch) else {
+            return false;
+        };
+
+        let mut text = [0u8; 4];
+        self.shape_with_face(face, ch.encode_utf8(&mut text), Direction::TopToBottom)
+            .first()
+            .and_then(|cluster| cluster.glyphs.first())
+            .is_some_and(|shaped| shaped.glyph_idx != glyph.0)
+    }
+
+    /// Shapes `text` and returns its clusters in logical order.
+    ///
+    /// For horizontal text `direction` is the one resolved by the bidi algorithm.
+    /// With [`Direction::TopToBottom`] glyphs are positioned relative to their
+    /// vertical origin and advance downwards. Script and language are guessed
+    /// from the content.
+    pub fn shape(&self, text: &str, direction: Direction) -> Vec<ShapedCluster> {
+        if text.is_empty() {
+            return Vec::new();
+        }
+
+        match &self.face {
+            Some(face) => self.shape_with_face(face, text, direction),
+            None => self.shape_with_cmap(text, direction),
+        }
+    }
+
+    fn shape_with_face(
+        &self,
+        face: &rustybuzz::Face<'a>,
+        text: &str,
+        direction: Direction,
+    ) -> Vec<ShapedCluster> {
+        let mut buffer = rustybuzz::UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.set_direction(direction);
+        buffer.guess_segment_properties();
+
+        let glyph_buffer = rustybuzz::shape(face, &[], buffer);
+        let infos = glyph_buffer.glyph_infos();
+        let positions = glyph_buffer.glyph_positions();
+
+        let scale = self.font_size / face.units_per_em() as f32;
+
+        // Group glyphs sharing the same cluster value. The shaper returns them in
+        // visual order, which is reversed for right-to-left text.
+        let mut clusters: Vec<ShapedCluster> = Vec::new();
+        let vertical = direction == Direction::TopToBottom;
+        let mut pen = 0.0;
+        for (info, pos) in infos.iter().zip(positions) {
+            let start = info.cluster as usize;
+
+            let cluster = match clusters.last_mut() {
+                Some(last) if last.range.start == start => last,
+                _ => {
+                    clusters.push(ShapedCluster {
+                        range: start..start,
+                        glyphs: Vec::new(),
+                        advance: 0.0,
+                    });
+                    pen = 0.0;
+                    clusters.last_mut().expect("cluster was just pushed")
+                }
+            };
+
+            let glyph_idx = info.glyph_id as u16;
+            let (x, y, advance) = if vertical {
+                // The shaper reports a negative Y advance (Y-axis goes up).
+                (0.0, -pen, -pos.y_advance as f32 * scale)
+            } else {
+                (pen, 0.0, pos.x_advance as f32 * scale)
+            };
+            let (metrics, color) = self.glyph_metrics(glyph_idx);
+            cluster.glyphs.push(ShapedGlyph {
+                glyph_idx,
+                x: x + pos.x_offset as f32 * scale,
+                y: y + pos.y_offset as f32 * scale,
+                metrics,
+                color,
+            });
+
+            cluster.advance += advance;
+            pen += advance;
+        }
+
+        // Back to logical order.
+        clusters.sort_by_key(|cluster| cluster.range.start);
+
+        let mut end = text.len();
+        for cluster in clusters.iter_mut().rev() {
+            cluster.range.end = end;
+            end = cluster.range.start;
+        }
+
+        split_soft_hyphens(text, clusters)
+    }
+
+    fn shape_with_cmap(&self, text: &str, direction: Direction) -> Vec<ShapedCluster> {
+        let mut clusters: Vec<ShapedCluster> = Vec::new();
+        let mut prev_glyph: Option<u16> = None;
+
+        for (offset, ch) in text.char_indices() {
+            let glyph_idx = self.font.lookup_glyph_index(ch);
+            let metrics = self.font.metrics_indexed(glyph_idx, self.font_size);
+
+            if direction == Direction::TopToBottom {
+                // Same fallback as the shaper: one em per glyph, centered horizontally
+                // below the vertical origin.
+                let ascent = self.line_metrics().map_or(0.0, |m| m.ascent);
+                clusters.push(ShapedCluster {
+                    range: offset..offset + ch.len_utf8(),
+                    glyphs: vec![ShapedGlyph {
+                        glyph_idx,
+                        x: -metrics.advance_width / 2.0,
+                        y: -ascent,
+                        metrics,
+                        color: false,
+                    }],
+                    advance: self.font_size,
+                });
+                continue;
+            }
+
+            if let (Some(prev), Some(last)) = (prev_glyph, clusters.last_mut()) {
+                last.advance += self
+                    .font
+                    .horizontal_kern_indexed(prev, glyph_idx, self.font_size)
+                    .unwrap_or(0.0);
+            }
+
+            clusters.push(ShapedCluster {
+                range: offset..offset + ch.len_utf8(),
+                glyphs: vec![ShapedGlyph {
+                    glyph_idx,
+                    x: 0.0,
+                    y: 0.0,
+                    metrics,
+                    color: false,
+                }],
+                advance: metrics.advance_width,
+            });
+            prev_glyph = Some(glyph_idx);
+        }
+
+        clusters
+    }
+}
+
+/// Returns `true` for characters that attach to the preceding character.
+///
+/// Combining marks, joiners, variation selectors, emoji modifiers and tag
+/// characters must be shaped with the same face as their base character.
+pub fn extends_cluster(ch: char) -> bool {
+    matches!(ch, '\u{200C}' | '\u{200D}' | '\u{1F3FB}'..='\u{1F3FF}' | '\u{E0020}'..='\u{E007F}')
+        || ch.general_category_group() == GeneralCategoryGroup::Mark
+}
+
+/// Gives the soft hyphens inside `clusters` clusters of their own, without glyphs.
+///
+/// The shaper hides soft hyphens and merges them into the previous cluster, but
+/// the line breaker needs them as hyphenation points.
+fn split_soft_hyphens(text: &str, clusters: Vec<ShapedCluster>) -> Vec<ShapedCluster> {
+    let mut split = Vec::with_capacity(clusters.len());
+    for mut cluster in clusters {
+        let end = cluster.range.end;
+        let hyphens: Vec<usize> = text[cluster.range.clone()]
+            .char_indices()
+            .filter(|&(offset, ch)| offset > 0 && ch == super::layout_utl::SOFT_HYPHEN)
+            .map(|(offset, _)| cluster.range.start + offset)
+            .collect();
+        let Some(&first) = hyphens.first() else {
+            split.push(cluster);
+            continue;
+        };
+
+        cluster.range.end = first;
+        split.push(cluster);
+        for (index, &start) in hyphens.iter().enumerate() {
+            split.push(ShapedCluster {
+                range: start..hyphens.get(index + 1).copied().unwrap_or(end),
+                glyphs: Vec::new(),
+                advance: 0.0,
+            });
+        }
+    }
+    split
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::font_storage::tests::{font_data_with_tables, u16s};
+
+    /// Characters of [`feature_font_data`], mapped to glyphs 1 to 7. The last
+    /// one only gives the font a glyph for the `fi` ligature.
+    const CHARS: [char; 7] = ['A', 'V', 'a', 'f', 'i', '\u{301}', '\u{E000}'];
+    const A: u16 = 1;
+    const V: u16 = 2;
+    const LOWER_A: u16 = 3;
+    const F: u16 = 4;
+    const I: u16 = 5;
+    const ACUTE: u16 = 6;
+    /// Glyph of the `fi` ligature.
+    const FI: u16 = 7;
+
+    /// Builds a `GSUB` or `GPOS` table with one feature per lookup, under the
+    /// default script.
+    fn layout_table(lookups: &[([u8; 4], u16, Vec<u8>)]) -> Vec<u8> {
+        let count = lookups.len() as u16;
+        let script_list_len = 18 + 2 * count;
+        let feature_list_len = 2 + 12 * count;
+
+        let mut table = Vec::new();
+        u16s(
+            &mut table,
+            &[
+                1,
+                0,
+                10,
+                10 + script_list_len,
+                10 + script_list_len + feature_list_len,
+            ],
+        );
+
+        // Script list: the default language of `DFLT` enables every feature.
+        u16s(&mut table, &[1]);
+        table.extend(b"DFLT");
+        u16s(&mut table, &[8, 4, 0, 0, 0xFFFF, count]);
+        u16s(&mut table, &(0..count).collect::<Vec<_>>());
+
+        // Feature list: feature `i` uses lookup `i`.
+        u16s(&mut table, &[count]);
+        for (i, (tag, _, _)) in lookups.iter().enumerate() {
+            table.extend(tag);
+            u16s(&mut table, &[2 + 6 * count + 6 * i as u16]);
+        }
+        for i in 0..count {
+            u16s(&mut table, &[0, 1, i]);
+        }
+
+        // Lookup list, each lookup with a single subtable.
+        u16s(&mut table, &[count]);
+        let mut offset = 2 + 2 * count;
+        for (_, _, subtable) in lookups {
+            u16s(&mut table, &[offset]);
+            offset += 8 + subtable.len() as u16;
+        }
+        for (_, kind, subtable) in lookups {
+            u16s(&mut table, &[*kind, 0, 1, 8]);
+            table.extend(subtable);
+        }
+        table
+    }
+
+    /// Builds a font with [`CHARS`] whose `liga` feature forms `fi`, whose
+    /// `kern` feature (and `kern` table) moves `V` 100 units closer to `A`, and
+    /// whose `mark` feature attaches the acute accent at (250, 700) on `a`.
+    ///
+    /// Like [`font_data`](crate::font_storage::tests::font_data), glyphs advance
+    /// by 500 units of 1000 per em.
+    pub(crate) fn feature_font_data() -> Vec<u8> {
+        // Ligature substitution: coverage, ligature set and ligature.
+        let mut liga = Vec::new();
+        u16s(&mut liga, &[1, 8, 1, 14]);
+        u16s(&mut liga, &[1, 1, F]);
+        u16s(&mut liga, &[1, 4, FI, 2, I]);
+
+        // Pair adjustment of the advance of the first glyph.
+        let mut kern = Vec::new();
+        u16s(&mut kern, &[1, 12, 0x0004, 0, 1, 18]);
+        u16s(&mut kern, &[1, 1, A]);
+        u16s(&mut kern, &[1, V, (-100i16) as u16]);
+
+        // Mark to base attachment: coverages, mark array and base array.
+        let mut mark = Vec::new();
+        u16s(&mut mark, &[1, 12, 18, 1, 24, 36]);
+        u16s(&mut mark, &[1, 1, ACUTE]);
+        u16s(&mut mark, &[1, 1, LOWER_A]);
+        u16s(&mut mark, &[1, 0, 6, 1, 0, 0]);
+        u16s(&mut mark, &[1, 4, 1, 250, 700]);
+
+        // The same kerning pair in the `kern` table, read by fontdue.
+        let mut kern_table = Vec::new();
+        u16s(&mut kern_table, &[0, 1, 0, 20, 0x0001, 1, 6, 0, 0]);
+        u16s(&mut kern_table, &[A, V, (-100i16) as u16]);
+
+        font_data_with_tables(
+            &CHARS,
+            vec![
+                (*b"GSUB", layout_table(&[(*b"liga", 4, liga)])),
+                (
+                    *b"GPOS",
+                    layout_table(&[(*b"kern", 2, kern), (*b"mark", 4, mark)]),
+                ),
+                (*b"kern", kern_table),
+            ],
+        )
+    }
+
+    /// Source range, glyphs (index, x, y) and advance of each cluster.
+    type Clusters = Vec<(Range<usize>, Vec<(u16, f32, f32)>, f32)>;
+
+    /// Shapes `text` at 100px, where one unit is 0.1px.
+    fn shape(face_data: &FaceData, text: &str, direction: Direction) -> Clusters {
+        let font = fontdue::Font::from_bytes(feature_font_data(), fontdue::FontSettings::default())
+            .unwrap();
+        Shaper::new(face_data, &font, 100.0)
+            .shape(text, direction)
+            .into_iter()
+            .map(|cluster| {
+                let glyphs = cluster
+                    .glyphs
+                    .iter()
+                    .map(|glyph| (glyph.glyph_idx, glyph.x, glyph.y))
+                    .collect();
+                (cluster.range, glyphs, cluster.advance)
+            })
+            .collect()
+    }
+
+    fn face_data() -> FaceData {
+        FaceData {
+            data: feature_font_data().into(),
+            index: 0,
+            color: None,
+        }
+    }
+
+    #[test]
+    fn test_shape_ligature() {
+        // Both characters map to the ligature glyph in one cluster.
+        assert_eq!(
+            shape(&face_data(), "fia", Direction::LeftToRight),
+            vec![
+                (0..2, vec![(FI, 0.0, 0.0)], 50.0),
+                (2..3, vec![(LOWER_A, 0.0, 0.0)], 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shape_kerning() {
+        assert_eq!(
+            shape(&face_data(), "AVA", Direction::LeftToRight),
+            vec![
+                (0..1, vec![(A, 0.0, 0.0)], 40.0),
+                (1..2, vec![(V, 0.0, 0.0)], 50.0),
+                (2..3, vec![(A, 0.0, 0.0)], 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shape_mark() {
+        // The accent has no advance of its own and sits on the anchor of `a`.
+        assert_eq!(
+            shape(&face_data(), "a\u{301}A", Direction::LeftToRight),
+            vec![
+                (0..3, vec![(LOWER_A, 0.0, 0.0), (ACUTE, 25.0, 70.0)], 50.0),
+                (3..4, vec![(A, 0.0, 0.0)], 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shape_right_to_left() {
+        // Clusters come back in logical order even though the shaper returns
+        // the glyphs right to left. `A` is kerned with `V`, which follows it
+        // visually.
+        assert_eq!(
+            shape(&face_data(), "VAa\u{301}", Direction::RightToLeft),
+            vec![
+                (0..1, vec![(V, 0.0, 0.0)], 50.0),
+                (1..2, vec![(A, 0.0, 0.0)], 40.0),
+                (2..5, vec![(LOWER_A, 0.0, 0.0), (ACUTE, 25.0, 70.0)], 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shape_cmap_fallback() {
+        // Data the shaper can not parse leaves the cmap of the fontdue font.
+        let unparsed = FaceData {
+            data: Arc::from(Vec::new()),
+            index: 0,
+            color: None,
+        };
+
+        // No ligatures, but the kerning of the `kern` table is applied.
+        assert_eq!(
+            shape(&unparsed, "fiAV", Direction::LeftToRight),
+            vec![
+                (0..1, vec![(F, 0.0, 0.0)], 50.0),
+                (1..2, vec![(I, 0.0, 0.0)], 50.0),
+                (2..3, vec![(A, 0.0, 0.0)], 40.0),
+                (3..4, vec![(V, 0.0, 0.0)], 50.0),
+            ]
+        );
+
+        // Vertical glyphs advance by one em, centered below the origin.
+        assert_eq!(
+            shape(&unparsed, "A", Direction::TopToBottom),
+            vec![(0..1, vec![(A, -25.0, -80.0)], 100.0)]
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use std::ops::Range;
+
+use rustybuzz::Direction;
+
+use super::{
+    layout_utl::{PlacedCluster, PlacedGlyph},
+    shaping::{self, ShapedCluster, Shaper},
+};
+
+/// How a character is set in a vertical line.
+///
+/// This is a simplified version of the `Vertical_Orientation` property of
+/// UAX #50: `Tu` characters are treated as upright and `Tr` characters are
+/// only kept upright when the font has a vertical form for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Orientation {
+    /// Set upright, advancing by the vertical advance of the glyph.
+    Upright,
+    /// Rotated 90° clockwise, advancing by the horizontal advance of the glyph.
+    Sideways,
+    /// A short run of digits set horizontally in the space of one character
+    /// (tate-chu-yoko).
+    Horizontal,
+}
+
+/// Shapes a run of vertical text and places its glyphs in column coordinates.
+///
+/// `context` holds the characters right before and after `text`, so digit
+/// runs are only set as tate-chu-yoko when they are not part of a longer number.
+pub fn shape(
+    shaper: &Shaper<'_>,
+    text: &str,
+    context: (Option<char>, Option<char>),
+    rtl: bool,
+    tate_chu_yoko: usize,
+    font_id: fontdb::ID,
+) -> Vec<PlacedCluster> {
+    let mut clusters = Vec::new();
+    let runs = segment(text, context, tate_chu_yoko, |ch| {
+        shaper.has_vertical_alternate(ch)
+    });
+    for (range, orientation) in runs {
+        let segment_text = &text[range.clone()];
+        match orientation {
+            Orientation::Upright => {
+                for cluster in shaper.shape(segment_text, Direction::TopToBottom) {
+                    clusters.push(place_upright(cluster, range.start, shaper, font_id));
+                }
+            }
+            Orientation::Sideways => {
+                let direction = if rtl {
+                    Direction::RightToLeft
+                } else {
+                    Direction::LeftToRight
+                };
+                for cluster in shaper.shape(segment_text, direction) {
+                    clusters.push(place_sideways(cluster, range.start, shaper, font_id));
+                }
+            }
+            Orientation::Horizontal => {
+                clusters.push(place_horizontal_in_column(
+                    segment_text,
+                    range,
+                    shaper,
+                    font_id,
+                ));
+            }
+        }
+    }
+
+    clusters
+}
+
+/// Splits `text` into runs of the same orientation.
+///
+/// `has_vertical_alternate` tells whether the font has a vertical form of a
+/// character, to keep it upright.
+fn segment(
+    text: &str,
+    context: (Option<char>, Option<char>),
+    tate_chu_yoko: usize,
+    has_vertical_alternate: impl Fn(char) -> bool,
+) -> Vec<(Range<usize>, Orientation)> {
+    let mut runs: Vec<(Range<usize>, Orientation)> = Vec::new();
+    let mut chars = text.char_indices();
+
+    while let Some((offset, ch)) = chars.next() {
+        let end = offset + ch.len_utf8();
+
+        let digits = if ch.is_ascii_digit() {
+            text[offset..]
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .count()
+        } else {
+            0
+        };
+        let before = text[..offset].chars().next_back().or(context.0);
+        if digits > 0
+            && digits <= tate_chu_yoko
+            && !before.is_some_and(|c| c.is_ascii_digit())
+            && !text[offset + digits..]
+                .chars()
+                .next()
+                .or(context.1)
+                .is_some_and(|c| c.is_ascii_digit())
+        {
+            // Digits are one byte each.
+            for _ in 1..digits {
+                chars.next();
+            }
+            runs.push((offset..offset + digits, Orientation::Horizontal));
+            continue;
+        }
+
+        let orientation = match runs.last() {
+            Some((_, previous)) if shaping::extends_cluster(ch) => *previous,
+            _ => match vertical_orientation(ch) {
+                VerticalOrientation::Upright => Orientation::Upright,
+                VerticalOrientation::Rotated => Orientation::Sideways,
+                VerticalOrientation::TransformedOrRotated => {
+                    if has_vertical_alternate(ch) {
+                        Orientation::Upright
+                    } else {
+                        Orientation::Sideways
+                    }
+                }
+            },
+        };
+
+        match runs.last_mut() {
+            Some((range, last))
+                if *last == orientation && orientation != Orientation::Horizontal =>
+            {
+                range.end = end
+            }
+            _ => runs.push((offset..end, orientation)),
+        }
+    }
+
+    runs
+}
+
+/// Places a cluster shaped top-to-bottom. The vertical origin is on the column center.
+fn place_upright(
+    cluster: ShapedCluster,
+    offset: usize,
+    shaper: &Shaper<'_>,
+    font_id: fontdb::ID,
+) -> PlacedCluster {
+    let glyphs = cluster
+        .glyphs
+        .iter()
+        .map(|glyph| {
+            let metrics = &glyph.metrics;
+            let top = -(glyph.y + metrics.ymin as f32 + metrics.height as f32);
+            PlacedGlyph {
+                glyph_id: crate::GlyphId::new(font_id, glyph.glyph_idx, shaper.font_size()),
+                x: top,
+                y: glyph.x + metrics.xmin as f32,
+                ink_end: top + metrics.height as f32,
+                color: glyph.color,
+            }
+        })
+        .collect();
+
+    PlacedCluster {
+        range: cluster.range.start + offset..cluster.range.end + offset,
+        glyphs,
+        advance: cluster.advance,
+    }
+}
+
+/// Places a horizontally shaped cluster rotated 90° clockwise, with its em box
+/// centered on the column center.
+pub fn place_sideways(
+    cluster: ShapedCluster,
+    offset: usize,
+    shaper: &Shaper<'_>,
+    font_id: fontdb::ID,
+) -> PlacedCluster {
+    // The baseline ends up on the left of the center for typical Latin fonts.
+    let baseline = shaper
+        .line_metrics()
+        .map_or(0.0, |m| -(m.ascent + m.descent) / 2.0);
+
+    let glyphs = cluster
+        .glyphs
+        .iter()
+        .map(|glyph| {
+            let metrics = &glyph.metrics;
+            let left = glyph.x + metrics.xmin as f32;
+            PlacedGlyph {
+                glyph_id: crate::GlyphId::new(font_id, glyph.glyph_idx, shaper.font_size())
+                    .with_rotation(true),
+                x: left,
+                y: baseline + glyph.y + metrics.ymin as f32,
+                ink_end: left + metrics.width as f32,
+                color: glyph.color,
+            }
+        })
+        .collect();
+
+    PlacedCluster {
+        range: cluster.range.start + offset..cluster.range.end + offset,
+        glyphs,
+        advance: cluster.advance,
+    }
+}
+
+/// Sets `text` horizontally in the space of one em (tate-chu-yoko).
+///
+/// The run is scaled down when it is wider than the column.
+fn place_horizontal_in_column(
+    text: &str,
+    range: Range<usize>,
+    shaper: &Shaper<'_>,
+    font_id: fontdb::ID,
+) -> PlacedCluster {
+    let em = shaper.font_size();
+    let width: f32 = shaper
+        .shape(text, Direction::LeftToRight)
+        .iter()
+        .map(|cluster| cluster.advance)
+        .sum();
+    let shaper = if width > em {
+        shaper.with_size(em * em / width)
+    } else {
+        shaper.clone()
+    };
+
+    let clusters = shaper.shape(text, Direction::LeftToRight);
+    let width: f32 = clusters.iter().map(|cluster| cluster.advance).sum();
+    let baseline = shaper
+        .line_metrics()
+        .map_or(em, |m| (em - (m.ascent - m.descent)) / 2.0 + m.ascent);
+
+    let mut glyphs = Vec::new();
+    let mut pen = -width / 2.0;
+    for cluster in clusters {
+        for glyph in &cluster.glyphs {
+            let metrics = &glyph.metrics;
+            let top = baseline - (glyph.y + metrics.ymin as f32 + metrics.height as f32);
+            glyphs.push(PlacedGlyph {
+                glyph_id: crate::GlyphId::new(font_id, glyph.glyph_idx, shaper.font_size()),
+                x: top,
+                y: pen + glyph.x + metrics.xmin as f32,
+                ink_end: top + metrics.height as f32,
+                color: glyph.color,
+            });
+        }
+        pen += cluster.advance;
+    }
+
+    PlacedCluster {
+        range,
+        glyphs,
+        advance: em,
+    }
+}
+
+/// Values of the `Vertical_Orientation` property, with `Tu` folded into `U`.
+enum VerticalOrientation {
+    Upright,
+    Rotated,
+    TransformedOrRotated,
+}
+
+fn vertical_orientation(ch: char) -> VerticalOrientation {
+    use VerticalOrientation::*;
+
+    match ch {
+        // Brackets, dashes and the long vowel mark.
+        '\u{2329}'..='\u{232A}'
+        | '\u{3008}'..='\u{3011}'
+        | '\u{3014}'..='\u{301F}'
+        | '\u{3030}'
+        | '\u{30A0}'
+        | '\u{30FC}'
+        | '\u{FE59}'..='\u{FE5E}'
+        | '\u{FF08}'..='\u{FF09}'
+        | '\u{FF0D}'
+        | '\u{FF1C}'..='\u{FF1E}'
+        | '\u{FF3B}'
+        | '\u{FF3D}'
+        | '\u{FF3F}'
+        | '\u{FF5B}'..='\u{FF60}'
+        | '\u{FFE3}' => TransformedOrRotated,
+        '\u{00A7}'
+        | '\u{00A9}'
+        | '\u{00AE}'
+        | '\u{00B1}'
+        | '\u{00BC}'..='\u{00BE}'
+        | '\u{00D7}'
+        | '\u{00F7}'
+        | '\u{1100}'..='\u{11FF}'
+        | '\u{1401}'..='\u{167F}'
+        | '\u{18B0}'..='\u{18FF}'
+        | '\u{2016}'
+        | '\u{2020}'..='\u{2021}'
+        | '\u{2030}'..='\u{2031}'
+        | '\u{203B}'..='\u{203C}'
+        | '\u{2042}'
+        | '\u{2047}'..='\u{2049}'
+        | '\u{2051}'
+        | '\u{2100}'..='\u{2101}'
+        | '\u{2103}'..='\u{2109}'
+        | '\u{210F}'
+        | '\u{2113}'..='\u{2114}'
+        | '\u{2116}'..='\u{2117}'
+        | '\u{211E}'..='\u{2123}'
+        | '\u{2125}'
+        | '\u{2127}'
+        | '\u{2129}'
+        | '\u{212E}'
+        | '\u{2135}'..='\u{213F}'
+        | '\u{2145}'..='\u{214A}'
+        | '\u{214C}'..='\u{214D}'
+        | '\u{214F}'..='\u{2189}'
+        | '\u{221E}'
+        | '\u{2234}'..='\u{2235}'
+        | '\u{2300}'..='\u{2307}'
+        | '\u{230C}'..='\u{231F}'
+        | '\u{2322}'..='\u{2328}'
+        | '\u{232B}'..='\u{237B}'
+        | '\u{237D}'..='\u{239A}'
+        | '\u{23BE}'..='\u{23CD}'
+        | '\u{23CF}'
+        | '\u{23D1}'..='\u{23DB}'
+        | '\u{23E2}'..='\u{2422}'
+        | '\u{2424}'..='\u{24FF}'
+        | '\u{25A0}'..='\u{2619}'
+        | '\u{2620}'..='\u{2767}'
+        | '\u{2776}'..='\u{2793}'
+        | '\u{2B12}'..='\u{2B2F}'
+        | '\u{2B50}'..='\u{2B59}'
+        | '\u{2BB8}'..='\u{2BFF}'
+        | '\u{2E80}'..='\u{A4CF}'
+        | '\u{A960}'..='\u{A97F}'
+        | '\u{AC00}'..='\u{D7FF}'
+        | '\u{E000}'..='\u{FAFF}'
+        | '\u{FE10}'..='\u{FE1F}'
+        | '\u{FE30}'..='\u{FE48}'
+        | '\u{FE50}'..='\u{FE6F}'
+        | '\u{FF00}'..='\u{FF60}'
+        | '\u{FFE0}'..='\u{FFE7}'
+        | '\u{1B000}'..='\u{1B2FF}'
+        | '\u{1D300}'..='\u{1D35F}'
+        | '\u{1F000}'..='\u{1FAFF}'
+        | '\u{20000}'..='\u{3FFFD}' => Upright,
+        _ => Rotated,
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orientations(text: &str) -> Vec<(&str, Orientation)> {
+        segment(text, (None, None), 2, |ch| ch == 'ー')
+            .into_iter()
+            .map(|(range, orientation)| (&text[range], orientation))
+            .collect()
+    }
+
+    #[test]
+    fn test_segment_orientation() {
+        use Orientation::*;
+
+        assert_eq!(
+            orientations("漢字abc、ー（"),
+            vec![
+                ("漢字", Upright),
+                ("abc", Sideways),
+                ("、ー", Upright),
+                ("（", Sideways),
+            ]
+        );
+        // Combining marks stay with their base.
+        assert_eq!(
+            orientations("a\u{301}か\u{3099}"),
+            vec![("a\u{301}", Sideways), ("か\u{3099}", Upright)]
+        );
+    }
+
+    #[test]
+    fn test_segment_digits() {
+        use Orientation::*;
+
+        // Each short digit run is set in one character cell.
+        assert_eq!(
+            orientations("第12回3日"),
+            vec![
+                ("第", Upright),
+                ("12", Horizontal),
+                ("回", Upright),
+                ("3", Horizontal),
+                ("日", Upright),
+            ]
+        );
+        // Longer numbers are set sideways.
+        assert_eq!(
+            orientations("2024年"),
+            vec![("2024", Sideways), ("年", Upright)]
+        );
+
+        // Digits continuing in the surrounding text are part of a longer number.
+        let runs = segment("12", (Some('9'), None), 2, |_| false);
+        assert_eq!(runs, vec![(0..2, Sideways)]);
+        let runs = segment("12", (None, Some('3')), 2, |_| false);
+        assert_eq!(runs, vec![(0..2, Sideways)]);
+    }
+}
@@ -0,0 +1,722 @@
+use std::fmt;
+
+use crate::{
+    font_storage::FontStorage,
+    text::{BaselineShift, SyntheticStyle, TextData, TextDecoration, TextElement},
+};
+
+/// Maps style attributes of the markup onto the user data of the runs.
+///
+/// The user data of the enclosing tag (or [`MarkupDefaults::user_data`] at the
+/// top level) is passed as `self`, so nested tags combine naturally.
+pub trait MarkupUserData: Clone {
+    /// Returns a copy of `self` with the attribute `name` set to `value`, or
+    /// `None` if the attribute or its value is not supported.
+    ///
+    /// Called with `name = "color"` for `<color value="...">` and with every
+    /// attribute of a `<span>` tag.
+    fn apply_attribute(&self, name: &str, value: &str) -> Option<Self>;
+}
+
+/// Style of the text outside of any tag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarkupDefaults<T> {
+    /// Comma separated list of font families, in the same format as the
+    /// `family` attribute of `<font>`.
+    pub family: String,
+    /// The size of the font in pixels.
+    pub font_size: f32,
+    /// User data of the top level text.
+    pub user_data: T,
+}
+
+/// Error found while parsing markup.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarkupError {
+    /// Byte offset in the markup where the error was found.
+    pub position: usize,
+    /// What went wrong.
+    pub kind: MarkupErrorKind,
+}
+
+/// The reason of a [`MarkupError`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarkupErrorKind {
+    /// The markup ended inside a tag or an entity.
+    UnexpectedEnd,
+    /// A tag could not be read.
+    InvalidTag,
+    /// The tag name is not supported.
+    UnknownTag(String),
+    /// A closing tag does not match the innermost open tag.
+    MismatchedClosingTag {
+        /// Name of the innermost open tag, if any.
+        expected: Option<String>,
+        /// Name of the closing tag.
+        found: String,
+    },
+    /// A tag is never closed. The position is the one of the opening tag.
+    UnclosedTag(String),
+    /// The tag does not take this attribute.
+    UnknownAttribute(String),
+    /// A required attribute is missing.
+    MissingAttribute(String),
+    /// The value of an attribute could not be used.
+    InvalidValue {
+        /// Name of the attribute.
+        attribute: String,
+        /// Value of the attribute.
+        value: String,
+    },
+    /// The entity (`&name;`) is not supported.
+    UnknownEntity(String),
+    /// No font matches the requested families. The position is the one of the
+    /// tag that set the families, or the start of the text for the defaults.
+    FontNotFound(String),
+}
+
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            MarkupErrorKind::UnexpectedEnd => write!(f, "unexpected end of markup"),
+            MarkupErrorKind::InvalidTag => write!(f, "invalid tag"),
+            MarkupErrorKind::UnknownTag(name) => write!(f, "unknown tag <{name}>"),
+            MarkupErrorKind::MismatchedClosingTag {
+                expected: Some(expected),
+                found,
+            } => write!(f, "expected </{expected}>, found </{found}>"),
+            MarkupErrorKind::MismatchedClosingTag {
+                expected: None,
+                found,
+            } => write!(f, "unexpected closing tag </{found}>"),
+            MarkupErrorKind::UnclosedTag(name) => write!(f, "unclosed tag <{name}>"),
+            MarkupErrorKind::UnknownAttribute(name) => write!(f, "unknown attribute `{name}`"),
+            MarkupErrorKind::MissingAttribute(name) => write!(f, "missing attribute `{name}`"),
+            MarkupErrorKind::InvalidValue { attribute, value } => {
+                write!(f, "invalid value `{value}` for attribute `{attribute}`")
+            }
+            MarkupErrorKind::UnknownEntity(name) => write!(f, "unknown entity &{name};"),
+            MarkupErrorKind::FontNotFound(family) => write!(f, "no font found for `{family}`"),
+        }?;
+        write!(f, " at byte {}", self.position)
+    }
+}
+
+impl std::error::Error for MarkupError {}
+
+impl<T: MarkupUserData> TextData<T> {
+    /// Parses markup into text runs.
+    ///
+    /// The markup is a small HTML-like language. Text is kept as is, including
+    /// whitespace and line breaks, and the following tags are supported:
+    ///
+    /// - `<b>`, `<i>`: bold and italic faces of the current families, simulated
+    ///   with [`SyntheticStyle`] when the family has no such face.
+    /// - `<u>`, `<s>`: underline and strikethrough.
+    /// - `<sup>`, `<sub>`: superscript and subscript baseline shifts.
+    /// - `<size value="20">`: font size in pixels.
+    /// - `<font family="Noto Serif, serif">`: comma separated list of families,
+    ///   tried in order. `serif`, `sans-serif`, `monospace`, `cursive` and
+    ///   `fantasy` are the generic families.
+    /// - `<color value="...">`, `<span name="value" ...>`: passed to
+    ///   [`MarkupUserData::apply_attribute`].
+    /// - `<br/>`: a line break.
+    ///
+    /// Attribute values are quoted with `"` or `'`. `&lt;`, `&gt;`, `&amp;`,
+    /// `&quot;`, `&apos;` and numeric character references (`&#931;`,
+    /// `&#x3A3;`) are decoded in text and attribute values.
+    ///
+    /// Fonts are resolved with [`FontStorage::query`], so they must be loaded
+    /// before parsing.
+    pub fn from_markup(
+        markup: &str,
+        defaults: &MarkupDefaults<T>,
+        font_storage: &mut FontStorage,
+    ) -> Result<Self, MarkupError> {
+        Self::from_markup_with(markup, defaults, &mut |query| {
+            let (font_id, _) = font_storage.query(query)?;
+            let synthetic_style = font_storage
+                .face(font_id)
+                .map(|face| SyntheticStyle::for_face(face, query.weight, query.style))
+                .unwrap_or_default();
+            Some((font_id, synthetic_style))
+        })
+    }
+
+    /// Parses markup like [`Self::from_markup`], resolving the fonts of the
+    /// runs with `resolve_font`.
+    fn from_markup_with(
+        markup: &str,
+        defaults: &MarkupDefaults<T>,
+        resolve_font: &mut ResolveFont<'_>,
+    ) -> Result<Self, MarkupError> {
+        Parser {
+            markup,
+            position: 0,
+            resolve_font,
+            stack: Vec::new(),
+            style: Style {
+                families: split_families(&defaults.family),
+                families_position: None,
+                font_size: defaults.font_size,
+                bold: false,
+                italic: false,
+                decoration: TextDecoration::default(),
+                baseline_shift: BaselineShift::Baseline,
+                user_data: defaults.user_data.clone(),
+            },
+            content: String::new(),
+            content_start: 0,
+            data: TextData::new(),
+        }
+        .parse()
+    }
+}
+
+/// Returns the font matching a query and the styles to simulate with it.
+type ResolveFont<'a> = dyn FnMut(&fontdb::Query<'_>) -> Option<(fontdb::ID, SyntheticStyle)> + 'a;
+
+#[derive(Clone)]
+struct Style<T> {
+    families: Vec<String>,
+    /// Position of the tag that set `families`.
+    families_position: Option<usize>,
+    font_size: f32,
+    bold: bool,
+    italic: bool,
+    decoration: TextDecoration,
+    baseline_shift: BaselineShift,
+    user_data: T,
+}
+
+struct Parser<'a, T: Clone> {
+    markup: &'a str,
+    position: usize,
+    resolve_font: &'a mut ResolveFont<'a>,
+    /// Open tags with their position and the style to restore when they close.
+    stack: Vec<(&'a str, usize, Style<T>)>,
+    style: Style<T>,
+    /// Text of the current style not yet appended to `data`.
+    content: String,
+    content_start: usize,
+    data: TextData<T>,
+}
+
+impl<'a, T: MarkupUserData> Parser<'a, T> {
+    fn parse(mut self) -> Result<TextData<T>, MarkupError> {
+        while let Some(ch) = self.rest().chars().next() {
+            match ch {
+                '<' => self.tag()?,
+                '&' => {
+                    let start = self.position;
+                    let ch = self.entity()?;
+                    self.push_char(ch, start);
+                }
+                _ => {
+                    self.push_char(ch, self.position);
+                    self.position += ch.len_utf8();
+                }
+            }
+        }
+
+        if let Some((name, position, _)) = self.stack.pop() {
+            return Err(MarkupError {
+                position,
+                kind: MarkupErrorKind::UnclosedTag(name.to_string()),
+            });
+        }
+        self.flush()?;
+        Ok(self.data)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.markup[self.position..]
+    }
+
+    fn error(&self, kind: MarkupErrorKind) -> MarkupError {
+        MarkupError {
+            position: self.position,
+            kind,
+        }
+    }
+
+    /// Adds a character found at `position` to the pending text.
+    fn push_char(&mut self, ch: char, position: usize) {
+        if self.content.is_empty() {
+            self.content_start = position;
+        }
+        self.content.push(ch);
+    }
+
+    /// Appends the pending text as a run of the current style.
+    fn flush(&mut self) -> Result<(), MarkupError> {
+        if self.content.is_empty() {
+            return Ok(());
+        }
+
+        let style = &self.style;
+        let families: Vec<fontdb::Family<'_>> = style
+            .families
+            .iter()
+            .map(|family| match family.to_ascii_lowercase().as_str() {
+                "serif" => fontdb::Family::Serif,
+                "sans-serif" => fontdb::Family::SansSerif,
+                "monospace" => fontdb::Family::Monospace,
+                "cursive" => fontdb::Family::Cursive,
+                "fantasy" => fontdb::Family::Fantasy,
+                _ => fontdb::Family::Name(family),
+            })
+            .collect();
+        let query = fontdb::Query {
+            families: &families,
+            weight: if style.bold {
+                fontdb::Weight::BOLD
+            } else {
+                fontdb::Weight::NORMAL
+            },
+            style: if style.italic {
+                fontdb::Style::Italic
+            } else {
+                fontdb::Style::Normal
+            },
+            ..Default::default()
+        };
+        let Some((font_id, synthetic_style)) = (self.resolve_font)(&query) else {
+            return Err(MarkupError {
+                position: style.families_position.unwrap_or(self.content_start),
+                kind: MarkupErrorKind::FontNotFound(style.families.join(", ")),
+            });
+        };
+
+        self.data.append(
+            TextElement::new(
+                font_id,
+                style.font_size,
+                std::mem::take(&mut self.content),
+                style.user_data.clone(),
+            )
+            .with_baseline_shift(style.baseline_shift)
+            .with_decoration(style.decoration)
+            .with_synthetic_style(synthetic_style),
+        );
+        Ok(())
+    }
+
+    /// Reads `&name;` and returns the character it stands for.
+    fn entity(&mut self) -> Result<char, MarkupError> {
+        let rest = self.rest();
+        let Some(end) = rest.find(';') else {
+            return Err(self.error(MarkupErrorKind::UnexpectedEnd));
+        };
+        let name = &rest[1..end];
+        let ch = match name {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => name
+                .strip_prefix("#x")
+                .or_else(|| name.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| name.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        let Some(ch) = ch else {
+            return Err(self.error(MarkupErrorKind::UnknownEntity(name.to_string())));
+        };
+        self.position += end + 1;
+        Ok(ch)
+    }
+
+    /// Reads an opening, closing or self-closing tag and updates the style.
+    fn tag(&mut self) -> Result<(), MarkupError> {
+        let start = self.position;
+        self.position += 1;
+
+        let closing = self.rest().starts_with('/');
+        if closing {
+            self.position += 1;
+        }
+        let name = self.name();
+        if name.is_empty() {
+            return Err(self.error(MarkupErrorKind::InvalidTag));
+        }
+
+        if closing {
+            self.skip_whitespace();
+            self.expect('>')?;
+            return match self.stack.last() {
+                Some((open, _, _)) if *open == name => {
+                    self.flush()?;
+                    if let Some((_, _, style)) = self.stack.pop() {
+                        self.style = style;
+                    }
+                    Ok(())
+                }
+                open => Err(MarkupError {
+                    position: start,
+                    kind: MarkupErrorKind::MismatchedClosingTag {
+                        expected: open.map(|(open, _, _)| open.to_string()),
+                        found: name.to_string(),
+                    },
+                }),
+            };
+        }
+
+        let mut attributes = Vec::new();
+        let self_closing = loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.position += 2;
+                break true;
+            }
+            if self.rest().starts_with('>') {
+                self.position += 1;
+                break false;
+            }
+            if self.rest().is_empty() {
+                return Err(self.error(MarkupErrorKind::UnexpectedEnd));
+            }
+            attributes.push(self.attribute()?);
+        };
+
+        let mut style = self.style.clone();
+        let mut required = None;
+        match name {
+            "b" => style.bold = true,
+            "i" => style.italic = true,
+            "u" => style.decoration.underline = true,
+            "s" => style.decoration.strikethrough = true,
+            "sup" => style.baseline_shift = BaselineShift::Superscript,
+            "sub" => style.baseline_shift = BaselineShift::Subscript,
+            "br" | "span" => {}
+            "size" | "color" => required = Some("value"),
+            "font" => required = Some("family"),
+            _ => {
+                return Err(MarkupError {
+                    position: start,
+                    kind: MarkupErrorKind::UnknownTag(name.to_string()),
+                });
+            }
+        }
+
+        if let Some(required) = required
+            && !attributes
+                .iter()
+                .any(|(attribute, _, _)| *attribute == required)
+        {
+            return Err(MarkupError {
+                position: start,
+                kind: MarkupErrorKind::MissingAttribute(required.to_string()),
+            });
+        }
+
+        for (attribute, value, position) in attributes {
+            let invalid = || MarkupError {
+                position,
+                kind: MarkupErrorKind::InvalidValue {
+                    attribute: attribute.to_string(),
+                    value: value.clone(),
+                },
+            };
+            match (name, attribute) {
+                ("size", "value") => {
+                    style.font_size = value
+                        .trim()
+                        .parse()
+                        .ok()
+                        .filter(|size: &f32| size.is_finite() && *size > 0.0)
+                        .ok_or_else(invalid)?;
+                }
+                ("font", "family") => {
+                    style.families = split_families(&value);
+                    style.families_position = Some(start);
+                }
+                ("color", "value") | ("span", _) => {
+                    let attribute = if name == "color" { "color" } else { attribute };
+                    style.user_data = style
+                        .user_data
+                        .apply_attribute(attribute, &value)
+                        .ok_or_else(invalid)?;
+                }
+                _ => {
+                    return Err(MarkupError {
+                        position,
+                        kind: MarkupErrorKind::UnknownAttribute(attribute.to_string()),
+                    });
+                }
+            }
+        }
+
+        if name == "br" {
+            // `<br>` has no content, so it never needs to be closed.
+            self.push_char('\n', start);
+        } else if !self_closing {
+            self.flush()?;
+            let previous = std::mem::replace(&mut self.style, style);
+            self.stack.push((name, start, previous));
+        }
+        Ok(())
+    }
+
+    /// Reads `name="value"` and returns the name, the decoded value and the
+    /// position of the attribute.
+    fn attribute(&mut self) -> Result<(&'a str, String, usize), MarkupError> {
+        let start = self.position;
+        let name = self.name();
+        if name.is_empty() {
+            return Err(self.error(MarkupErrorKind::InvalidTag));
+        }
+        self.skip_whitespace();
+        self.expect('=')?;
+        self.skip_whitespace();
+
+        let Some(quote) = self
+            .rest()
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '"' | '\''))
+        else {
+            return Err(if self.rest().is_empty() {
+                self.error(MarkupErrorKind::UnexpectedEnd)
+            } else {
+                self.error(MarkupErrorKind::InvalidTag)
+            });
+        };
+        self.position += 1;
+
+        let mut value = String::new();
+        loop {
+            match self.rest().chars().next() {
+                None => return Err(self.error(MarkupErrorKind::UnexpectedEnd)),
+                Some(ch) if ch == quote => {
+                    self.position += 1;
+                    break;
+                }
+                Some('&') => value.push(self.entity()?),
+                Some(ch) => {
+                    value.push(ch);
+                    self.position += ch.len_utf8();
+                }
+            }
+        }
+
+        Ok((name, value, start))
+    }
+
+    fn name(&mut self) -> &'a str {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(rest.len());
+        self.position += len;
+        &rest[..len]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn expect(&mut self, ch: char) -> Result<(), MarkupError> {
+        match self.rest().chars().next() {
+            Some(c) if c == ch => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(_) => Err(self.error(MarkupErrorKind::InvalidTag)),
+            None => Err(self.error(MarkupErrorKind::UnexpectedEnd)),
+        }
+    }
+}
+
+fn split_families(families: &str) -> Vec<String> {
+    families
+        .split(',')
+        .map(|family| family.trim().trim_matches(|c| c == '"' || c == '\''))
+        .filter(|family| !family.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Paint {
+        color: u32,
+        alpha: u8,
+    }
+
+    impl MarkupUserData for Paint {
+        fn apply_attribute(&self, name: &str, value: &str) -> Option<Self> {
+            match name {
+                "color" => Some(Paint {
+                    color: u32::from_str_radix(value.strip_prefix('#')?, 16).ok()?,
+                    ..*self
+                }),
+                "alpha" => Some(Paint {
+                    alpha: value.parse().ok()?,
+                    ..*self
+                }),
+                _ => None,
+            }
+        }
+    }
+
+    fn defaults() -> MarkupDefaults<Paint> {
+        MarkupDefaults {
+            family: "sans-serif".to_string(),
+            font_size: 16.0,
+            user_data: Paint {
+                color: 0,
+                alpha: 255,
+            },
+        }
+    }
+
+    fn parse(markup: &str) -> Result<TextData<Paint>, MarkupError> {
+        TextData::from_markup(markup, &defaults(), &mut FontStorage::new())
+    }
+
+    fn font_id(id: u64) -> fontdb::ID {
+        unsafe { std::mem::transmute(id) }
+    }
+
+    /// Parses with the families "Serif A" (1), sans-serif (2) and "A & B" (3),
+    /// simulating their bold and italic faces.
+    fn parse_with_fonts(markup: &str) -> Result<TextData<Paint>, MarkupError> {
+        TextData::from_markup_with(markup, &defaults(), &mut |query| {
+            let font_id = query.families.iter().find_map(|family| match family {
+                fontdb::Family::Name("Serif A") => Some(font_id(1)),
+                fontdb::Family::Name("A & B") => Some(font_id(3)),
+                fontdb::Family::SansSerif => Some(font_id(2)),
+                _ => None,
+            })?;
+            let synthetic_style = SyntheticStyle {
+                bold: query.weight == fontdb::Weight::BOLD,
+                oblique: query.style == fontdb::Style::Italic,
+            };
+            Some((font_id, synthetic_style))
+        })
+    }
+
+    #[test]
+    fn test_markup_error_positions() {
+        let error = parse("<b><i></b></i>").unwrap_err();
+        assert_eq!(error.position, 6);
+        assert_eq!(
+            error.kind,
+            MarkupErrorKind::MismatchedClosingTag {
+                expected: Some("i".to_string()),
+                found: "b".to_string(),
+            }
+        );
+
+        let error = parse("<b><size value='12'>").unwrap_err();
+        assert_eq!(error.position, 3);
+        assert_eq!(error.kind, MarkupErrorKind::UnclosedTag("size".to_string()));
+
+        let error = parse("<color value=\"red\"></color>").unwrap_err();
+        assert_eq!(error.position, 7);
+
+        let error = parse("<b>&nbsp;</b>").unwrap_err();
+        assert_eq!(error.position, 3);
+        assert_eq!(
+            error.kind,
+            MarkupErrorKind::UnknownEntity("nbsp".to_string())
+        );
+
+        // No fonts are loaded, so every run fails to resolve its family.
+        let error = parse("<size value='20'></size><font family=\"Serif\">a</font>").unwrap_err();
+        assert_eq!(error.position, 24);
+        assert_eq!(
+            error.kind,
+            MarkupErrorKind::FontNotFound("Serif".to_string())
+        );
+
+        let error = parse("<b></b>a &amp; b").unwrap_err();
+        assert_eq!(error.position, 7);
+        assert_eq!(
+            error.kind,
+            MarkupErrorKind::FontNotFound("sans-serif".to_string())
+        );
+    }
+
+    #[test]
+    fn test_markup_without_text() {
+        let data = parse("<b><color value='#ff0000'><u/></color></b>").unwrap();
+        assert!(data.texts.is_empty());
+    }
+
+    #[test]
+    fn test_markup_styles() {
+        let data = parse_with_fonts(
+            "a<b>b<i><size value='20'>c</size></i></b><u><s><sup>d</sup></s></u>e",
+        )
+        .unwrap();
+        let runs: Vec<_> = data
+            .texts
+            .iter()
+            .map(|text| (text.content.as_str(), text.font_size, text.synthetic_style))
+            .collect();
+        let bold = SyntheticStyle {
+            bold: true,
+            oblique: false,
+        };
+        assert_eq!(
+            runs,
+            vec![
+                ("a", 16.0, SyntheticStyle::default()),
+                ("b", 16.0, bold),
+                (
+                    "c",
+                    20.0,
+                    SyntheticStyle {
+                        oblique: true,
+                        ..bold
+                    }
+                ),
+                ("d", 16.0, SyntheticStyle::default()),
+                ("e", 16.0, SyntheticStyle::default()),
+            ]
+        );
+
+        // Decorations and shifts nest and are restored by the closing tags.
+        let d = &data.texts[3];
+        assert!(d.decoration.underline && d.decoration.strikethrough);
+        assert_eq!(d.baseline_shift, BaselineShift::Superscript);
+        let e = &data.texts[4];
+        assert_eq!(e.decoration, TextDecoration::default());
+        assert_eq!(e.baseline_shift, BaselineShift::Baseline);
+    }
+
+    #[test]
+    fn test_markup_fonts_and_user_data() {
+        let data = parse_with_fonts(
+            "<font family='Missing, \"Serif A\"'>a<br>b<br/></font>\
+             <font family=\"A &amp; B\">&lt;c&#x3e;</font>\
+             <color value='#ff0000'><span alpha='128'>d</span>e</color>",
+        )
+        .unwrap();
+        let runs: Vec<_> = data
+            .texts
+            .iter()
+            .map(|text| (text.content.as_str(), text.font_id, text.user_data.clone()))
+            .collect();
+        let paint = |color, alpha| Paint { color, alpha };
+        assert_eq!(
+            runs,
+            vec![
+                ("a\nb\n", font_id(1), paint(0, 255)),
+                ("<c>", font_id(3), paint(0, 255)),
+                ("d", font_id(2), paint(0xff0000, 128)),
+                ("e", font_id(2), paint(0xff0000, 255)),
+            ]
+        );
+    }
+}